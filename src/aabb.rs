@@ -0,0 +1,96 @@
+use crate::ray::*;
+use crate::vec3::*;
+
+/// axis-aligned bounding box given by its two extreme corners
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    min: Point3,
+    max: Point3,
+}
+
+impl Aabb {
+    /// corners don't have to be ordered, box is built around both of them
+    pub fn new(a: Point3, b: Point3) -> Self {
+        Self {
+            min: min_components(a, b),
+            max: max_components(a, b),
+        }
+    }
+
+    pub fn min(&self) -> Point3 {
+        self.min
+    }
+
+    pub fn max(&self) -> Point3 {
+        self.max
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    /// index of the axis along which the box is the widest
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x() > d.y() && d.x() > d.z() {
+            0
+        } else if d.y() > d.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// the eight corners, bit i of the index picks max along axis i
+    pub fn corners(&self) -> [Point3; 8] {
+        std::array::from_fn(|i| {
+            let pick = |axis: usize| match i >> axis & 1 {
+                0 => self.min.component(axis),
+                _ => self.max.component(axis),
+            };
+            Point3::new(pick(0), pick(1), pick(2))
+        })
+    }
+
+    /// smallest box containing both boxes
+    pub fn surrounding_box(a: &Aabb, b: &Aabb) -> Aabb {
+        Aabb {
+            min: min_components(a.min, b.min),
+            max: max_components(a.max, b.max),
+        }
+    }
+
+    /// smallest box containing the box and the point
+    pub fn expanded_to(&self, point: Point3) -> Aabb {
+        Aabb {
+            min: min_components(self.min, point),
+            max: max_components(self.max, point),
+        }
+    }
+
+    /// slab test: true if the ray enters the box somewhere in (t_min, t_max)
+    pub fn hit(&self, r: &Ray, mut t_min: f64, mut t_max: f64) -> bool {
+        let origin = r.origin();
+        let direction = r.direction();
+        for axis in 0..3 {
+            let inv_d = 1.0 / direction.component(axis);
+            let mut t0 = (self.min.component(axis) - origin.component(axis)) * inv_d;
+            let mut t1 = (self.max.component(axis) - origin.component(axis)) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // written so that NaN (ray parallel to a slab and lying on its border) keeps the old value
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,134 @@
+//! Adaptive sampling: pixels stop getting samples once their estimate is good enough,
+//! so flat sky pixels don't take as long as noisy caustics.
+
+use crate::vec3::*;
+
+/// luminance below which pixels count as black for their relative error
+const DARK_LUMINANCE: f64 = 0.01;
+
+/// Running mean of the samples of a pixel and the variance of their luminance,
+/// updated one sample at a time with Welford's algorithm.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PixelEstimate {
+    count: u32,
+    mean: Color,
+    luminance_mean: f64,
+    /// sum of squared differences from the luminance mean
+    luminance_m2: f64,
+}
+
+impl PixelEstimate {
+    pub fn add(&mut self, sample: Color) {
+        self.count += 1;
+        let n = f64::from(self.count);
+        self.mean += (sample - self.mean) / n;
+        let y = luminance(sample);
+        let delta = y - self.luminance_mean;
+        self.luminance_mean += delta / n;
+        self.luminance_m2 += delta * (y - self.luminance_mean);
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// black before the first sample
+    pub fn mean(&self) -> Color {
+        self.mean
+    }
+
+    /// sample variance of the luminance, 0 with less than two samples
+    pub fn luminance_variance(&self) -> f64 {
+        match self.count {
+            0 | 1 => 0.0,
+            n => self.luminance_m2 / f64::from(n - 1),
+        }
+    }
+
+    /// Standard error of the mean luminance relative to the luminance. Pixels darker than
+    /// `DARK_LUMINANCE` are compared to it instead, or they would never be good enough.
+    pub fn relative_error(&self) -> f64 {
+        if self.count == 0 {
+            return f64::INFINITY;
+        }
+        let standard_error = (self.luminance_variance() / f64::from(self.count)).sqrt();
+        standard_error / self.luminance_mean.max(DARK_LUMINANCE)
+    }
+}
+
+/// how many samples a pixel gets
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveSampling {
+    min_samples: u32,
+    max_samples: u32,
+    /// relative error at which a pixel stops before `max_samples`
+    threshold: f64,
+}
+
+impl AdaptiveSampling {
+    /// min_samples is lowered to max_samples if it's above it, both should be at least 1
+    pub fn new(min_samples: u32, max_samples: u32, threshold: f64) -> Self {
+        Self {
+            min_samples: min_samples.min(max_samples),
+            max_samples,
+            threshold,
+        }
+    }
+
+    /// every pixel gets the same number of samples
+    pub fn fixed(samples: u32) -> Self {
+        Self::new(samples, samples, 0.0)
+    }
+
+    pub fn max_samples(&self) -> u32 {
+        self.max_samples
+    }
+
+    pub fn is_done(&self, estimate: &PixelEstimate) -> bool {
+        estimate.count() >= self.max_samples
+            || (estimate.count() >= self.min_samples && estimate.relative_error() <= self.threshold)
+    }
+}
+
+/// Color of a pixel of the heatmap of samples taken, from black for none through red
+/// and yellow to white for max_samples.
+pub fn heatmap_color(samples: u32, max_samples: u32) -> Color {
+    let t = f64::from(samples) / f64::from(max_samples.max(1));
+    let ramp = |start: f64| (3.0 * t - start).clamp(0.0, 1.0);
+    Color::new(ramp(0.0), ramp(1.0), ramp(2.0))
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_stop_when_good_enough() {
+        let samples = [0.2, 0.9, 0.4, 0.4, 0.6, 0.1, 0.8, 0.3];
+        let mut estimate = PixelEstimate::default();
+        for &y in &samples {
+            estimate.add(Color::new(y, y, y));
+        }
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|y| (y - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        assert!((luminance(estimate.mean()) - mean).abs() < 1e-12);
+        assert!((estimate.luminance_variance() - variance).abs() < 1e-12);
+        assert!((estimate.relative_error() - (variance / n).sqrt() / mean).abs() < 1e-12);
+
+        let adaptive = AdaptiveSampling::new(4, 16, 0.1);
+        let mut flat = PixelEstimate::default();
+        for _ in 0..3 {
+            flat.add(Color::new(0.5, 0.5, 0.5));
+        }
+        assert!(!adaptive.is_done(&flat));
+        flat.add(Color::new(0.5, 0.5, 0.5));
+        assert!(adaptive.is_done(&flat));
+        // the samples above are off by about 20%
+        assert!(!adaptive.is_done(&estimate));
+        assert!(AdaptiveSampling::new(4, 8, 0.1).is_done(&estimate));
+        assert!(!AdaptiveSampling::fixed(16).is_done(&flat));
+    }
+}
@@ -0,0 +1,187 @@
+//! Arbitrary output variables: what the camera rays hit first, written next to the
+//! image as separate files so compositing can pick objects, relight or add fog.
+
+use crate::material::Material;
+use crate::vec3::*;
+use crate::{HitRecord, Ray};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// the outputs that can be picked by name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov {
+    /// distance from the camera
+    Depth,
+    /// world space normal pointing out of the object
+    Normal,
+    /// color of the surface without lighting, see `Material::albedo`
+    Albedo,
+    ObjectId,
+    MaterialId,
+    /// world space point
+    Position,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 6] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::ObjectId,
+        Aov::MaterialId,
+        Aov::Position,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::ObjectId => "object-id",
+            Aov::MaterialId => "material-id",
+            Aov::Position => "position",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+
+    /// PFM file next to the image with the name of the output added, so image.png gets
+    /// image.depth.pfm. Floats keep depths, positions and IDs exact.
+    pub fn path_next_to(&self, image: &Path) -> PathBuf {
+        let stem = image.file_stem().unwrap_or_default().to_string_lossy();
+        image.with_file_name(format!("{}.{}.pfm", stem, self.name()))
+    }
+}
+
+/// Finds the IDs of materials, which count from 1 in the order of the scene description.
+/// Materials that aren't in it, like the ones of MTL files, get 0.
+pub struct MaterialIds {
+    /// address of every material to its ID
+    ids: HashMap<usize, u32>,
+}
+
+impl MaterialIds {
+    pub fn new(materials: &[Arc<dyn Material>]) -> Self {
+        let mut ids = HashMap::new();
+        for (index, material) in materials.iter().enumerate() {
+            let address = Arc::as_ptr(material) as *const () as usize;
+            ids.entry(address).or_insert(index as u32 + 1);
+        }
+        Self { ids }
+    }
+
+    pub fn id(&self, material: &dyn Material) -> u32 {
+        let address = material as *const dyn Material as *const () as usize;
+        self.ids.get(&address).copied().unwrap_or(0)
+    }
+}
+
+/// What the first hit of one camera ray tells, everything is 0 for rays that hit nothing
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AovSample {
+    pub depth: f64,
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub position: Point3,
+    pub object_id: u32,
+    pub material_id: u32,
+}
+
+impl AovSample {
+    pub fn from_hit(ray: &Ray, record: &HitRecord<'_>, material_ids: &MaterialIds) -> Self {
+        let material = record.material_reference;
+        Self {
+            depth: record.t * ray.direction().length(),
+            normal: match record.outside_ray {
+                true => record.normal,
+                false => -record.normal,
+            },
+            albedo: material.map_or(Color::default(), |material| material.albedo(record)),
+            position: record.point,
+            object_id: record.object_id,
+            material_id: material.map_or(0, |material| material_ids.id(material)),
+        }
+    }
+}
+
+/// AOVs of a pixel, averaged over its samples like the image. IDs can't be averaged, they
+/// are the ones of the first sample.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AovPixel {
+    count: u32,
+    mean: AovSample,
+}
+
+impl AovPixel {
+    pub fn add(&mut self, sample: &AovSample) {
+        self.count += 1;
+        let n = f64::from(self.count);
+        let mean = &mut self.mean;
+        mean.depth += (sample.depth - mean.depth) / n;
+        mean.normal += (sample.normal - mean.normal) / n;
+        mean.albedo += (sample.albedo - mean.albedo) / n;
+        mean.position += (sample.position - mean.position) / n;
+        if self.count == 1 {
+            mean.object_id = sample.object_id;
+            mean.material_id = sample.material_id;
+        }
+    }
+
+    /// the output as a color, single numbers are in every channel
+    pub fn value(&self, aov: Aov) -> Color {
+        let gray = |value: f64| Color::new(value, value, value);
+        match aov {
+            Aov::Depth => gray(self.mean.depth),
+            Aov::Normal => self.mean.normal,
+            Aov::Albedo => self.mean.albedo,
+            Aov::ObjectId => gray(f64::from(self.mean.object_id)),
+            Aov::MaterialId => gray(f64::from(self.mean.material_id)),
+            Aov::Position => self.mean.position,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{Lambertian, Metal};
+    use crate::ray_trace::{Hittable, Identified};
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn first_hits_fill_the_outputs() {
+        let red: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.1, 0.1)));
+        let steel: Arc<dyn Material> = Arc::new(Metal::new(Color::new(0.6, 0.6, 0.6), 0.0));
+        let stray: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let ids = MaterialIds::new(&[red.clone(), steel.clone()]);
+        assert_eq!(ids.id(steel.as_ref()), 2);
+        assert_eq!(ids.id(stray.as_ref()), 0);
+
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, red);
+        let sphere = Identified::new(7, Arc::new(sphere));
+        let ray = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -2.0));
+        let record = sphere.hit(&ray, 0.001, f64::MAX).unwrap();
+        let sample = AovSample::from_hit(&ray, &record, &ids);
+        assert!((sample.depth - 2.0).abs() < 1e-12);
+        assert_eq!(sample.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(sample.position, Point3::new(0.0, 0.0, -2.0));
+        assert_eq!(sample.albedo, Color::new(0.8, 0.1, 0.1));
+        assert_eq!((sample.object_id, sample.material_id), (7, 1));
+
+        // half of the pixel is background
+        let mut pixel = AovPixel::default();
+        pixel.add(&sample);
+        pixel.add(&AovSample::default());
+        assert_eq!(pixel.value(Aov::Depth), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(pixel.value(Aov::ObjectId), Color::new(7.0, 7.0, 7.0));
+
+        let path = Aov::MaterialId.path_next_to(Path::new("renders/image.png"));
+        assert_eq!(path, PathBuf::from("renders/image.material-id.pfm"));
+        assert_eq!(Aov::from_name("object-id"), Some(Aov::ObjectId));
+    }
+}
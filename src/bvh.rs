@@ -0,0 +1,247 @@
+use crate::aabb::Aabb;
+use crate::ray::*;
+use crate::ray_trace::*;
+use std::sync::Arc;
+
+/// how a set of objects gets split into two children while building the tree
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SplitHeuristic {
+    /// split the widest axis of the centroids in half
+    Midpoint,
+    /// binned surface area heuristic, slower to build but faster to trace
+    #[default]
+    Sah,
+}
+
+/// number of buckets the surface area heuristic sorts centroids into
+const SAH_BUCKETS: usize = 12;
+
+/// Bounding volume hierarchy. Can be used instead of a `HittableList` as the world,
+/// a ray only visits the objects whose boxes it crosses.
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    // None when the node wraps a single object
+    right: Option<Arc<dyn Hittable>>,
+    /// None for the tree of an empty list
+    bbox: Option<Aabb>,
+}
+
+impl BvhNode {
+    /// Builds the tree from every object of the list, nothing hits the tree of an empty
+    /// list. Panics if the list has an unbounded object.
+    pub fn new(list: &HittableList, heuristic: SplitHeuristic) -> Self {
+        if list.is_empty() {
+            return Self {
+                left: Arc::new(HittableList::default()),
+                right: None,
+                bbox: None,
+            };
+        }
+        let mut entries: Vec<(Arc<dyn Hittable>, Aabb)> = list
+            .objects()
+            .iter()
+            .map(|obj| {
+                let bbox = obj
+                    .bounding_box()
+                    .expect("every object in a BVH should have a bounding box");
+                (obj.clone(), bbox)
+            })
+            .collect();
+        Self::build(&mut entries, heuristic)
+    }
+
+    fn build(entries: &mut [(Arc<dyn Hittable>, Aabb)], heuristic: SplitHeuristic) -> Self {
+        let bbox = entries
+            .iter()
+            .skip(1)
+            .fold(entries[0].1, |acc, (_, b)| Aabb::surrounding_box(&acc, b));
+        match entries.len() {
+            1 => {
+                return Self {
+                    left: entries[0].0.clone(),
+                    right: None,
+                    bbox: Some(bbox),
+                }
+            }
+            2 => {
+                return Self {
+                    left: entries[0].0.clone(),
+                    right: Some(entries[1].0.clone()),
+                    bbox: Some(bbox),
+                }
+            }
+            _ => {}
+        }
+
+        let centroid_bounds = entries.iter().skip(1).fold(
+            Aabb::new(entries[0].1.centroid(), entries[0].1.centroid()),
+            |acc, (_, b)| acc.expanded_to(b.centroid()),
+        );
+        let axis = centroid_bounds.longest_axis();
+        let lo = centroid_bounds.min().component(axis);
+        let hi = centroid_bounds.max().component(axis);
+
+        let mid = match heuristic {
+            SplitHeuristic::Midpoint => {
+                partition(entries, |b| b.centroid().component(axis) < 0.5 * (lo + hi))
+            }
+            SplitHeuristic::Sah => match sah_split(entries, axis, lo, hi) {
+                Some(split) => partition(entries, |b| split.contains(b, axis, lo, hi)),
+                None => 0,
+            },
+        };
+        // every centroid ended up on one side, fall back to splitting by count
+        let mid = if mid == 0 || mid == entries.len() {
+            entries.sort_by(|a, b| {
+                a.1.centroid()
+                    .component(axis)
+                    .total_cmp(&b.1.centroid().component(axis))
+            });
+            entries.len() / 2
+        } else {
+            mid
+        };
+
+        let (left, right) = entries.split_at_mut(mid);
+        Self {
+            left: Arc::new(Self::build(left, heuristic)),
+            right: Some(Arc::new(Self::build(right, heuristic))),
+            bbox: Some(bbox),
+        }
+    }
+}
+
+/// bucket boundary chosen by the surface area heuristic
+struct SahSplit {
+    bucket: usize,
+}
+
+impl SahSplit {
+    fn bucket_of(bbox: &Aabb, axis: usize, lo: f64, hi: f64) -> usize {
+        let offset = (bbox.centroid().component(axis) - lo) / (hi - lo);
+        ((offset * SAH_BUCKETS as f64) as usize).min(SAH_BUCKETS - 1)
+    }
+
+    fn contains(&self, bbox: &Aabb, axis: usize, lo: f64, hi: f64) -> bool {
+        Self::bucket_of(bbox, axis, lo, hi) <= self.bucket
+    }
+}
+
+fn sah_split(
+    entries: &[(Arc<dyn Hittable>, Aabb)],
+    axis: usize,
+    lo: f64,
+    hi: f64,
+) -> Option<SahSplit> {
+    if hi <= lo {
+        return None;
+    }
+    let mut buckets: [Option<(usize, Aabb)>; SAH_BUCKETS] = [None; SAH_BUCKETS];
+    for (_, bbox) in entries {
+        let bucket = &mut buckets[SahSplit::bucket_of(bbox, axis, lo, hi)];
+        *bucket = match bucket {
+            Some((count, b)) => Some((*count + 1, Aabb::surrounding_box(b, bbox))),
+            None => Some((1, *bbox)),
+        };
+    }
+
+    // cost of splitting after bucket i is count(left) * area(left) + count(right) * area(right)
+    let grow = |acc: Option<(usize, Aabb)>, bucket: &Option<(usize, Aabb)>| match (acc, bucket) {
+        (Some((c1, b1)), Some((c2, b2))) => Some((c1 + c2, Aabb::surrounding_box(&b1, b2))),
+        (acc, None) => acc,
+        (None, bucket) => *bucket,
+    };
+    let cost = |side: Option<(usize, Aabb)>| side.map_or(0.0, |(c, b)| c as f64 * b.surface_area());
+    let mut left_sides = [None; SAH_BUCKETS];
+    let mut acc = None;
+    for (i, bucket) in buckets.iter().enumerate() {
+        acc = grow(acc, bucket);
+        left_sides[i] = acc;
+    }
+    let mut best: Option<(usize, f64)> = None;
+    let mut right_side = None;
+    for i in (0..SAH_BUCKETS - 1).rev() {
+        right_side = grow(right_side, &buckets[i + 1]);
+        let split_cost = cost(left_sides[i]) + cost(right_side);
+        if best.is_none_or(|(_, c)| split_cost < c) {
+            best = Some((i, split_cost));
+        }
+    }
+    best.map(|(bucket, _)| SahSplit { bucket })
+}
+
+/// moves entries whose box satisfies the predicate to the front, returns how many did
+fn partition(entries: &mut [(Arc<dyn Hittable>, Aabb)], pred: impl Fn(&Aabb) -> bool) -> usize {
+    let mut mid = 0;
+    for i in 0..entries.len() {
+        if pred(&entries[i].1) {
+            entries.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        if !self.bbox?.hit(r, t_min, t_max) {
+            return None;
+        }
+        let left_record = self.left.hit(r, t_min, t_max);
+        let closest = left_record.as_ref().map_or(t_max, |record| record.t);
+        let right_record = self
+            .right
+            .as_ref()
+            .and_then(|right| right.hit(r, t_min, closest));
+        right_record.or(left_record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate_scene::generate_scene;
+    use crate::number_stuff::Rng;
+    use crate::vec3::*;
+
+    #[test]
+    fn slab_test() {
+        let bbox = Aabb::new(Point3::new(1.0, 1.0, 1.0), Point3::new(-1.0, -1.0, -1.0));
+        let towards = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let away = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, -1.0));
+        let beside = Ray::new(Point3::new(2.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(bbox.hit(&towards, 0.0, f64::MAX));
+        assert!(!bbox.hit(&towards, 0.0, 3.0));
+        assert!(!bbox.hit(&away, 0.0, f64::MAX));
+        assert!(!bbox.hit(&beside, 0.0, f64::MAX));
+    }
+
+    #[test]
+    fn same_hits_as_list() {
+        let rng = &mut Rng::new(3);
+        let world = generate_scene(rng);
+        for heuristic in [SplitHeuristic::Midpoint, SplitHeuristic::Sah] {
+            let bvh = BvhNode::new(&world, heuristic);
+            for _ in 0..1000 {
+                let r = Ray::new(
+                    rng.random_vec_in_range(-10.0, 10.0) + Vec3::new(0.0, 11.0, 0.0),
+                    rng.random_vec_in_range(-1.0, 1.0),
+                );
+                let expected = world.hit(&r, 0.00001, f64::MAX).map(|record| record.t);
+                let got = bvh.hit(&r, 0.00001, f64::MAX).map(|record| record.t);
+                assert_eq!(expected, got);
+            }
+        }
+        // scenes with nothing but a background are fine
+        let empty = BvhNode::new(&HittableList::default(), SplitHeuristic::Sah);
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, 1.0));
+        assert!(empty.hit(&r, 0.00001, f64::MAX).is_none());
+        assert!(empty.bounding_box().is_none());
+    }
+}
@@ -1,30 +1,75 @@
 use crate::number_stuff::degrees_to_radians;
-use crate::number_stuff::random_in_unit_disk;
 use crate::ray::*;
+use crate::sampler::{sample_disk, Sampler};
 use crate::vec3::*;
 
+/// everything needed to place a camera, without the derived viewport vectors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraSettings {
+    pub vertical_fov_degrees: f64,
+    pub aspect_ratio: f64,
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub vec_up: Vec3,
+    pub aperture: f64,
+    pub focus_dist: f64,
+    /// rays get times between these two, equal times mean no motion blur
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+}
+
+impl Default for CameraSettings {
+    /// the camera of the book's final scene
+    fn default() -> Self {
+        Self {
+            vertical_fov_degrees: 30.0,
+            aspect_ratio: 3.0 / 2.0,
+            look_from: Point3::new(13.0, 2.0, 3.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            vec_up: Vec3::new(0.0, 1.0, 0.0),
+            aperture: 0.1,
+            focus_dist: 10.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        }
+    }
+}
+
+impl CameraSettings {
+    pub fn build(&self, samples_per_pixel: u32) -> Camera {
+        Camera::new(
+            self.vertical_fov_degrees,
+            self.aspect_ratio,
+            self.look_from,
+            samples_per_pixel,
+            self.look_at,
+            self.vec_up,
+            self.aperture,
+            self.focus_dist,
+        )
+        .with_shutter(self.shutter_open, self.shutter_close)
+    }
+}
+
 pub struct Camera {
-    vertical_fov_degrees: f64,
     aspect_ratio: f64,
-    viewpoint_height: f64,
-    viewpoint_width: f64,
-    focal_length: f64,
     look_from: Point3,
     horizontal: Vec3,
     vertical: Vec3,
     lower_left_corner: Point3,
     samples_per_pixel: u32,
-    look_at: Point3,
     lens_radius: f64,
     u: Vec3,
     v: Vec3,
+    shutter_open: f64,
+    shutter_close: f64,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         vertical_fov_degrees: f64,
         aspect_ratio: f64,
-        focal_length: f64,
         look_from: Point3,
         samples_per_pixel: u32,
         look_at: Point3,
@@ -43,34 +88,47 @@ impl Camera {
         let vertical = viewpoint_height * v * focus_dist;
 
         Self {
-            vertical_fov_degrees,
             aspect_ratio,
-            viewpoint_height,
-            viewpoint_width,
-            focal_length,
             look_from,
             samples_per_pixel,
             horizontal,
             vertical,
             lower_left_corner: look_from - horizontal / 2.0 - vertical / 2.0 - w * focus_dist,
-            look_at,
             lens_radius: aperture / 2.0,
             u,
             v,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
         }
     }
 
+    /// The shutter stays open from `open` to `close`, rays get a random time in between.
+    /// A shutter that closes before it opens takes pictures at `open`, like a still one.
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Self {
+        self.shutter_open = open;
+        self.shutter_close = close;
+        self
+    }
+
     /// returns a ray that goes from camera origin to (u , v) point. When (u, v) = (0.0, 0.0) end of the ray
     /// will be lower_left_corner. When (u, v) = (1.0, 1.0), end of the ray will be higher  up corner.
-    pub fn get_ray(&self, s: f64, x: f64) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
+    /// The lens and the shutter time take the next two and one dimensions of the sampler.
+    pub fn get_ray(&self, s: f64, x: f64, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * sample_disk(sampler.get_2d());
         let offset = self.u * rd.x() + self.v * rd.y();
+        // the shutter takes its dimension either way, so the next ones stay the same
+        let shutter = sampler.get_1d();
+        let time = match self.shutter_close > self.shutter_open {
+            true => self.shutter_open + shutter * (self.shutter_close - self.shutter_open),
+            false => self.shutter_open,
+        };
         Ray::new(
             self.look_from + offset,
             self.lower_left_corner + s * self.horizontal + x * self.vertical
                 - self.look_from
                 - offset,
         )
+        .with_time(time)
     }
 
     pub fn samples_per_pixel(&self) -> u32 {
@@ -81,3 +139,39 @@ impl Camera {
         self.aspect_ratio
     }
 }
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+
+    #[test]
+    fn rays_are_timed_while_the_shutter_is_open() {
+        let settings = CameraSettings {
+            shutter_open: 1.0,
+            shutter_close: 3.0,
+            ..Default::default()
+        };
+        let mut sampler = IndependentSampler::new(5);
+        let camera = settings.build(100);
+        let times: Vec<_> = (0..100)
+            .map(|index| {
+                sampler.start_pixel_sample((0, 0), index);
+                camera.get_ray(0.5, 0.5, &mut sampler).time()
+            })
+            .collect();
+        assert!(times.iter().all(|time| (1.0..3.0).contains(time)));
+        assert!(times.iter().any(|&time| time > 2.0) && times.iter().any(|&time| time < 2.0));
+
+        let backwards = CameraSettings {
+            shutter_open: 3.0,
+            shutter_close: 1.0,
+            ..settings
+        };
+        let camera = backwards.build(100);
+        sampler.start_pixel_sample((0, 0), 0);
+        assert_eq!(camera.get_ray(0.5, 0.5, &mut sampler).time(), 3.0);
+    }
+}
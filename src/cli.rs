@@ -0,0 +1,294 @@
+use clap::Parser;
+use ray_tracing_rust::aov::Aov;
+use ray_tracing_rust::camera::CameraSettings;
+use ray_tracing_rust::sampler::SamplerKind;
+use ray_tracing_rust::scene::BuiltinScene;
+use ray_tracing_rust::vec3::*;
+use std::path::PathBuf;
+
+/// Renders a scene with path tracing and writes it to an image file.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    /// Output image, format is picked by extension (.png, .ppm or .pfm)
+    #[arg(default_value = "image.png")]
+    pub output: PathBuf,
+
+    /// Image width in pixels
+    #[arg(long, default_value_t = 1200, value_parser = clap::value_parser!(u32).range(2..))]
+    pub width: u32,
+
+    /// Image height in pixels [default: width / aspect ratio of the scene camera]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(2..))]
+    pub height: Option<u32>,
+
+    /// Samples per pixel
+    #[arg(long, default_value_t = 500, value_parser = clap::value_parser!(u32).range(1..))]
+    pub spp: u32,
+
+    /// Stop sampling a pixel once the standard error of its brightness is below this
+    /// fraction of the brightness, --spp becomes the most samples a pixel gets [default: off]
+    #[arg(long, value_parser = parse_positive, allow_negative_numbers = true)]
+    pub adaptive_threshold: Option<f64>,
+
+    /// Samples every pixel gets before --adaptive-threshold can stop it
+    #[arg(
+        long,
+        default_value_t = 16,
+        requires = "adaptive_threshold",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub min_spp: u32,
+
+    /// Also write an image of how many samples every pixel took, black for none and
+    /// white for --spp
+    #[arg(long)]
+    pub heatmap: Option<PathBuf>,
+
+    /// Also write what the camera rays hit first, comma separated: depth, normal, albedo,
+    /// object-id, material-id or position. Every one goes to a .pfm file next to the image,
+    /// like image.depth.pfm
+    #[arg(long = "aov", value_delimiter = ',', value_parser = parse_aov)]
+    pub aovs: Vec<Aov>,
+
+    /// Where the random numbers of the samples come from: independent, stratified, halton or sobol.
+    /// All but independent spread the samples of a pixel evenly and need fewer of them.
+    #[arg(long, default_value = "sobol", value_parser = parse_sampler)]
+    pub sampler: SamplerKind,
+
+    /// Trace one wavelength of light per sample instead of red, green and blue, so glass
+    /// with dispersion splits light into rainbows. Colors need more samples to settle.
+    #[arg(long)]
+    pub spectral: bool,
+
+    /// Maximum number of bounces of a ray
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u16).range(1..))]
+    pub max_depth: u16,
+
+    /// Number of render threads [default: number of cores]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Seed for the random numbers, a seed renders the same image with any number of threads [default: random]
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Built in scene to render
+    #[arg(long, default_value = "random-spheres", value_parser = parse_scene)]
+    pub scene: BuiltinScene,
+
+    /// Scene file to render instead of a built in scene
+    #[arg(long, conflicts_with = "scene")]
+    pub scene_file: Option<PathBuf>,
+
+    /// Save the scene to a scene file and exit without rendering
+    #[arg(long)]
+    pub export_scene: Option<PathBuf>,
+
+    /// Write 16 bit png instead of 8 bit
+    #[arg(long)]
+    pub png_16bit: bool,
+
+    /// Camera position, as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub look_from: Option<Point3>,
+
+    /// Point the camera looks at, as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub look_at: Option<Point3>,
+
+    /// Up direction of the camera, as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub vec_up: Option<Vec3>,
+
+    /// Vertical field of view in degrees
+    #[arg(long, value_parser = parse_fov, allow_negative_numbers = true)]
+    pub vfov: Option<f64>,
+
+    /// Lens diameter, 0 disables depth of field
+    #[arg(long, value_parser = parse_non_negative, allow_negative_numbers = true)]
+    pub aperture: Option<f64>,
+
+    /// Distance to the plane in focus
+    #[arg(long, value_parser = parse_positive, allow_negative_numbers = true)]
+    pub focus_dist: Option<f64>,
+}
+
+impl Args {
+    /// applies the camera overrides on top of the scene camera
+    pub fn camera_settings(&self, scene_camera: CameraSettings) -> Result<CameraSettings, String> {
+        let mut settings = scene_camera;
+        if let Some(height) = self.height {
+            settings.aspect_ratio = f64::from(self.width) / f64::from(height);
+        }
+        settings.look_from = self.look_from.unwrap_or(settings.look_from);
+        settings.look_at = self.look_at.unwrap_or(settings.look_at);
+        settings.vec_up = self.vec_up.unwrap_or(settings.vec_up);
+        settings.vertical_fov_degrees = self.vfov.unwrap_or(settings.vertical_fov_degrees);
+        settings.aperture = self.aperture.unwrap_or(settings.aperture);
+        settings.focus_dist = self.focus_dist.unwrap_or(settings.focus_dist);
+
+        let view_direction = settings.look_at - settings.look_from;
+        if view_direction.really_small() {
+            return Err(String::from(
+                "--look-from and --look-at should be different points",
+            ));
+        }
+        if cross(view_direction, settings.vec_up).really_small() {
+            return Err(String::from(
+                "--vec-up should not be parallel to the view direction",
+            ));
+        }
+        Ok(settings)
+    }
+
+    pub fn height(&self, aspect_ratio: f64) -> u32 {
+        self.height
+            .unwrap_or_else(|| (f64::from(self.width) / aspect_ratio).round().max(2.0) as u32)
+    }
+}
+
+fn parse_scene(s: &str) -> Result<BuiltinScene, String> {
+    BuiltinScene::from_name(s).ok_or_else(|| {
+        let names: Vec<&str> = BuiltinScene::ALL.iter().map(|scene| scene.name()).collect();
+        format!("unknown scene, expected one of: {}", names.join(", "))
+    })
+}
+
+fn parse_sampler(s: &str) -> Result<SamplerKind, String> {
+    SamplerKind::from_name(s).ok_or_else(|| {
+        let names: Vec<&str> = SamplerKind::ALL.iter().map(|kind| kind.name()).collect();
+        format!("unknown sampler, expected one of: {}", names.join(", "))
+    })
+}
+
+fn parse_aov(s: &str) -> Result<Aov, String> {
+    Aov::from_name(s).ok_or_else(|| {
+        let names: Vec<&str> = Aov::ALL.iter().map(|aov| aov.name()).collect();
+        format!("unknown output, expected one of: {}", names.join(", "))
+    })
+}
+
+fn parse_f64(s: &str) -> Result<f64, String> {
+    let value: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("`{}` is not a number", s.trim()))?;
+    if !value.is_finite() {
+        return Err(format!("`{}` is not a finite number", s.trim()));
+    }
+    Ok(value)
+}
+
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let parts: Vec<&str> = s.split(',').collect();
+    if parts.len() != 3 {
+        return Err(format!(
+            "expected three comma separated numbers like 1,2.5,-3 but got `{}`",
+            s
+        ));
+    }
+    Ok(Vec3::new(
+        parse_f64(parts[0])?,
+        parse_f64(parts[1])?,
+        parse_f64(parts[2])?,
+    ))
+}
+
+fn parse_fov(s: &str) -> Result<f64, String> {
+    let value = parse_f64(s)?;
+    if value <= 0.0 || value >= 180.0 {
+        return Err(String::from(
+            "field of view should be between 0 and 180 degrees",
+        ));
+    }
+    Ok(value)
+}
+
+fn parse_non_negative(s: &str) -> Result<f64, String> {
+    let value = parse_f64(s)?;
+    if value < 0.0 {
+        return Err(String::from("should not be negative"));
+    }
+    Ok(value)
+}
+
+fn parse_positive(s: &str) -> Result<f64, String> {
+    let value = parse_f64(s)?;
+    if value <= 0.0 {
+        return Err(String::from("should be positive"));
+    }
+    Ok(value)
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::error::ErrorKind;
+
+    fn parse_error(args: &[&str]) -> ErrorKind {
+        let args = ["ray-tracing-rust"].iter().chain(args);
+        Args::try_parse_from(args)
+            .expect_err("arguments should be rejected")
+            .kind()
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        for args in [
+            ["--width", "1"],
+            ["--width", "wide"],
+            ["--height", "0"],
+            ["--spp", "0"],
+            ["--max-depth", "0"],
+            ["--max-depth", "70000"],
+            ["--threads", "0"],
+            ["--seed", "1.5"],
+            ["--vfov", "180"],
+            ["--vfov", "-30"],
+            ["--aperture", "-0.1"],
+            ["--focus-dist", "0"],
+            ["--adaptive-threshold", "0"],
+            ["--look-from", "1,2"],
+            ["--look-at", "1,nan,3"],
+            ["--scene", "teapot"],
+            ["--sampler", "sobolev"],
+            ["--aov", "depth,shadow"],
+        ] {
+            assert_eq!(parse_error(&args), ErrorKind::ValueValidation, "{:?}", args);
+        }
+        let both = parse_error(&["--scene", "cornell-box", "--scene-file", "a.scene"]);
+        assert_eq!(both, ErrorKind::ArgumentConflict);
+
+        let args = Args::try_parse_from(["ray-tracing-rust", "--look-from", "1,-2,3"]).unwrap();
+        let camera = args.camera_settings(CameraSettings::default()).unwrap();
+        assert_eq!(camera.look_from, Point3::new(1.0, -2.0, 3.0));
+        let args = Args::try_parse_from(["ray-tracing-rust", "--look-at", "13,2,3"]).unwrap();
+        assert_eq!(
+            args.camera_settings(CameraSettings::default()),
+            Err(String::from(
+                "--look-from and --look-at should be different points"
+            ))
+        );
+    }
+
+    #[test]
+    fn adaptive_options_go_together() {
+        let missing = parse_error(&["--min-spp", "4"]);
+        assert_eq!(missing, ErrorKind::MissingRequiredArgument);
+        let args = [
+            "ray-tracing-rust",
+            "--min-spp",
+            "4",
+            "--adaptive-threshold",
+            "0.1",
+        ];
+        assert_eq!(Args::try_parse_from(args).unwrap().min_spp, 4);
+        assert_eq!(
+            Args::try_parse_from(["ray-tracing-rust"]).unwrap().min_spp,
+            16
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use crate::image_output::Framebuffer;
+use crate::number_stuff::degrees_to_radians;
+use crate::vec3::*;
+use std::f64::consts::PI;
+
+/// light that comes from infinitely far away, seen by rays that hit nothing
+pub trait Environment: Send + Sync {
+    /// radiance coming from the given direction, direction doesn't have to be a unit vector
+    fn color(&self, direction: Vec3) -> Color;
+}
+
+/// same color from every direction, black makes a closed room look
+pub struct ConstantEnvironment {
+    color: Color,
+}
+
+impl ConstantEnvironment {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Environment for ConstantEnvironment {
+    fn color(&self, _direction: Vec3) -> Color {
+        self.color
+    }
+}
+
+/// blends from the bottom color straight down to the top color straight up
+pub struct GradientEnvironment {
+    bottom: Color,
+    top: Color,
+}
+
+impl GradientEnvironment {
+    pub fn new(bottom: Color, top: Color) -> Self {
+        Self { bottom, top }
+    }
+
+    /// white to light blue sky
+    pub fn sky() -> Self {
+        Self::new(Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Environment for GradientEnvironment {
+    fn color(&self, direction: Vec3) -> Color {
+        let unit_direction = unit_vector(direction);
+        let t = 0.5 * (unit_direction.y() + 1.0);
+        (1.0 - t) * self.bottom + t * self.top
+    }
+}
+
+/// Equirectangular (latitude-longitude) map, like the ones in .hdr files.
+/// The middle of the image looks down -z, the top row is straight up.
+pub struct EnvironmentMap {
+    image: Framebuffer,
+    rotation_radians: f64,
+    intensity: f64,
+}
+
+impl EnvironmentMap {
+    /// rotation turns the map around the y axis, intensity scales its colors
+    pub fn new(image: Framebuffer, rotation_degrees: f64, intensity: f64) -> Self {
+        Self {
+            image,
+            rotation_radians: degrees_to_radians(rotation_degrees),
+            intensity,
+        }
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn color(&self, direction: Vec3) -> Color {
+        let d = unit_vector(direction);
+        let phi = d.x().atan2(-d.z()) + self.rotation_radians;
+        let u = (phi / (2.0 * PI) + 0.5).rem_euclid(1.0);
+        let v = d.y().clamp(-1.0, 1.0).acos() / PI;
+
+        // bilinear filtering, wraps around horizontally and clamps vertically
+        let width = self.image.width();
+        let height = self.image.height();
+        let x = u * width as f64 - 0.5;
+        let y = (v * height as f64 - 0.5).clamp(0.0, (height - 1) as f64);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let column = |x: f64| (x as i64).rem_euclid(width as i64) as usize;
+        let (left, right) = (column(x0), column(x0 + 1.0));
+        let (upper, lower) = (y0 as usize, (y0 as usize + 1).min(height - 1));
+        let top_row =
+            (1.0 - fx) * self.image.pixel(left, upper) + fx * self.image.pixel(right, upper);
+        let bottom_row =
+            (1.0 - fx) * self.image.pixel(left, lower) + fx * self.image.pixel(right, lower);
+        self.intensity * ((1.0 - fy) * top_row + fy * bottom_row)
+    }
+}
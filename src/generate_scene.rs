@@ -1,72 +1,228 @@
 use crate::{
-    material::{Dielectric, Lambertian, Metal},
-    number_stuff::{random_f64, random_f64_in_range, random_vec, random_vec_in_range},
+    camera::CameraSettings,
+    number_stuff::Rng,
     ray_trace::HittableList,
-    sphere::Sphere,
-    vec3::{Color, Point3},
+    scene::{EnvironmentDescription, MaterialDescription, ObjectDescription, SceneDescription},
+    vec3::{Color, Point3, Vec3},
 };
-use std::rc::Rc;
 
-pub fn generate_scene() -> HittableList {
-    let mut world = HittableList::default();
-    let ground_material = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    world.add(Rc::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        ground_material,
-    )));
+/// the same generator state always gives the same layout
+pub fn generate_scene(rng: &mut Rng) -> HittableList {
+    generate_scene_description(rng)
+        .build(rng)
+        .expect("random spheres don't load files")
+        .world
+}
+
+/// the random spheres scene as plain data, so it can be exported to a scene file
+pub fn generate_scene_description(rng: &mut Rng) -> SceneDescription {
+    random_spheres_description(false, rng)
+}
+
+/// Random spheres where the small diffuse ones jump up while the shutter is open,
+/// like the motion blur scene of Ray Tracing: The Next Week
+pub fn bouncing_spheres_description(rng: &mut Rng) -> SceneDescription {
+    random_spheres_description(true, rng)
+}
+
+fn random_spheres_description(bouncing: bool, rng: &mut Rng) -> SceneDescription {
+    let mut scene = SceneDescription {
+        camera: CameraSettings {
+            shutter_close: if bouncing { 1.0 } else { 0.0 },
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    // every sphere gets a material of its own
+    let add_material = |scene: &mut SceneDescription, material| {
+        let name = format!("material{}", scene.materials.len());
+        scene.materials.push((name.clone(), material));
+        name
+    };
+    let add_sphere = |scene: &mut SceneDescription, center, radius, material| {
+        let material = add_material(scene, material);
+        scene.objects.push(ObjectDescription::Sphere {
+            center,
+            radius,
+            material,
+        });
+    };
+
+    let ground_material = MaterialDescription::Lambertian {
+        texture: Color::new(0.5, 0.5, 0.5).into(),
+    };
+    let ground_material = add_material(&mut scene, ground_material);
+    scene.objects.push(ObjectDescription::Quad {
+        corner: Point3::new(-1000.0, 0.0, -1000.0),
+        u: Vec3::new(0.0, 0.0, 2000.0),
+        v: Vec3::new(2000.0, 0.0, 0.0),
+        material: ground_material,
+    });
 
     for a_int in -11..11 {
         for b_int in -11..11 {
             let a = a_int as f64;
             let b = b_int as f64;
-            let choose_mat = random_f64();
-            let center = Point3::new(a + 0.9 * random_f64(), 0.2, b + 0.9 * random_f64());
+            let choose_mat = rng.random_f64();
+            let center = Point3::new(a + 0.9 * rng.random_f64(), 0.2, b + 0.9 * rng.random_f64());
 
             if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                // shared_ptr<material> sphere_material;
                 if choose_mat < 0.8 {
                     // diffuse
-                    let color = random_vec() * random_vec();
-                    world.add(Rc::new(Sphere::new(
-                        center,
-                        0.2,
-                        Rc::new(Lambertian::new(color)),
-                    )));
+                    let color = rng.random_vec() * rng.random_vec();
+                    let texture = color.into();
+                    let material = MaterialDescription::Lambertian { texture };
+                    if bouncing {
+                        let material = add_material(&mut scene, material);
+                        scene.objects.push(ObjectDescription::MovingSphere {
+                            center0: center,
+                            center1: center
+                                + Vec3::new(0.0, rng.random_f64_in_range(0.0, 0.5), 0.0),
+                            time0: 0.0,
+                            time1: 1.0,
+                            radius: 0.2,
+                            material,
+                        });
+                    } else {
+                        add_sphere(&mut scene, center, 0.2, material);
+                    }
                 } else if choose_mat < 0.95 {
                     // metal
-                    let color = random_vec_in_range(0.5, 1.0);
-                    let fuzz = random_f64_in_range(0.0, 0.5);
-                    let sphere_material = Rc::new(Metal::new(color, fuzz));
-                    world.add(Rc::new(Sphere::new(center, 0.2, sphere_material)));
+                    let color = rng.random_vec_in_range(0.5, 1.0);
+                    let fuzziness = rng.random_f64_in_range(0.0, 0.5);
+                    let texture = color.into();
+                    add_sphere(
+                        &mut scene,
+                        center,
+                        0.2,
+                        MaterialDescription::Metal { texture, fuzziness },
+                    );
                 } else {
                     // glass
-                    let sphere_material = Rc::new(Dielectric::new(1.5));
-                    world.add(Rc::new(Sphere::new(center, 0.2, sphere_material)));
+                    let sphere_material = MaterialDescription::dielectric(1.5);
+                    add_sphere(&mut scene, center, 0.2, sphere_material);
                 }
             }
         }
     }
-    let material1 = Rc::new(Dielectric::new(1.5));
-    world.add(Rc::new(Sphere::new(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        material1,
-    )));
-
-    let material2 = Rc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Rc::new(Sphere::new(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material2,
-    )));
-
-    let material3 = Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Rc::new(Sphere::new(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        material3,
-    )));
-
-    world
+    let material1 = MaterialDescription::dielectric(1.5);
+    add_sphere(&mut scene, Point3::new(0.0, 1.0, 0.0), 1.0, material1);
+
+    let material2 = MaterialDescription::Lambertian {
+        texture: Color::new(0.4, 0.2, 0.1).into(),
+    };
+    add_sphere(&mut scene, Point3::new(-4.0, 1.0, 0.0), 1.0, material2);
+
+    let material3 = MaterialDescription::Metal {
+        texture: Color::new(0.7, 0.6, 0.5).into(),
+        fuzziness: 0.0,
+    };
+    add_sphere(&mut scene, Point3::new(4.0, 1.0, 0.0), 1.0, material3);
+
+    scene
+}
+
+fn add_material(scene: &mut SceneDescription, name: &str, material: MaterialDescription) {
+    scene.materials.push((name.to_string(), material));
+}
+
+fn add_quad(scene: &mut SceneDescription, corner: Point3, u: Vec3, v: Vec3, material: &str) {
+    scene.objects.push(ObjectDescription::Quad {
+        corner,
+        u,
+        v,
+        material: material.to_string(),
+    });
+}
+
+/// box standing on the floor with the given size, turned around the y axis and moved
+fn add_box(
+    scene: &mut SceneDescription,
+    size: Vec3,
+    angle_degrees: f64,
+    offset: Vec3,
+    material: &str,
+) {
+    let shape = ObjectDescription::BoxShape {
+        min: Point3::default(),
+        max: size,
+        material: material.to_string(),
+    };
+    scene.objects.push(ObjectDescription::Instance {
+        object: Box::new(shape),
+        scale: Vec3::new(1.0, 1.0, 1.0),
+        rotation_degrees: Vec3::new(0.0, angle_degrees, 0.0),
+        translation: offset,
+    });
+}
+
+/// the empty room of the Cornell box, 555 units wide, with a light on the ceiling
+/// between the given x and z coordinates
+fn cornell_room(light_x: (f64, f64), light_z: (f64, f64), light_color: Color) -> SceneDescription {
+    let mut scene = SceneDescription {
+        camera: CameraSettings {
+            vertical_fov_degrees: 40.0,
+            aspect_ratio: 1.0,
+            look_from: Point3::new(278.0, 278.0, -800.0),
+            look_at: Point3::new(278.0, 278.0, 0.0),
+            vec_up: Vec3::new(0.0, 1.0, 0.0),
+            aperture: 0.0,
+            focus_dist: 10.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+        },
+        environment: EnvironmentDescription::Constant {
+            color: Color::default(),
+        },
+        ..Default::default()
+    };
+    let lambertian = |r, g, b| MaterialDescription::Lambertian {
+        texture: Color::new(r, g, b).into(),
+    };
+    add_material(&mut scene, "red", lambertian(0.65, 0.05, 0.05));
+    add_material(&mut scene, "white", lambertian(0.73, 0.73, 0.73));
+    add_material(&mut scene, "green", lambertian(0.12, 0.45, 0.15));
+    let light = MaterialDescription::DiffuseLight {
+        texture: light_color.into(),
+    };
+    add_material(&mut scene, "light", light);
+
+    let x = Vec3::new(555.0, 0.0, 0.0);
+    let y = Vec3::new(0.0, 555.0, 0.0);
+    let z = Vec3::new(0.0, 0.0, 555.0);
+    let origin = Point3::default();
+    // walls face into the room
+    add_quad(&mut scene, origin + x, z, y, "green");
+    add_quad(&mut scene, origin, y, z, "red");
+    add_quad(&mut scene, origin, z, x, "white");
+    add_quad(&mut scene, origin + y, x, z, "white");
+    add_quad(&mut scene, origin + z, y, x, "white");
+    add_quad(
+        &mut scene,
+        Point3::new(light_x.0, 554.0, light_z.0),
+        Vec3::new(light_x.1 - light_x.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, light_z.1 - light_z.0),
+        "light",
+    );
+    scene
+}
+
+/// the classic Cornell box with two white boxes inside
+pub fn cornell_box_description() -> SceneDescription {
+    let mut scene = cornell_room((213.0, 343.0), (227.0, 332.0), Color::new(15.0, 15.0, 15.0));
+    add_box(
+        &mut scene,
+        Vec3::new(165.0, 330.0, 165.0),
+        15.0,
+        Vec3::new(265.0, 0.0, 295.0),
+        "white",
+    );
+    add_box(
+        &mut scene,
+        Vec3::new(165.0, 165.0, 165.0),
+        -18.0,
+        Vec3::new(130.0, 0.0, 65.0),
+        "white",
+    );
+    scene
 }
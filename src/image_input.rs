@@ -0,0 +1,257 @@
+use crate::image_output::Framebuffer;
+use crate::vec3::*;
+use std::path::Path;
+
+/// Reads a PNG, JPEG or Radiance .hdr file, picked by the extension.
+/// 8 and 16 bit images are turned into linear colors with the inverse of the gamma 2
+/// that `image_output::tone_map` applies, so a texture renders as its own colors.
+pub fn load_image(path: &Path) -> Result<Framebuffer, String> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let image = match extension.as_deref() {
+        Some("png") => parse_png(&bytes),
+        Some("jpg" | "jpeg") => parse_jpeg(&bytes),
+        Some("hdr") => parse_hdr(&bytes),
+        _ => Err(String::from(
+            "unknown image format, expected .png, .jpg, .jpeg or .hdr",
+        )),
+    };
+    image.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn to_linear(value: f64, max: f64) -> f64 {
+    let c = value / max;
+    c * c
+}
+
+pub fn parse_png(bytes: &[u8]) -> Result<Framebuffer, String> {
+    let mut decoder = png::Decoder::new(bytes);
+    // palettes become rgb and bit depths below 8 become 8
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+    let channels = info.color_type.samples();
+    let sixteen_bit = info.bit_depth == png::BitDepth::Sixteen;
+    let (sample_size, max) = match sixteen_bit {
+        true => (2, 65535.0),
+        false => (1, 255.0),
+    };
+    let sample = |bytes: &[u8]| match sixteen_bit {
+        true => f64::from(u16::from_be_bytes([bytes[0], bytes[1]])),
+        false => f64::from(bytes[0]),
+    };
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    let mut framebuffer = Framebuffer::new(width, height);
+    for y in 0..height {
+        let row = &buffer[y * info.line_size..(y + 1) * info.line_size];
+        for x in 0..width {
+            let pixel = &row[x * channels * sample_size..(x + 1) * channels * sample_size];
+            let channel = |i: usize| to_linear(sample(&pixel[i * sample_size..]), max);
+            // alpha is ignored, gray (with or without alpha) has fewer than 3 channels
+            let color = match channels {
+                1 | 2 => Color::new(channel(0), channel(0), channel(0)),
+                _ => Color::new(channel(0), channel(1), channel(2)),
+            };
+            framebuffer.set_pixel(x, y, color);
+        }
+    }
+    Ok(framebuffer)
+}
+
+pub fn parse_jpeg(bytes: &[u8]) -> Result<Framebuffer, String> {
+    let mut decoder = jpeg_decoder::Decoder::new(bytes);
+    let pixels = decoder.decode().map_err(|e| e.to_string())?;
+    let info = decoder.info().ok_or("missing image info")?;
+    let (width, height) = (usize::from(info.width), usize::from(info.height));
+    let channels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 => 1,
+        jpeg_decoder::PixelFormat::RGB24 => 3,
+        format => return Err(format!("unsupported pixel format {:?}", format)),
+    };
+    let mut framebuffer = Framebuffer::new(width, height);
+    for (i, pixel) in pixels.chunks(channels).enumerate() {
+        let channel = |i: usize| to_linear(f64::from(pixel[i]), 255.0);
+        let color = match channels {
+            1 => Color::new(channel(0), channel(0), channel(0)),
+            _ => Color::new(channel(0), channel(1), channel(2)),
+        };
+        framebuffer.set_pixel(i % width, i / width, color);
+    }
+    Ok(framebuffer)
+}
+
+/// reads a Radiance .hdr (RGBE) file into linear colors
+pub fn load_hdr(path: &Path) -> Result<Framebuffer, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_hdr(&bytes).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn parse_hdr(bytes: &[u8]) -> Result<Framebuffer, String> {
+    let mut rest = bytes;
+    let mut next_line = || -> Result<&[u8], String> {
+        let end = rest
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or("unexpected end of the header")?;
+        let line = &rest[..end];
+        rest = &rest[end + 1..];
+        Ok(line)
+    };
+
+    let magic = next_line()?;
+    if magic != b"#?RADIANCE" && magic != b"#?RGBE" {
+        return Err(String::from("not a Radiance HDR file"));
+    }
+    loop {
+        let line = next_line()?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix(b"FORMAT=") {
+            if format != b"32-bit_rle_rgbe" {
+                return Err(format!(
+                    "unsupported format {}",
+                    String::from_utf8_lossy(format)
+                ));
+            }
+        }
+    }
+    let resolution = String::from_utf8_lossy(next_line()?).into_owned();
+    let parts: Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match parts[..] {
+        ["-Y", height, "+X", width] => (height.parse::<usize>(), width.parse::<usize>()),
+        _ => {
+            return Err(format!(
+                "unsupported resolution line `{}`, only -Y h +X w is supported",
+                resolution
+            ))
+        }
+    };
+    let (Ok(height), Ok(width)) = (height, width) else {
+        return Err(format!("bad resolution line `{}`", resolution));
+    };
+    // environment maps look up pixels around the direction, they need at least one
+    if width == 0 || height == 0 {
+        return Err(format!("image has no pixels: `{}`", resolution));
+    }
+
+    let mut framebuffer = Framebuffer::new(width, height);
+    let mut data = rest;
+    let mut scanline = vec![[0u8; 4]; width];
+    for y in 0..height {
+        data = read_scanline(data, &mut scanline)?;
+        for (x, rgbe) in scanline.iter().enumerate() {
+            framebuffer.set_pixel(x, y, rgbe_to_color(*rgbe));
+        }
+    }
+    Ok(framebuffer)
+}
+
+fn rgbe_to_color([r, g, b, e]: [u8; 4]) -> Color {
+    if e == 0 {
+        return Color::default();
+    }
+    let scale = 2f64.powi(i32::from(e) - 128 - 8);
+    Color::new(
+        (f64::from(r) + 0.5) * scale,
+        (f64::from(g) + 0.5) * scale,
+        (f64::from(b) + 0.5) * scale,
+    )
+}
+
+/// decodes one scanline, either flat or new style run length encoded, returns the rest of data
+fn read_scanline<'a>(data: &'a [u8], scanline: &mut [[u8; 4]]) -> Result<&'a [u8], String> {
+    let width = scanline.len();
+    let truncated = || String::from("pixel data is truncated");
+    let is_rle = data.len() >= 4
+        && data[0] == 2
+        && data[1] == 2
+        && data[2] & 0x80 == 0
+        && (8..0x8000).contains(&width);
+    if !is_rle {
+        let bytes = data.get(..width * 4).ok_or_else(truncated)?;
+        for (pixel, chunk) in scanline.iter_mut().zip(bytes.chunks(4)) {
+            pixel.copy_from_slice(chunk);
+        }
+        return Ok(&data[width * 4..]);
+    }
+
+    let encoded_width = (usize::from(data[2]) << 8) | usize::from(data[3]);
+    if encoded_width != width {
+        return Err(String::from("scanline width doesn't match the image width"));
+    }
+    let mut pos = 4;
+    // channels are stored one after another, each as runs and literal dumps
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let count = *data.get(pos).ok_or_else(truncated)? as usize;
+            pos += 1;
+            if count > 128 {
+                let run = count - 128;
+                let value = *data.get(pos).ok_or_else(truncated)?;
+                pos += 1;
+                if x + run > width {
+                    return Err(String::from("run goes past the end of the scanline"));
+                }
+                for pixel in &mut scanline[x..x + run] {
+                    pixel[channel] = value;
+                }
+                x += run;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(String::from("bad dump length in a scanline"));
+                }
+                let values = data.get(pos..pos + count).ok_or_else(truncated)?;
+                for (pixel, &value) in scanline[x..x + count].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+                pos += count;
+                x += count;
+            }
+        }
+    }
+    Ok(&data[pos..])
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_and_rle_scanlines() {
+        let mut file = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 8\n".to_vec();
+        // first row is flat, every pixel is (1, 0.5, 0)
+        for _ in 0..8 {
+            file.extend_from_slice(&[128, 64, 0, 129]);
+        }
+        // second row is run length encoded: red is a run, green is a dump, blue and e are runs
+        file.extend_from_slice(&[2, 2, 0, 8]);
+        file.extend_from_slice(&[128 + 8, 255]);
+        file.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        file.extend_from_slice(&[128 + 8, 0]);
+        file.extend_from_slice(&[128 + 8, 128]);
+
+        let image = parse_hdr(&file).unwrap();
+        assert_eq!((image.width(), image.height()), (8, 2));
+        let scale = 1.0 / 256.0;
+        assert_eq!(
+            image.pixel(3, 0),
+            Color::new(128.5, 64.5, 0.5) * 2.0 * scale
+        );
+        assert_eq!(image.pixel(2, 1), Color::new(255.5, 32.5, 0.5) * scale);
+
+        for resolution in ["-Y 0 +X 0", "-Y 0 +X 8", "-Y 2 +X 0"] {
+            let empty = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n{}\n", resolution);
+            let error = parse_hdr(empty.as_bytes()).unwrap_err();
+            assert_eq!(error, format!("image has no pixels: `{}`", resolution));
+        }
+    }
+}
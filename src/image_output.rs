@@ -0,0 +1,268 @@
+use crate::vec3::*;
+use num::clamp;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Linear float image. Pixel (0, 0) is the top left one, rows go top to bottom.
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::default(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// all pixels row after row, handy for filling rows in parallel with `chunks_mut(width)`
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+}
+
+/// gamma 2 correction and clamping to [0.0, 1.0], what a linear color needs before it's quantized
+pub fn tone_map(color: Color) -> Color {
+    let map = |c: f64| clamp(c.sqrt(), 0.0, 1.0);
+    Color::new(map(color.x()), map(color.y()), map(color.z()))
+}
+
+fn to_u8(c: f64) -> u8 {
+    (256.0 * clamp(c, 0.0, 0.999)) as u8
+}
+
+fn to_u16(c: f64) -> u16 {
+    (65535.0 * clamp(c, 0.0, 1.0)).round() as u16
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// binary P6 ppm, 8 bits per channel
+    Ppm,
+    Png {
+        sixteen_bit: bool,
+    },
+    /// linear floats without tone mapping, for compositing
+    Pfm,
+}
+
+impl ImageFormat {
+    /// picks the format by file extension, 8 bit for png
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png { sixteen_bit: false }),
+            "pfm" => Some(ImageFormat::Pfm),
+            _ => None,
+        }
+    }
+}
+
+/// writes the image to the file, format is chosen by the file extension
+pub fn write_image(path: &Path, framebuffer: &Framebuffer) -> io::Result<()> {
+    let format = ImageFormat::from_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "can't tell image format of {}, expected .ppm, .png or .pfm",
+                path.display()
+            ),
+        )
+    })?;
+    write_image_as(path, framebuffer, format)
+}
+
+pub fn write_image_as(
+    path: &Path,
+    framebuffer: &Framebuffer,
+    format: ImageFormat,
+) -> io::Result<()> {
+    write_mapped(path, framebuffer, format, tone_map)
+}
+
+/// Writes colors that are already how they should look, like false colors, without
+/// tone mapping. They are still clamped to [0.0, 1.0] except in pfm files.
+pub fn write_display_image_as(
+    path: &Path,
+    framebuffer: &Framebuffer,
+    format: ImageFormat,
+) -> io::Result<()> {
+    write_mapped(path, framebuffer, format, |color| color)
+}
+
+/// `map` turns colors into what 8 and 16 bit formats store, pfm stores them as they are
+fn write_mapped(
+    path: &Path,
+    framebuffer: &Framebuffer,
+    format: ImageFormat,
+    map: fn(Color) -> Color,
+) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    match format {
+        ImageFormat::Ppm => write_ppm_mapped(&mut out, framebuffer, map)?,
+        ImageFormat::Png { sixteen_bit } => {
+            write_png_mapped(&mut out, framebuffer, sixteen_bit, map)?
+        }
+        ImageFormat::Pfm => write_pfm(&mut out, framebuffer)?,
+    }
+    out.flush()
+}
+
+pub fn write_ppm<W: Write>(out: &mut W, framebuffer: &Framebuffer) -> io::Result<()> {
+    write_ppm_mapped(out, framebuffer, tone_map)
+}
+
+fn write_ppm_mapped<W: Write>(
+    out: &mut W,
+    framebuffer: &Framebuffer,
+    map: fn(Color) -> Color,
+) -> io::Result<()> {
+    write!(
+        out,
+        "P6\n{} {}\n255\n",
+        framebuffer.width(),
+        framebuffer.height()
+    )?;
+    let bytes: Vec<u8> = framebuffer
+        .pixels()
+        .iter()
+        .map(|&color| map(color))
+        .flat_map(|c| [to_u8(c.x()), to_u8(c.y()), to_u8(c.z())])
+        .collect();
+    out.write_all(&bytes)
+}
+
+pub fn write_png<W: Write>(
+    out: &mut W,
+    framebuffer: &Framebuffer,
+    sixteen_bit: bool,
+) -> io::Result<()> {
+    write_png_mapped(out, framebuffer, sixteen_bit, tone_map)
+}
+
+fn write_png_mapped<W: Write>(
+    out: &mut W,
+    framebuffer: &Framebuffer,
+    sixteen_bit: bool,
+    map: fn(Color) -> Color,
+) -> io::Result<()> {
+    let mut encoder =
+        png::Encoder::new(out, framebuffer.width() as u32, framebuffer.height() as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    let pixels = framebuffer.pixels().iter().map(|&color| map(color));
+    let data: Vec<u8> = if sixteen_bit {
+        encoder.set_depth(png::BitDepth::Sixteen);
+        // png stores 16 bit samples big endian
+        pixels
+            .flat_map(|c| [to_u16(c.x()), to_u16(c.y()), to_u16(c.z())])
+            .flat_map(u16::to_be_bytes)
+            .collect()
+    } else {
+        encoder.set_depth(png::BitDepth::Eight);
+        pixels
+            .flat_map(|c| [to_u8(c.x()), to_u8(c.y()), to_u8(c.z())])
+            .collect()
+    };
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&data).map_err(io::Error::other)
+}
+
+/// pfm stores rows bottom to top, negative scale means little endian
+pub fn write_pfm<W: Write>(out: &mut W, framebuffer: &Framebuffer) -> io::Result<()> {
+    write!(
+        out,
+        "PF\n{} {}\n-1.0\n",
+        framebuffer.width(),
+        framebuffer.height()
+    )?;
+    let mut bytes = Vec::with_capacity(framebuffer.pixels().len() * 12);
+    for row in framebuffer.pixels().chunks(framebuffer.width()).rev() {
+        for color in row {
+            for c in [color.x(), color.y(), color.z()] {
+                bytes.extend_from_slice(&(c as f32).to_le_bytes());
+            }
+        }
+    }
+    out.write_all(&bytes)
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_pixels() -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(1, 2);
+        framebuffer.set_pixel(0, 0, Color::new(0.25, 1.0, 4.0));
+        framebuffer.set_pixel(0, 1, Color::new(0.0, 0.0, -1.0));
+        framebuffer
+    }
+
+    #[test]
+    fn ppm_is_tone_mapped() {
+        let mut out = Vec::new();
+        write_ppm(&mut out, &two_pixels()).unwrap();
+        let mut expected = b"P6\n1 2\n255\n".to_vec();
+        expected.extend_from_slice(&[128, 255, 255, 0, 0, 0]);
+        assert_eq!(out, expected);
+
+        // false colors are written as they are
+        let mut out = Vec::new();
+        write_ppm_mapped(&mut out, &two_pixels(), |color| color).unwrap();
+        assert_eq!(out[out.len() - 6..], [64, 255, 255, 0, 0, 0]);
+    }
+
+    #[test]
+    fn pfm_is_linear_and_bottom_up() {
+        let mut out = Vec::new();
+        write_pfm(&mut out, &two_pixels()).unwrap();
+        let header = b"PF\n1 2\n-1.0\n";
+        assert_eq!(&out[..header.len()], header);
+        let floats: Vec<f32> = out[header.len()..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(floats, vec![0.0, 0.0, -1.0, 0.25, 1.0, 4.0]);
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            ImageFormat::from_path(Path::new("out/image.PNG")),
+            Some(ImageFormat::Png { sixteen_bit: false })
+        );
+        assert_eq!(
+            ImageFormat::from_path(Path::new("image.pfm")),
+            Some(ImageFormat::Pfm)
+        );
+        assert_eq!(ImageFormat::from_path(Path::new("image.jpg")), None);
+    }
+}
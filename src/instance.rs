@@ -0,0 +1,138 @@
+use crate::aabb::Aabb;
+use crate::number_stuff::degrees_to_radians;
+use crate::ray::*;
+use crate::ray_trace::*;
+use crate::vec3::*;
+use std::sync::Arc;
+
+/// Places a shared object in the world with an affine transform, so one mesh
+/// can be drawn many times at different positions, rotations and sizes.
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    /// object space to world space
+    transform: Matrix4,
+    /// world space to object space
+    inverse: Matrix4,
+    /// normals are transformed by the inverse transpose, so they stay perpendicular
+    normal_transform: Matrix4,
+}
+
+impl Instance {
+    /// errors if the transform can't be inverted, like a scale by 0 or close to it
+    pub fn new(object: Arc<dyn Hittable>, transform: Matrix4) -> Result<Self, String> {
+        let inverse = transform
+            .inverse()
+            .ok_or("instance transform should be invertible")?;
+        Ok(Self {
+            object,
+            transform,
+            inverse,
+            normal_transform: inverse.transpose(),
+        })
+    }
+
+    pub fn transform(&self) -> Matrix4 {
+        self.transform
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        hit_transformed(
+            self.object.as_ref(),
+            &self.transform,
+            &self.inverse,
+            &self.normal_transform,
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(transformed_box(
+            &self.object.bounding_box()?,
+            &self.transform,
+        ))
+    }
+}
+
+/// scales, then rotates around x, y and z (in degrees), then moves
+pub fn placement(scale: Vec3, rotation_degrees: Vec3, translation: Vec3) -> Matrix4 {
+    let rotate = |axis: usize| degrees_to_radians(rotation_degrees.component(axis));
+    Matrix4::translate(translation)
+        * Matrix4::rotate_z(rotate(2))
+        * Matrix4::rotate_y(rotate(1))
+        * Matrix4::rotate_x(rotate(0))
+        * Matrix4::scale(scale)
+}
+
+/// Hits an object placed by `transform` by moving the ray into object space and
+/// the hit back out. `inverse` and `normal_transform` have to belong to `transform`.
+pub(crate) fn hit_transformed<'a>(
+    object: &'a dyn Hittable,
+    transform: &Matrix4,
+    inverse: &Matrix4,
+    normal_transform: &Matrix4,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord<'a>> {
+    // the direction isn't normalized, so t means the same in both spaces
+    let local = r.moved(
+        inverse.transform_point(r.origin()),
+        inverse.transform_vector(r.direction()),
+    );
+    let mut record = object.hit(&local, t_min, t_max)?;
+    record.point = transform.transform_point(record.point);
+    record.normal = unit_vector(normal_transform.transform_vector(record.normal));
+    record.tangent = transform.transform_vector(record.tangent);
+    Some(record)
+}
+
+/// box around the transformed corners of the box
+pub(crate) fn transformed_box(bbox: &Aabb, transform: &Matrix4) -> Aabb {
+    let corners = bbox
+        .corners()
+        .map(|corner| transform.transform_point(corner));
+    let first = Aabb::new(corners[0], corners[0]);
+    corners[1..]
+        .iter()
+        .fold(first, |acc, corner| acc.expanded_to(*corner))
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn instances_are_hit_where_they_are_placed() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Arc::new(Sphere::new(Point3::default(), 1.0, material));
+        // squashed to an ellipsoid 4 wide and 1 tall, then moved up
+        let transform = Matrix4::translate(Vec3::new(0.0, 3.0, 0.0))
+            * Matrix4::rotate_y(std::f64::consts::FRAC_PI_2)
+            * Matrix4::scale(Vec3::new(1.0, 0.5, 2.0));
+        let instance = Instance::new(sphere.clone(), transform).unwrap();
+
+        let ray = Ray::new(Point3::new(-5.0, 3.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let record = instance.hit(&ray, 0.0, f64::MAX).unwrap();
+        assert!((record.point - Point3::new(-2.0, 3.0, 0.0)).really_small());
+        assert!((record.normal - Vec3::new(-1.0, 0.0, 0.0)).really_small());
+        assert!((record.t - 3.0).abs() < 1e-9);
+
+        let above = Ray::new(Point3::new(0.0, 3.6, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(instance.hit(&above, 0.0, f64::MAX).is_none());
+
+        let bbox = instance.bounding_box().unwrap();
+        assert!((bbox.min() - Point3::new(-2.0, 2.5, -1.0)).really_small());
+        assert!((bbox.max() - Point3::new(2.0, 3.5, 1.0)).really_small());
+
+        let flat = Matrix4::scale(Vec3::new(1e-13, 1.0, 1.0));
+        assert!(Instance::new(sphere, flat).is_err());
+    }
+}
@@ -0,0 +1,36 @@
+#![warn(rust_2018_idioms)]
+
+pub mod aabb;
+pub mod adaptive;
+pub mod aov;
+pub mod bvh;
+pub mod camera;
+pub mod environment;
+pub mod generate_scene;
+pub mod image_input;
+pub mod image_output;
+pub mod instance;
+pub mod material;
+pub mod medium;
+pub mod microfacet;
+pub mod motion;
+pub mod number_stuff;
+pub mod obj;
+pub mod pdf;
+pub mod perlin;
+pub mod principled;
+pub mod quad;
+pub mod ray;
+pub mod ray_trace;
+pub mod sampler;
+pub mod scene;
+pub mod scene_file;
+pub mod spectrum;
+pub mod sphere;
+pub mod texture;
+pub mod triangle;
+pub mod vec3;
+
+pub use ray::Ray;
+pub use ray_trace::HitRecord;
+pub use vec3::Color;
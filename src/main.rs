@@ -1,93 +1,442 @@
 #![warn(rust_2018_idioms)]
 
-/*
-Constants to increase/decrease quality:
-image_width_pixels
-samples_per_pixel (in cam)
-max_depth
-*/
-
-use crate::ray::*;
-use crate::ray_trace::*;
-use crate::vec3::*;
-use camera::*;
-use generate_scene::generate_scene;
-use number_stuff::*;
-
-pub mod camera;
-pub mod generate_scene;
-pub mod material;
-pub mod number_stuff;
-pub mod ray;
-pub mod ray_trace;
-pub mod sphere;
-pub mod vec3;
-
-fn ray_color<T: Hittable>(ray: &mut Ray, world: &T, depth: u16) -> Color {
+mod cli;
+
+// Quality is controlled with --width, --spp and --max-depth, see --help
+
+use clap::{CommandFactory, Parser};
+use cli::Args;
+use ray_tracing_rust::adaptive::{heatmap_color, AdaptiveSampling, PixelEstimate};
+use ray_tracing_rust::aov::{AovPixel, AovSample, MaterialIds};
+use ray_tracing_rust::bvh::{BvhNode, SplitHeuristic};
+use ray_tracing_rust::camera::*;
+use ray_tracing_rust::environment::Environment;
+use ray_tracing_rust::image_output::{
+    write_display_image_as, write_image_as, Framebuffer, ImageFormat,
+};
+use ray_tracing_rust::number_stuff::*;
+use ray_tracing_rust::pdf::{HittablePdf, MaterialPdf, MixturePdf, Pdf};
+use ray_tracing_rust::ray::*;
+use ray_tracing_rust::ray_trace::*;
+use ray_tracing_rust::sampler::Sampler;
+use ray_tracing_rust::scene_file::{read_scene_file, write_scene_file};
+use ray_tracing_rust::spectrum::{at_wavelength, sample_wavelength, wavelength_to_srgb};
+use ray_tracing_rust::vec3::*;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// everything a ray can run into
+struct World<'a, T: Hittable> {
+    objects: &'a T,
+    /// lights that are sampled directly, rays only find the others by chance
+    lights: &'a HittableList,
+    environment: &'a dyn Environment,
+    material_ids: &'a MaterialIds,
+}
+
+/// Light coming back along the ray. Rays with a wavelength find the light at that
+/// wavelength only, every channel of the result has it. What the ray hits first goes
+/// to `aov` when it is given, camera rays take it from there.
+fn ray_color<T: Hittable>(
+    ray: &Ray,
+    world: &World<'_, T>,
+    depth: u16,
+    sampler: &mut dyn Sampler,
+    aov: Option<&mut AovSample>,
+) -> Color {
     if depth == 0 {
         return Color::default();
     }
-    match world.hit(ray, 0.00001, f64::MAX) {
-        Some(record) => {
-            // TODO: add class for reflection method
-            // let reflect_point = record.point + record.normal + random_unit_vector();
-            let color = record
-                .material_reference
-                .clone()
-                .expect("no idea why empty world works too")
-                .project_ray(ray, &record);
-            color * ray_color(ray, world, depth - 1)
-        }
-        None => {
-            let unit_direction = unit_vector(ray.direction());
-            let t = 0.5 * (unit_direction.y() + 1.0);
-            (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+    // media hit by the ray pick where it scatters with this
+    let ray = &ray.with_free_flight(sampler.get_1d());
+    let wavelength = ray.wavelength();
+    let hit = world.objects.hit(ray, 0.00001, f64::MAX);
+    if let Some(aov) = aov {
+        *aov = match &hit {
+            Some(record) => AovSample::from_hit(ray, record, world.material_ids),
+            None => AovSample::default(),
+        };
+    }
+    let Some(record) = hit else {
+        return at_wavelength(world.environment.color(ray.direction()), wavelength);
+    };
+    let material = record
+        .material_reference
+        .expect("no idea why empty world works too");
+    let emitted = at_wavelength(material.emitted(&record), wavelength);
+    let Some(scatter) = material.scatter(ray, &record, sampler) else {
+        // absorbed, the rest of the path can't add anything
+        return emitted;
+    };
+    if scatter.is_blocked() && world.lights.is_empty() {
+        return emitted;
+    }
+    if scatter.is_specular() || world.lights.is_empty() {
+        let attenuation = at_wavelength(scatter.attenuation, wavelength);
+        let scattered = scatter.ray.with_wavelength(wavelength);
+        return emitted + attenuation * ray_color(&scattered, world, depth - 1, sampler, None);
+    }
+
+    // half of the rays go where the material sends them and half towards a light,
+    // weighting by the mixture keeps either kind from adding noise
+    let towards_light = HittablePdf::new(world.lights, record.point);
+    let material_pdf = MaterialPdf::new(material, ray, &record);
+    let mixture = MixturePdf::new(&towards_light, &material_pdf);
+    let direction = match sampler.get_1d() < 0.5 {
+        true => towards_light.generate(sampler),
+        // the material already picked one, light sampling still counts when it's blocked
+        false => (!scatter.is_blocked()).then(|| scatter.ray.direction()),
+    };
+    let Some(direction) = direction else {
+        return emitted;
+    };
+    let pdf = mixture.value(direction);
+    if pdf <= 0.0 {
+        return emitted;
+    }
+    let scattered = Ray::new(record.point, direction)
+        .with_time(ray.time())
+        .with_wavelength(wavelength);
+    let weight = at_wavelength(material.bsdf_cosine(ray, &record, direction), wavelength) / pdf;
+    emitted + weight * ray_color(&scattered, world, depth - 1, sampler, None)
+}
+
+/// renders one scanline of the image (row 0 is the bottom one) into `line` and the
+/// number of samples every pixel took into `counts`, colors are averaged over samples
+/// but not tone mapped. Spectral rendering gives every sample a wavelength of its own.
+/// AOVs go to `aov_line`, which is empty when none are written.
+#[allow(clippy::too_many_arguments)]
+fn render_row<T: Hittable>(
+    row: i32,
+    line: &mut [Color],
+    counts: &mut [u32],
+    aov_line: &mut [AovPixel],
+    cam: &Camera,
+    world: &World<'_, T>,
+    image_height_pixels: i32,
+    max_depth: u16,
+    adaptive: &AdaptiveSampling,
+    spectral: bool,
+    sampler: &mut dyn Sampler,
+) {
+    let image_width_pixels = line.len();
+    for (j, (pixel, count)) in line.iter_mut().zip(counts).enumerate() {
+        let mut estimate = PixelEstimate::default();
+        while !adaptive.is_done(&estimate) {
+            sampler.start_pixel_sample((j as u32, row as u32), estimate.count());
+            let (dx, dy) = sampler.get_2d();
+            let u = (j as f64 + dx) / (image_width_pixels as f64 - 1.0);
+            let v = (row as f64 + dy) / (image_height_pixels as f64 - 1.0);
+            let r = cam.get_ray(u, v, sampler);
+            let mut first_hit = AovSample::default();
+            let aov = aov_line.get_mut(j);
+            let first_hit_wanted = aov.is_some().then_some(&mut first_hit);
+            if !spectral {
+                estimate.add(ray_color(&r, world, max_depth, sampler, first_hit_wanted));
+            } else {
+                let (wavelength, pdf) = sample_wavelength(sampler.get_1d());
+                let r = r.with_wavelength(Some(wavelength));
+                let value = ray_color(&r, world, max_depth, sampler, first_hit_wanted).x();
+                estimate.add(wavelength_to_srgb(value, wavelength, pdf));
+            }
+            if let Some(aov) = aov {
+                aov.add(&first_hit);
+            }
         }
+        *pixel = estimate.mean();
+        *count = estimate.count();
+    }
+}
+
+/// the image and what was found while rendering it
+struct Rendered {
+    framebuffer: Framebuffer,
+    /// samples every pixel took, in the order of the framebuffer
+    sample_counts: Vec<u32>,
+    /// rows of AOV pixels top to bottom, empty rows when no AOVs are written
+    aov_rows: Vec<Vec<AovPixel>>,
+}
+
+/// Renders the rows in parallel on the current thread pool. Every row builds its own
+/// sampler from the seed, so the image is the same with any number of threads.
+fn render<T: Hittable>(
+    args: &Args,
+    cam: &Camera,
+    world: &World<'_, T>,
+    adaptive: &AdaptiveSampling,
+    seed: u64,
+    image_width_pixels: i32,
+    image_height_pixels: i32,
+) -> Rendered {
+    // Rows of the framebuffer go top to bottom, camera rows go bottom to top.
+    // Rows are rendered in parallel, each thread writes only its own row.
+    let mut framebuffer =
+        Framebuffer::new(image_width_pixels as usize, image_height_pixels as usize);
+    let mut sample_counts = vec![0; framebuffer.pixels().len()];
+    let aov_width = match args.aovs.is_empty() {
+        true => 0,
+        false => image_width_pixels as usize,
+    };
+    let mut aov_rows = vec![vec![AovPixel::default(); aov_width]; image_height_pixels as usize];
+    let lines_left = AtomicUsize::new(image_height_pixels as usize);
+    framebuffer
+        .pixels_mut()
+        .par_chunks_mut(image_width_pixels as usize)
+        .zip(sample_counts.par_chunks_mut(image_width_pixels as usize))
+        .zip(aov_rows.par_iter_mut())
+        .enumerate()
+        .for_each(|(y, ((line, counts), aov_line))| {
+            let row = image_height_pixels - 1 - y as i32;
+            let mut sampler = args.sampler.build(args.spp, seed);
+            render_row(
+                row,
+                line,
+                counts,
+                aov_line,
+                cam,
+                world,
+                image_height_pixels,
+                args.max_depth,
+                adaptive,
+                args.spectral,
+                sampler.as_mut(),
+            );
+            eprintln!(
+                "Lines left: {}",
+                lines_left.fetch_sub(1, Ordering::Relaxed) - 1
+            );
+        });
+    Rendered {
+        framebuffer,
+        sample_counts,
+        aov_rows,
     }
 }
 
 fn main() {
-    let mut to_write = String::new();
+    let args = Args::parse();
+    let fail = |message: String| -> ! {
+        Args::command()
+            .error(clap::error::ErrorKind::ValueValidation, message)
+            .exit()
+    };
+
+    let format = match ImageFormat::from_path(&args.output) {
+        Some(ImageFormat::Png { .. }) => ImageFormat::Png {
+            sixteen_bit: args.png_16bit,
+        },
+        Some(format) => format,
+        None => fail(format!(
+            "can't tell image format of {}, expected .ppm, .png or .pfm",
+            args.output.display()
+        )),
+    };
+    let heatmap = args
+        .heatmap
+        .as_ref()
+        .map(|path| match ImageFormat::from_path(path) {
+            Some(format) => (path, format),
+            None => fail(format!(
+                "can't tell image format of {}, expected .ppm, .png or .pfm",
+                path.display()
+            )),
+        });
+    // stream 0 builds the scene, samplers derive the numbers of every pixel sample from the seed
+    let seed = args
+        .seed
+        .unwrap_or_else(|| Rng::from_entropy().random_u64());
+    let mut scene_rng = Rng::for_stream(seed, 0);
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build_global()
+            .expect("thread pool is built only once");
+    }
 
-    let samples_per_pixel = 500;
-    let max_depth = 50;
-    let image_width_pixels = 1200;
+    let description = match &args.scene_file {
+        Some(path) => read_scene_file(path).unwrap_or_else(|e| fail(e)),
+        None => args.scene.description(&mut scene_rng),
+    };
+    if let Some(path) = &args.export_scene {
+        if let Err(err) = write_scene_file(path, &description) {
+            eprintln!("Couldn't write {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+        eprintln!("Scene saved to {}", path.display());
+        return;
+    }
+
+    // World
+    let scene = description
+        .build(&mut scene_rng)
+        .unwrap_or_else(|e| fail(e));
+    let objects = BvhNode::new(&scene.world, SplitHeuristic::Sah);
+    let material_ids = MaterialIds::new(&scene.materials);
+    let world = World {
+        objects: &objects,
+        lights: &scene.lights,
+        environment: scene.environment.as_ref(),
+        material_ids: &material_ids,
+    };
 
     // Image
-    let cam = Camera::new(
-        30.0,
-        3.0 / 2.0,
-        1.0,
-        Point3::new(13.0, 2.0, 3.0),
-        samples_per_pixel,
-        Point3::new(0.0, 0.0, 0.0),
-        Vec3::new(0.0, 1.0, 0.0),
-        0.1,
-        10.0,
-    );
+    let camera_settings = args
+        .camera_settings(scene.camera)
+        .unwrap_or_else(|e| fail(e));
+    let cam = camera_settings.build(args.spp);
+    let image_width_pixels = args.width as i32;
+    let image_height_pixels = args.height(cam.aspect_ratio()) as i32;
+    let adaptive = match args.adaptive_threshold {
+        Some(threshold) => AdaptiveSampling::new(args.min_spp, args.spp, threshold),
+        None => AdaptiveSampling::fixed(args.spp),
+    };
 
-    let image_height_pixels = (f64::from(image_width_pixels) / cam.aspect_ratio()).round() as i32;
+    let Rendered {
+        framebuffer,
+        sample_counts,
+        aov_rows,
+    } = render(
+        &args,
+        &cam,
+        &world,
+        &adaptive,
+        seed,
+        image_width_pixels,
+        image_height_pixels,
+    );
 
-    // World
-    let world = generate_scene();
-
-    to_write.push_str(&format!(
-        "P3\n{} {}\n255\n",
-        image_width_pixels, image_height_pixels
-    ));
-    for i in (0..image_height_pixels).rev() {
-        eprintln!("Lines left: {}", i + 1);
-        for j in 0..image_width_pixels {
-            let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-            for _ in 0..cam.samples_per_pixel() {
-                let u = (j as f64 + random_f64()) / (image_width_pixels as f64 - 1.0);
-                let v = (i as f64 + random_f64()) / (image_height_pixels as f64 - 1.0);
-                let mut r = cam.get_ray(u, v);
-                pixel_color += ray_color(&mut r, &world, max_depth);
-            }
-            to_write.push_str(&generate_color(pixel_color, cam.samples_per_pixel()));
+    if let Err(err) = write_image_as(&args.output, &framebuffer, format) {
+        eprintln!("Couldn't write {}: {}", args.output.display(), err);
+        std::process::exit(1);
+    }
+    let total_samples: u64 = sample_counts.iter().map(|&count| u64::from(count)).sum();
+    eprintln!(
+        "Average samples per pixel: {:.1}",
+        total_samples as f64 / sample_counts.len() as f64
+    );
+    for aov in &args.aovs {
+        let mut buffer = Framebuffer::new(framebuffer.width(), framebuffer.height());
+        let pixels = aov_rows.iter().flatten();
+        for (pixel, aov_pixel) in buffer.pixels_mut().iter_mut().zip(pixels) {
+            *pixel = aov_pixel.value(*aov);
+        }
+        let path = aov.path_next_to(&args.output);
+        if let Err(err) = write_image_as(&path, &buffer, ImageFormat::Pfm) {
+            eprintln!("Couldn't write {}: {}", path.display(), err);
+            std::process::exit(1);
+        }
+    }
+    if let Some((path, format)) = heatmap {
+        let mut heat = Framebuffer::new(framebuffer.width(), framebuffer.height());
+        for (pixel, &count) in heat.pixels_mut().iter_mut().zip(&sample_counts) {
+            *pixel = heatmap_color(count, adaptive.max_samples());
+        }
+        if let Err(err) = write_display_image_as(path, &heat, format) {
+            eprintln!("Couldn't write {}: {}", path.display(), err);
+            std::process::exit(1);
         }
     }
-    println!("{}", to_write);
     eprintln!("Done!");
 }
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ray_tracing_rust::aov::Aov;
+    use ray_tracing_rust::sampler::IndependentSampler;
+    use ray_tracing_rust::scene::Scene;
+    use ray_tracing_rust::scene_file::parse_scene;
+
+    /// the scene of the text with a BVH around it, the world borrows from both
+    fn build(text: &str) -> (Scene, BvhNode, MaterialIds) {
+        let scene = parse_scene(text).unwrap().build(&mut Rng::new(1)).unwrap();
+        let objects = BvhNode::new(&scene.world, SplitHeuristic::Sah);
+        let material_ids = MaterialIds::new(&scene.materials);
+        (scene, objects, material_ids)
+    }
+
+    fn world<'a>(
+        (scene, objects, material_ids): &'a (Scene, BvhNode, MaterialIds),
+    ) -> World<'a, BvhNode> {
+        World {
+            objects,
+            lights: &scene.lights,
+            environment: scene.environment.as_ref(),
+            material_ids,
+        }
+    }
+
+    #[test]
+    fn lights_and_the_background_add_light() {
+        let built = build(
+            "background color=0.1,0.2,0.3\n\
+             material lamp diffuse_light color=2,3,4\n\
+             material soot lambertian color=0,0,0\n\
+             sphere center=0,0,-2 radius=1 material=lamp\n\
+             sphere center=0,0,2 radius=1 material=soot",
+        );
+        let world = world(&built);
+        let mut sampler = IndependentSampler::new(3);
+        let mut trace = |direction| {
+            let ray = Ray::new(Point3::default(), direction);
+            ray_color(&ray, &world, 10, &mut sampler, None)
+        };
+        assert_eq!(trace(Vec3::new(0.0, 0.0, -1.0)), Color::new(2.0, 3.0, 4.0));
+        assert_eq!(trace(Vec3::new(0.0, 1.0, 0.0)), Color::new(0.1, 0.2, 0.3));
+        // black surfaces reflect nothing of the lamp or the background
+        assert_eq!(trace(Vec3::new(0.0, 0.0, 1.0)), Color::default());
+    }
+
+    #[test]
+    fn images_do_not_depend_on_the_threads() {
+        let built = build(
+            "camera look_from=0,1,-4 look_at=0,0.5,0 vfov=40\n\
+             material ground lambertian color=0.5,0.5,0.5\n\
+             material glass dielectric refraction_index=1.5\n\
+             material lamp diffuse_light color=4,4,4\n\
+             material smoke isotropic color=0.8,0.8,0.8\n\
+             sphere center=0,-100,0 radius=100 material=ground\n\
+             sphere center=-0.6,0.5,0 radius=0.5 material=glass\n\
+             medium density=2 material=smoke sphere center=0.6,0.5,0 radius=0.5\n\
+             quad corner=-1,3,-1 u=2,0,0 v=0,0,2 material=lamp",
+        );
+        let world = world(&built);
+        let args = Args::try_parse_from(["test", "--spp", "4", "--max-depth", "8"]).unwrap();
+        let cam = built.0.camera.build(args.spp);
+        let adaptive = AdaptiveSampling::fixed(args.spp);
+        let render_with = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let rendered = pool.install(|| render(&args, &cam, &world, &adaptive, 7, 16, 12));
+            rendered.framebuffer.pixels().to_vec()
+        };
+        let pixels = render_with(1);
+        assert!(pixels.iter().any(|&pixel| pixel != Color::default()));
+        assert_eq!(pixels, render_with(3));
+
+        // the outputs come from the paths of the image and leave it as it is
+        let args = Args::try_parse_from([
+            "test",
+            "--spp",
+            "4",
+            "--max-depth",
+            "8",
+            "--aov",
+            "object-id",
+        ])
+        .unwrap();
+        let rendered = render(&args, &cam, &world, &adaptive, 7, 16, 12);
+        assert_eq!(rendered.framebuffer.pixels(), pixels);
+        let ids: Vec<_> = rendered
+            .aov_rows
+            .iter()
+            .flatten()
+            .map(|pixel| pixel.value(Aov::ObjectId).x())
+            .collect();
+        assert!(ids.contains(&0.0) && ids.contains(&1.0) && ids.contains(&2.0));
+    }
+}
@@ -1,68 +1,355 @@
 use crate::{
-    number_stuff::{random_f64, random_in_unit_sphere, random_unit_vector},
-    vec3::{dot, reflect, refract, unit_vector},
+    microfacet::{
+        dielectric, dielectric_pdf, fresnel_complex_color, fresnel_schlick, reflection,
+        reflection_pdf, roughness_to_alpha, sample_dielectric, sample_reflection, TrowbridgeReitz,
+    },
+    pdf::{CosinePdf, Pdf, SpherePdf},
+    sampler::Sampler,
+    spectrum::Dispersion,
+    texture::Texture,
+    vec3::{dot, reflect, refract, unit_vector, Onb, Vec3},
     Color, HitRecord, Ray,
 };
+use std::sync::Arc;
 
-pub trait Material {
-    fn project_ray(&self, ray: &mut Ray, record: &HitRecord) -> Color;
+/// where a ray goes after hitting a material and how much of its light is kept
+#[derive(Debug)]
+pub struct ScatterRecord {
+    pub ray: Ray,
+    /// what the light coming back along `ray` gets multiplied by
+    pub attenuation: Color,
+    /// Density of the direction of `ray` over the sphere of directions. None for specular
+    /// scattering like mirrors and glass, where only that one direction is possible.
+    pub pdf: Option<f64>,
 }
 
+impl ScatterRecord {
+    /// Nothing comes back along the ray, like when a rough surface sends it below
+    /// itself. Unlike an absorber the surface still reflects light coming from elsewhere.
+    pub fn blocked(ray: Ray) -> Self {
+        Self {
+            ray,
+            attenuation: Color::default(),
+            pdf: Some(0.0),
+        }
+    }
+
+    pub fn is_specular(&self) -> bool {
+        self.pdf.is_none()
+    }
+
+    pub fn is_blocked(&self) -> bool {
+        self.pdf == Some(0.0)
+    }
+}
+
+pub trait Material: Send + Sync {
+    /// None if the material absorbs every ray, like lights
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord<'_>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord>;
+
+    /// Density of `scatter` picking the direction, so other directions can be weighted
+    /// like the ones the material picks. Only asked when the scatter isn't specular.
+    fn scattering_pdf(&self, _ray: &Ray, _record: &HitRecord<'_>, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    /// What the light coming back along direction gets multiplied by on its way out
+    /// along the ray, the BSDF times the cosine to the normal. Divided by
+    /// `scattering_pdf` it's the attenuation of a scatter in that direction.
+    /// Only asked when the scatter isn't specular.
+    fn bsdf_cosine(&self, _ray: &Ray, _record: &HitRecord<'_>, _direction: Vec3) -> Color {
+        Color::default()
+    }
+
+    /// light given off by the surface at the hit point, black for anything but lights
+    fn emitted(&self, _record: &HitRecord<'_>) -> Color {
+        Color::default()
+    }
+
+    /// Color of the surface at the hit point, without lighting, for the albedo output
+    /// that denoisers and compositing use. Black if the material has none.
+    fn albedo(&self, _record: &HitRecord<'_>) -> Color {
+        Color::default()
+    }
+}
+
+/// takes a `Color` or any texture
 pub struct Lambertian {
-    color: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 impl Lambertian {
-    pub fn new(color: Color) -> Self {
-        Self { color }
+    pub fn new(albedo: impl Into<Arc<dyn Texture>>) -> Self {
+        Self {
+            albedo: albedo.into(),
+        }
     }
 }
 
 impl Material for Lambertian {
-    fn project_ray<'a>(&self, ray: &mut Ray, record: &HitRecord) -> Color {
-        let mut projected_direction = record.normal + random_unit_vector();
-        if projected_direction.really_small() {
-            projected_direction = record.normal;
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord<'_>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        // cosine weighted, so the cosine of the rendering equation cancels out
+        let pdf = CosinePdf::new(record.normal);
+        let direction = pdf.generate(sampler)?;
+        Some(ScatterRecord {
+            ray: Ray::new(record.point, direction).with_time(ray.time()),
+            attenuation: self.albedo.value(record.uv, record.point),
+            pdf: Some(pdf.value(direction)),
+        })
+    }
+
+    fn scattering_pdf(&self, _ray: &Ray, record: &HitRecord<'_>, direction: Vec3) -> f64 {
+        CosinePdf::new(record.normal).value(direction)
+    }
+
+    fn bsdf_cosine(&self, _ray: &Ray, record: &HitRecord<'_>, direction: Vec3) -> Color {
+        let pdf = CosinePdf::new(record.normal).value(direction);
+        pdf * self.albedo.value(record.uv, record.point)
+    }
+
+    fn albedo(&self, record: &HitRecord<'_>) -> Color {
+        self.albedo.value(record.uv, record.point)
+    }
+}
+
+/// Rough reflection off GGX microfacets seen from the ray, with the normal of the hit
+/// along z. Anisotropy goes along the tangent of the hit, see `HitRecord::tangent`.
+struct Microfacet<'a> {
+    distribution: TrowbridgeReitz,
+    frame: Onb,
+    /// the direction back to where the ray came from, in the local frame
+    wo: Vec3,
+    ray: &'a Ray,
+    record: &'a HitRecord<'a>,
+}
+
+impl<'a> Microfacet<'a> {
+    fn new(distribution: TrowbridgeReitz, ray: &'a Ray, record: &'a HitRecord<'a>) -> Self {
+        let frame = Onb::with_tangent(record.normal, record.tangent);
+        Self {
+            distribution,
+            frame,
+            wo: frame.to_local(-unit_vector(ray.direction())),
+            ray,
+            record,
         }
-        *ray = Ray::new(record.point, projected_direction);
-        self.color
+    }
+
+    /// Perfect mirror for smooth surfaces, a direction picked from the visible normals
+    /// otherwise. Blocked if it goes below the surface.
+    fn scatter(&self, sampler: &mut dyn Sampler, fresnel: impl Fn(f64) -> Color) -> ScatterRecord {
+        let ray = |direction| Ray::new(self.record.point, direction).with_time(self.ray.time());
+        if self.distribution.effectively_smooth() {
+            let direction = reflect(&unit_vector(self.ray.direction()), &self.record.normal);
+            return ScatterRecord {
+                ray: ray(direction),
+                attenuation: fresnel(self.wo.z()),
+                pdf: None,
+            };
+        }
+        let wi = sample_reflection(&self.distribution, self.wo, sampler.get_2d());
+        let pdf = reflection_pdf(&self.distribution, self.wo, wi);
+        if pdf <= 0.0 {
+            return ScatterRecord::blocked(ray(self.frame.local(wi)));
+        }
+        ScatterRecord {
+            ray: ray(self.frame.local(wi)),
+            attenuation: reflection(&self.distribution, self.wo, wi, fresnel) / pdf,
+            pdf: Some(pdf),
+        }
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let wi = self.frame.to_local(unit_vector(direction));
+        reflection_pdf(&self.distribution, self.wo, wi)
+    }
+
+    fn bsdf_cosine(&self, direction: Vec3, fresnel: impl Fn(f64) -> Color) -> Color {
+        let wi = self.frame.to_local(unit_vector(direction));
+        reflection(&self.distribution, self.wo, wi, fresnel)
     }
 }
 
+/// Takes a `Color` or any texture, which is the reflectance head on. Fuzziness is the
+/// roughness of GGX microfacets, see `Conductor`.
 pub struct Metal {
-    color: Color,
+    albedo: Arc<dyn Texture>,
     fuzziness: f64,
+    distribution: TrowbridgeReitz,
 }
 
 impl Metal {
-    pub fn new(color: Color, fuzziness: f64) -> Self {
+    pub fn new(albedo: impl Into<Arc<dyn Texture>>, fuzziness: f64) -> Self {
+        Self::try_new(albedo, fuzziness).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// same as `new` but returns an error instead of panicking on bad fuzziness
+    pub fn try_new(albedo: impl Into<Arc<dyn Texture>>, fuzziness: f64) -> Result<Self, String> {
         if !(0.0..=1.0).contains(&fuzziness) {
-            panic!(
+            return Err(format!(
                 "fuzziness should be in [0.0, 1.0] range but was given: {}",
                 fuzziness
-            );
+            ));
         }
-        Self { color, fuzziness }
+        let (alpha_x, alpha_y) = roughness_to_alpha(fuzziness, 0.0);
+        Ok(Self {
+            albedo: albedo.into(),
+            fuzziness,
+            distribution: TrowbridgeReitz::new(alpha_x, alpha_y),
+        })
+    }
+
+    pub fn fuzziness(&self) -> f64 {
+        self.fuzziness
     }
 }
 
 impl Material for Metal {
-    fn project_ray(&self, ray: &mut Ray, record: &HitRecord) -> Color {
-        *ray = Ray::new(
-            record.point,
-            reflect(&ray.direction(), &record.normal) + self.fuzziness * random_in_unit_sphere(),
-        );
-        self.color
+    /// absorbs the rays that a rough surface sends below it
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord<'_>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let f0 = self.albedo.value(record.uv, record.point);
+        let microfacet = Microfacet::new(self.distribution, ray, record);
+        Some(microfacet.scatter(sampler, |cosine| fresnel_schlick(cosine, f0)))
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, record: &HitRecord<'_>, direction: Vec3) -> f64 {
+        Microfacet::new(self.distribution, ray, record).pdf(direction)
+    }
+
+    fn bsdf_cosine(&self, ray: &Ray, record: &HitRecord<'_>, direction: Vec3) -> Color {
+        let f0 = self.albedo.value(record.uv, record.point);
+        Microfacet::new(self.distribution, ray, record)
+            .bsdf_cosine(direction, |cosine| fresnel_schlick(cosine, f0))
+    }
+
+    fn albedo(&self, record: &HitRecord<'_>) -> Color {
+        self.albedo.value(record.uv, record.point)
     }
 }
 
+/// Metal with a complex index of refraction eta + k i for every channel, like measured
+/// ones: gold is about eta = 0.143,0.374,1.442 and k = 3.983,2.385,1.603. Rough surfaces
+/// are GGX microfacets, anisotropy stretches the highlight like brushed metal along the
+/// direction in which the u texture coordinate grows.
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: TrowbridgeReitz,
+}
+
+impl Conductor {
+    /// roughness and anisotropy should be in [0.0, 1.0]
+    pub fn new(eta: Color, k: Color, roughness: f64, anisotropy: f64) -> Self {
+        let (alpha_x, alpha_y) = roughness_to_alpha(roughness, anisotropy);
+        Self {
+            eta,
+            k,
+            distribution: TrowbridgeReitz::new(alpha_x, alpha_y),
+        }
+    }
+}
+
+impl Material for Conductor {
+    /// absorbs the rays that a rough surface sends below it
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord<'_>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let microfacet = Microfacet::new(self.distribution, ray, record);
+        Some(microfacet.scatter(sampler, |cosine| {
+            fresnel_complex_color(cosine, self.eta, self.k)
+        }))
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, record: &HitRecord<'_>, direction: Vec3) -> f64 {
+        Microfacet::new(self.distribution, ray, record).pdf(direction)
+    }
+
+    fn bsdf_cosine(&self, ray: &Ray, record: &HitRecord<'_>, direction: Vec3) -> Color {
+        Microfacet::new(self.distribution, ray, record).bsdf_cosine(direction, |cosine| {
+            fresnel_complex_color(cosine, self.eta, self.k)
+        })
+    }
+
+    /// reflectance head on
+    fn albedo(&self, _record: &HitRecord<'_>) -> Color {
+        fresnel_complex_color(1.0, self.eta, self.k)
+    }
+}
+
+/// Glass and water. Roughness from 0 to 1 frosts it with GGX microfacets. Tinted glass
+/// keeps `tint` of the light after it goes `tint_distance` through the inside, less for
+/// longer paths (Beer-Lambert), which takes closed objects whose normals point out.
+/// Thin walled glass like windows and bubbles has no inside: light goes straight
+/// through, tinted once. With a `Dispersion` the index depends on the wavelength of
+/// the ray, rays without one use refraction_index.
 pub struct Dielectric {
     refraction_index: f64,
+    dispersion: Option<Dispersion>,
+    distribution: TrowbridgeReitz,
+    tint: Color,
+    /// fraction of the light absorbed per unit of length for each channel
+    absorption: Color,
+    thin_walled: bool,
 }
 
 impl Dielectric {
+    /// smooth clear glass
     pub fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+        Self {
+            refraction_index,
+            dispersion: None,
+            distribution: TrowbridgeReitz::new(0.0, 0.0),
+            tint: Color::new(1.0, 1.0, 1.0),
+            absorption: Color::default(),
+            thin_walled: false,
+        }
+    }
+
+    /// roughness should be in [0.0, 1.0], thin walled glass stays smooth
+    pub fn with_roughness(mut self, roughness: f64) -> Self {
+        let (alpha_x, alpha_y) = roughness_to_alpha(roughness, 0.0);
+        self.distribution = TrowbridgeReitz::new(alpha_x, alpha_y);
+        self
+    }
+
+    /// tint channels should be in (0.0, 1.0] and the distance positive
+    pub fn with_tint(mut self, tint: Color, distance: f64) -> Self {
+        let coefficient = |t: f64| -t.max(1e-6).ln() / distance;
+        self.tint = tint;
+        self.absorption = Color::new(
+            coefficient(tint.x()),
+            coefficient(tint.y()),
+            coefficient(tint.z()),
+        );
+        self
+    }
+
+    pub fn with_dispersion(mut self, dispersion: Option<Dispersion>) -> Self {
+        self.dispersion = dispersion;
+        self
+    }
+
+    pub fn with_thin_walled(mut self, thin_walled: bool) -> Self {
+        self.thin_walled = thin_walled;
+        self
     }
 
     pub fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
@@ -70,25 +357,298 @@ impl Dielectric {
         r0 *= r0;
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
     }
+
+    /// Light left after the path from the last hit to this one. Only a ray hitting the
+    /// surface from the inside went through the glass.
+    fn transmittance(&self, ray: &Ray, record: &HitRecord<'_>) -> Color {
+        if record.outside_ray || self.thin_walled {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        let distance = record.t * ray.direction().length();
+        let channel = |absorption: f64| (-absorption * distance).exp();
+        Color::new(
+            channel(self.absorption.x()),
+            channel(self.absorption.y()),
+            channel(self.absorption.z()),
+        )
+    }
+
+    /// index of refraction on the other side of the surface over the one on the ray side
+    fn relative_index(&self, ray: &Ray, record: &HitRecord<'_>) -> f64 {
+        match record.outside_ray {
+            true => self.index(ray),
+            false => 1.0 / self.index(ray),
+        }
+    }
+
+    fn index(&self, ray: &Ray) -> f64 {
+        match (self.dispersion, ray.wavelength()) {
+            (Some(dispersion), Some(wavelength)) => dispersion.index(wavelength),
+            _ => self.refraction_index,
+        }
+    }
+
+    /// Reflects with the reflectance of both sides together, light bouncing between
+    /// them included, and lets the rest through without bending it.
+    fn scatter_thin(
+        &self,
+        ray: &Ray,
+        record: &HitRecord<'_>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let unit_direction = unit_vector(ray.direction());
+        let cos_theta = dot(-unit_direction, record.normal).min(1.0);
+        let mut r = Dielectric::reflectance(cos_theta, 1.0 / self.index(ray));
+        r += (1.0 - r) * (1.0 - r) * r / (1.0 - r * r);
+        let (direction, attenuation) = match r > sampler.get_1d() {
+            true => (
+                reflect(&unit_direction, &record.normal),
+                Color::new(1.0, 1.0, 1.0),
+            ),
+            false => (unit_direction, self.tint),
+        };
+        Some(ScatterRecord {
+            ray: Ray::new(record.point, direction).with_time(ray.time()),
+            attenuation,
+            pdf: None,
+        })
+    }
 }
 
 impl Material for Dielectric {
-    fn project_ray(&self, ray: &mut Ray, record: &HitRecord) -> Color {
-        let refraction_ratio = match record.outside_ray {
-            true => 1.0 / self.refraction_index,
-            false => self.refraction_index,
-        };
+    /// rays that rough glass facets send to the wrong side are blocked
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord<'_>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        if self.thin_walled {
+            return self.scatter_thin(ray, record, sampler);
+        }
+        let transmittance = self.transmittance(ray, record);
+        if !self.distribution.effectively_smooth() {
+            let frame = Onb::new(record.normal);
+            let wo = frame.to_local(-unit_vector(ray.direction()));
+            let eta = self.relative_index(ray, record);
+            let choice = sampler.get_1d();
+            let wi = sample_dielectric(&self.distribution, wo, eta, choice, sampler.get_2d());
+            let pdf = wi.map_or(0.0, |wi| dielectric_pdf(&self.distribution, wo, wi, eta));
+            let (Some(wi), true) = (wi, pdf > 0.0) else {
+                return Some(ScatterRecord::blocked(
+                    ray.moved(record.point, ray.direction()),
+                ));
+            };
+            let f = dielectric(&self.distribution, wo, wi, eta);
+            return Some(ScatterRecord {
+                ray: Ray::new(record.point, frame.local(wi)).with_time(ray.time()),
+                attenuation: f / pdf * transmittance,
+                pdf: Some(pdf),
+            });
+        }
+        let refraction_ratio = 1.0 / self.relative_index(ray, record);
         let unit_direction = unit_vector(ray.direction());
         let cos_theta = dot(-unit_direction, record.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
         let schlick_approximation =
-            Dielectric::reflectance(cos_theta, refraction_ratio) > random_f64();
+            Dielectric::reflectance(cos_theta, refraction_ratio) > sampler.get_1d();
         let direction = match cannot_refract || schlick_approximation {
             true => reflect(&unit_direction, &record.normal),
             false => refract(&unit_direction, &record.normal, refraction_ratio),
         };
-        *ray = Ray::new(record.point, direction);
-        Color::new(1.0, 1.0, 1.0) // white
+        Some(ScatterRecord {
+            ray: Ray::new(record.point, direction).with_time(ray.time()),
+            attenuation: transmittance,
+            pdf: None,
+        })
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, record: &HitRecord<'_>, direction: Vec3) -> f64 {
+        let frame = Onb::new(record.normal);
+        let wo = frame.to_local(-unit_vector(ray.direction()));
+        let wi = frame.to_local(unit_vector(direction));
+        dielectric_pdf(&self.distribution, wo, wi, self.relative_index(ray, record))
+    }
+
+    fn bsdf_cosine(&self, ray: &Ray, record: &HitRecord<'_>, direction: Vec3) -> Color {
+        let frame = Onb::new(record.normal);
+        let wo = frame.to_local(-unit_vector(ray.direction()));
+        let wi = frame.to_local(unit_vector(direction));
+        let f = dielectric(&self.distribution, wo, wi, self.relative_index(ray, record));
+        f * self.transmittance(ray, record)
+    }
+
+    fn albedo(&self, _record: &HitRecord<'_>) -> Color {
+        self.tint
+    }
+}
+
+/// Emits light of the given color or texture from both sides and doesn't reflect anything.
+/// Color can go above 1.0 for bright lights.
+pub struct DiffuseLight {
+    emit: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: impl Into<Arc<dyn Texture>>) -> Self {
+        Self { emit: emit.into() }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray: &Ray,
+        _record: &HitRecord<'_>,
+        _sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        None // absorbs everything
+    }
+
+    fn emitted(&self, record: &HitRecord<'_>) -> Color {
+        self.emit.value(record.uv, record.point)
+    }
+
+    fn albedo(&self, record: &HitRecord<'_>) -> Color {
+        self.emit.value(record.uv, record.point)
+    }
+}
+
+/// Scatters light the same way in every direction, the material of `ConstantMedium`
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: impl Into<Arc<dyn Texture>>) -> Self {
+        Self {
+            albedo: albedo.into(),
+        }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord<'_>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            ray: Ray::new(record.point, SpherePdf.generate(sampler)?).with_time(ray.time()),
+            attenuation: self.albedo.value(record.uv, record.point),
+            pdf: Some(SpherePdf.value(record.normal)),
+        })
+    }
+
+    fn scattering_pdf(&self, _ray: &Ray, _record: &HitRecord<'_>, direction: Vec3) -> f64 {
+        SpherePdf.value(direction)
+    }
+
+    fn bsdf_cosine(&self, _ray: &Ray, record: &HitRecord<'_>, direction: Vec3) -> Color {
+        SpherePdf.value(direction) * self.albedo.value(record.uv, record.point)
+    }
+
+    fn albedo(&self, record: &HitRecord<'_>) -> Color {
+        self.albedo.value(record.uv, record.point)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::Point3;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn scatter_records() {
+        let mut sampler = IndependentSampler::new(1);
+        let record = HitRecord {
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            outside_ray: true,
+            ..Default::default()
+        };
+        let ray = Ray::new(Point3::new(0.0, 1.0, -1.0), Vec3::new(0.0, -1.0, 1.0));
+        let gray = Color::new(0.5, 0.5, 0.5);
+
+        let diffuse = Lambertian::new(gray)
+            .scatter(&ray, &record, &mut sampler)
+            .unwrap();
+        let cosine = dot(unit_vector(diffuse.ray.direction()), record.normal);
+        assert_eq!(diffuse.attenuation, gray);
+        assert!((diffuse.pdf.unwrap() - cosine / PI).abs() < 1e-12);
+
+        let mirror = Metal::new(gray, 0.0)
+            .scatter(&ray, &record, &mut sampler)
+            .unwrap();
+        assert!(mirror.is_specular());
+        assert!((mirror.ray.direction() - unit_vector(Vec3::new(0.0, 1.0, 1.0))).really_small());
+
+        assert!(DiffuseLight::new(gray)
+            .scatter(&ray, &record, &mut sampler)
+            .is_none());
+
+        // rough metal seen at a grazing angle sends some rays below the surface, which
+        // blocks them without making it an absorber
+        let grazing = Ray::new(Point3::new(0.0, 1.0, -10.0), Vec3::new(0.0, -1.0, 10.0));
+        let rough = Metal::new(gray, 0.8);
+        let scatters: Vec<ScatterRecord> = (0..100)
+            .map(|_| rough.scatter(&grazing, &record, &mut sampler).unwrap())
+            .collect();
+        assert!(scatters.iter().any(|scatter| scatter.is_blocked()));
+        for scatter in scatters.iter().filter(|scatter| scatter.is_blocked()) {
+            assert!(!scatter.is_specular());
+            assert_eq!(scatter.attenuation, Color::default());
+        }
+    }
+
+    #[test]
+    fn tinted_and_thin_glass() {
+        let mut sampler = IndependentSampler::new(2);
+        // the tint is what is left after its distance, twice as far leaves its square
+        let bottle = Dielectric::new(1.5).with_tint(Color::new(0.5, 0.8, 1.0), 2.0);
+        let mut leaving = |distance: f64, outside_ray| {
+            let ray = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, 2.0));
+            let record = HitRecord {
+                point: ray.at(distance / 2.0),
+                normal: Vec3::new(0.0, 0.0, -1.0),
+                t: distance / 2.0,
+                outside_ray,
+                ..Default::default()
+            };
+            let scatter = bottle.scatter(&ray, &record, &mut sampler).unwrap();
+            scatter.attenuation
+        };
+        assert!((leaving(2.0, false) - Color::new(0.5, 0.8, 1.0)).really_small());
+        assert!((leaving(4.0, false) - Color::new(0.25, 0.64, 1.0)).really_small());
+        // coming from outside the ray wasn't in the glass
+        assert_eq!(leaving(4.0, true), Color::new(1.0, 1.0, 1.0));
+
+        let window = Dielectric::new(1.5)
+            .with_thin_walled(true)
+            .with_tint(Color::new(0.9, 0.9, 0.9), 1.0);
+        let record = HitRecord {
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            outside_ray: true,
+            ..Default::default()
+        };
+        let ray = Ray::new(Point3::new(0.0, 1.0, -1.0), Vec3::new(0.0, -1.0, 1.0));
+        let mut passed = 0;
+        for _ in 0..100 {
+            let scatter = window.scatter(&ray, &record, &mut sampler).unwrap();
+            let direction = scatter.ray.direction();
+            if (direction - unit_vector(ray.direction())).really_small() {
+                assert_eq!(scatter.attenuation, Color::new(0.9, 0.9, 0.9));
+                passed += 1;
+            } else {
+                assert!((direction - unit_vector(Vec3::new(0.0, 1.0, 1.0))).really_small());
+            }
+        }
+        // light passes straight through most of the time
+        assert!(passed > 50, "{}", passed);
     }
 }
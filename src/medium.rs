@@ -0,0 +1,111 @@
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::number_stuff::hash_to_unit;
+use crate::ray::*;
+use crate::ray_trace::*;
+use crate::vec3::*;
+use std::sync::Arc;
+
+/// Fog, smoke or anything else that scatters light inside a closed boundary.
+/// A ray going through it scatters with the same probability along every unit of length,
+/// the higher the density the sooner. Usually paired with the `Isotropic` material.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    phase_function: Arc<dyn Material>,
+}
+
+impl ConstantMedium {
+    /// boundary should be closed, like a sphere or a box
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        // the first boundary hit either enters the volume or, when the ray starts inside
+        // like the ones scattered by the medium itself, leaves it
+        let first = self.boundary.hit(r, t_min, f64::INFINITY)?;
+        let (enter, exit) = if first.outside_ray {
+            let exit = self.boundary.hit(r, first.t + 1e-4, f64::INFINITY)?;
+            (first.t, exit.t)
+        } else {
+            (t_min, first.t)
+        };
+        let exit = exit.min(t_max);
+        if enter >= exit {
+            return None;
+        }
+
+        let ray_length = r.direction().length();
+        let distance_inside = (exit - enter) * ray_length;
+        // the number comes from the sampler, mixing in where the ray enters keeps media
+        // behind each other from letting the same rays through
+        let random = hash_to_unit(&[r.free_flight(), enter]);
+        let hit_distance = self.neg_inv_density * random.ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+        let t = enter + hit_distance / ray_length;
+        Some(HitRecord {
+            point: r.at(t),
+            // a point inside the volume has no surface, the normal doesn't matter
+            normal: Vec3::new(1.0, 0.0, 0.0),
+            t,
+            material_reference: Some(self.phase_function.as_ref()),
+            outside_ray: true,
+            ..Default::default()
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Isotropic;
+    use crate::sphere::Sphere;
+
+    #[test]
+    fn rays_scatter_inside_and_from_inside() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let phase: Arc<dyn Material> = Arc::new(Isotropic::new(white));
+        let sphere = Arc::new(Sphere::new(Point3::default(), 1.0, phase.clone()));
+        // so dense that rays scatter right after entering
+        let fog = ConstantMedium::new(sphere.clone(), 1e6, phase.clone());
+
+        let from_outside = Ray::new(Point3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let record = fog.hit(&from_outside, 0.001, f64::MAX).unwrap();
+        assert!((record.t - 4.0).abs() < 1e-3);
+
+        let from_inside = Ray::new(Point3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let record = fog.hit(&from_inside, 0.001, f64::MAX).unwrap();
+        assert!(record.t < 0.01);
+
+        // the sampler's number picks how far rays get into thin fog
+        let thin_fog = ConstantMedium::new(sphere.clone(), 0.5, phase.clone());
+        let depths: Vec<_> = [0.1, 0.2]
+            .into_iter()
+            .map(|u| thin_fog.hit(&from_inside.with_free_flight(u), 0.001, 100.0))
+            .map(|record| record.map_or(f64::MAX, |record| record.t))
+            .collect();
+        assert_ne!(depths[0], depths[1]);
+
+        let missing = Ray::new(Point3::new(0.0, 2.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(fog.hit(&missing, 0.001, f64::MAX).is_none());
+    }
+}
@@ -0,0 +1,333 @@
+//! rough surfaces as GGX microfacets, in a local frame with the normal along z
+
+use crate::vec3::*;
+use num::complex::Complex64;
+use std::f64::consts::PI;
+
+/// Roughness of 0 is a mirror and 1 is very rough. Anisotropy from 0 to 1 stretches the
+/// highlight along the local x axis, the way brushed metal does.
+pub fn roughness_to_alpha(roughness: f64, anisotropy: f64) -> (f64, f64) {
+    let alpha = roughness * roughness;
+    let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+    ((alpha / aspect).max(1e-4), (alpha * aspect).max(1e-4))
+}
+
+/// GGX distribution of microfacet normals with its Smith shadowing-masking
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrowbridgeReitz {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Self { alpha_x, alpha_y }
+    }
+
+    /// Alphas this small are drawn as perfect mirrors, the distribution is too spiky
+    /// to sample or evaluate well.
+    pub fn effectively_smooth(&self) -> bool {
+        self.alpha_x.max(self.alpha_y) < 1e-3
+    }
+
+    /// density of microfacet normals, projected area of the facets facing wm
+    pub fn d(&self, wm: Vec3) -> f64 {
+        let cos2_theta = wm.z() * wm.z();
+        let sin2_theta = (1.0 - cos2_theta).max(0.0);
+        if cos2_theta < 1e-8 {
+            return 0.0;
+        }
+        let tan2_theta = sin2_theta / cos2_theta;
+        let (cos_phi, sin_phi) = phi(wm);
+        let e = tan2_theta * ((cos_phi / self.alpha_x).powi(2) + (sin_phi / self.alpha_y).powi(2));
+        1.0 / (PI * self.alpha_x * self.alpha_y * cos2_theta * cos2_theta * (1.0 + e).powi(2))
+    }
+
+    /// Smith's auxiliary function, the area of facets hidden from w per visible area
+    fn lambda(&self, w: Vec3) -> f64 {
+        let cos2_theta = w.z() * w.z();
+        if cos2_theta < 1e-12 {
+            return f64::INFINITY;
+        }
+        let tan2_theta = (1.0 - cos2_theta).max(0.0) / cos2_theta;
+        let (cos_phi, sin_phi) = phi(w);
+        let alpha2 = (cos_phi * self.alpha_x).powi(2) + (sin_phi * self.alpha_y).powi(2);
+        ((1.0 + alpha2 * tan2_theta).sqrt() - 1.0) / 2.0
+    }
+
+    /// fraction of the facets visible from w
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// fraction of the facets visible from both wo and wi
+    pub fn g(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// density of the normals seen from w, which is what `sample_wm` picks
+    pub fn pdf(&self, w: Vec3, wm: Vec3) -> f64 {
+        if w.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(w) / w.z() * self.d(wm) * dot(w, wm).abs()
+    }
+
+    /// Normal of a facet visible from w, picked from two numbers in [0, 1) with Heitz's
+    /// sampling of visible normals. w should be above the surface.
+    pub fn sample_wm(&self, w: Vec3, (u, v): (f64, f64)) -> Vec3 {
+        // stretch to the distribution with alpha 1, where visible normals are a hemisphere
+        let wh = unit_vector(Vec3::new(self.alpha_x * w.x(), self.alpha_y * w.y(), w.z()));
+        let t1 = match wh.z() < 0.99999 {
+            true => unit_vector(cross(Vec3::new(0.0, 0.0, 1.0), wh)),
+            false => Vec3::new(1.0, 0.0, 0.0),
+        };
+        let t2 = cross(wh, t1);
+        // disk point squeezed to the part of the hemisphere that w sees
+        let (r, angle) = (u.sqrt(), 2.0 * PI * v);
+        let (px, py) = (r * angle.cos(), r * angle.sin());
+        let h = (1.0 - px * px).sqrt();
+        let s = (1.0 + wh.z()) / 2.0;
+        let py = (1.0 - s) * h + s * py;
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+        let nh = px * t1 + py * t2 + pz * wh;
+        unit_vector(Vec3::new(
+            self.alpha_x * nh.x(),
+            self.alpha_y * nh.y(),
+            nh.z().max(1e-6),
+        ))
+    }
+}
+
+/// cosine and sine of the angle around z
+fn phi(w: Vec3) -> (f64, f64) {
+    let sin_theta = (w.x() * w.x() + w.y() * w.y()).sqrt();
+    match sin_theta == 0.0 {
+        true => (1.0, 0.0),
+        false => (w.x() / sin_theta, w.y() / sin_theta),
+    }
+}
+
+/// Reflected part of unpolarized light at a surface with the complex index of refraction
+/// eta + k i, what conductors have. cos_theta is between the light and the normal.
+pub fn fresnel_complex(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos_theta = cos_theta.clamp(0.0, 1.0);
+    let eta = Complex64::new(eta, k);
+    let sin2_theta_t = (1.0 - cos_theta * cos_theta) / (eta * eta);
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    let parallel = (eta * cos_theta - cos_theta_t) / (eta * cos_theta + cos_theta_t);
+    let perpendicular = (cos_theta - eta * cos_theta_t) / (cos_theta + eta * cos_theta_t);
+    (parallel.norm_sqr() + perpendicular.norm_sqr()) / 2.0
+}
+
+/// `fresnel_complex` for every channel
+pub fn fresnel_complex_color(cos_theta: f64, eta: Color, k: Color) -> Color {
+    Color::new(
+        fresnel_complex(cos_theta, eta.x(), k.x()),
+        fresnel_complex(cos_theta, eta.y(), k.y()),
+        fresnel_complex(cos_theta, eta.z(), k.z()),
+    )
+}
+
+/// Schlick's approximation of the reflectance, f0 is the reflectance head on
+pub fn fresnel_schlick(cos_theta: f64, f0: Color) -> Color {
+    let weight = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + weight * (Color::new(1.0, 1.0, 1.0) - f0)
+}
+
+/// Cook-Torrance reflection off the visible facets, the BRDF times the cosine of wi.
+/// fresnel gets the cosine between wo and the facet normal.
+pub fn reflection(
+    distribution: &TrowbridgeReitz,
+    wo: Vec3,
+    wi: Vec3,
+    fresnel: impl Fn(f64) -> Color,
+) -> Color {
+    if wo.z() <= 0.0 || wi.z() <= 0.0 {
+        return Color::default();
+    }
+    let wm = unit_vector(wo + wi);
+    let f = distribution.d(wm) * distribution.g(wo, wi) / (4.0 * wo.z() * wi.z());
+    f * wi.z() * fresnel(dot(wo, wm))
+}
+
+/// density of `sample_reflection` picking wi
+pub fn reflection_pdf(distribution: &TrowbridgeReitz, wo: Vec3, wi: Vec3) -> f64 {
+    if wo.z() <= 0.0 || wi.z() <= 0.0 {
+        return 0.0;
+    }
+    let wm = unit_vector(wo + wi);
+    distribution.pdf(wo, wm) / (4.0 * dot(wo, wm).abs())
+}
+
+/// Mirrors wo on a visible facet, which can send it below the surface where
+/// `reflection` and `reflection_pdf` are 0
+pub fn sample_reflection(distribution: &TrowbridgeReitz, wo: Vec3, u: (f64, f64)) -> Vec3 {
+    let wm = distribution.sample_wm(wo, u);
+    -wo + 2.0 * dot(wo, wm) * wm
+}
+
+/// Reflected part of unpolarized light at the boundary of a dielectric, eta is the
+/// index of refraction below the surface over the one above it. A negative cos_theta
+/// means the light comes from below.
+pub fn fresnel_dielectric(cos_theta: f64, eta: f64) -> f64 {
+    let (cos_theta, eta) = match cos_theta < 0.0 {
+        true => ((-cos_theta).min(1.0), 1.0 / eta),
+        false => (cos_theta.min(1.0), eta),
+    };
+    let sin2_theta_t = (1.0 - cos_theta * cos_theta) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0; // total internal reflection
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    let parallel = (eta * cos_theta - cos_theta_t) / (eta * cos_theta + cos_theta_t);
+    let perpendicular = (cos_theta - eta * cos_theta_t) / (cos_theta + eta * cos_theta_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+/// Normal of the facet that sends wo to wi through a dielectric with relative index
+/// eta, facing up. None when no facet does, or when it's seen from behind.
+fn dielectric_normal(wo: Vec3, wi: Vec3, eta: f64) -> Option<(Vec3, f64)> {
+    let etap = match wo.z() * wi.z() > 0.0 {
+        true => 1.0,
+        false => eta,
+    };
+    let wm = wi * etap + wo;
+    if wo.z() <= 0.0 || wi.z() == 0.0 || wm.length_squared() == 0.0 {
+        return None;
+    }
+    let wm = unit_vector(wm);
+    let wm = match wm.z() < 0.0 {
+        true => -wm,
+        false => wm,
+    };
+    (dot(wm, wi) * wi.z() > 0.0 && dot(wm, wo) > 0.0).then_some((wm, etap))
+}
+
+/// Walter et al's rough dielectric, reflection and refraction through the visible facets.
+/// The BSDF times the cosine of wi, for light traced from the camera, so refraction
+/// scales it by 1 / eta^2. wo should be above the surface, eta is the index of
+/// refraction below it over the one above it.
+pub fn dielectric(distribution: &TrowbridgeReitz, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+    let Some((wm, etap)) = dielectric_normal(wo, wi, eta) else {
+        return 0.0;
+    };
+    let fresnel = fresnel_dielectric(dot(wo, wm), eta);
+    let d_g = distribution.d(wm) * distribution.g(wo, wi);
+    if etap == 1.0 {
+        return d_g * fresnel / (4.0 * wo.z());
+    }
+    let denominator = (dot(wi, wm) + dot(wo, wm) / etap).powi(2);
+    let f = d_g * (1.0 - fresnel) * (dot(wi, wm) * dot(wo, wm) / (wo.z() * denominator)).abs();
+    f / (etap * etap)
+}
+
+/// density of `sample_dielectric` picking wi
+pub fn dielectric_pdf(distribution: &TrowbridgeReitz, wo: Vec3, wi: Vec3, eta: f64) -> f64 {
+    let Some((wm, etap)) = dielectric_normal(wo, wi, eta) else {
+        return 0.0;
+    };
+    let reflected = fresnel_dielectric(dot(wo, wm), eta);
+    if etap == 1.0 {
+        return distribution.pdf(wo, wm) / (4.0 * dot(wo, wm).abs()) * reflected;
+    }
+    let denominator = (dot(wi, wm) + dot(wo, wm) / etap).powi(2);
+    distribution.pdf(wo, wm) * dot(wi, wm).abs() / denominator * (1.0 - reflected)
+}
+
+/// Reflects or refracts wo on a visible facet, picked by its Fresnel reflectance with
+/// `choice` in [0, 1). None when the facet sends it to the wrong side.
+pub fn sample_dielectric(
+    distribution: &TrowbridgeReitz,
+    wo: Vec3,
+    eta: f64,
+    choice: f64,
+    u: (f64, f64),
+) -> Option<Vec3> {
+    let wm = distribution.sample_wm(wo, u);
+    let cos_theta = dot(wo, wm);
+    if choice < fresnel_dielectric(cos_theta, eta) {
+        let wi = -wo + 2.0 * cos_theta * wm;
+        return (wi.z() > 0.0).then_some(wi);
+    }
+    let sin2_theta_t = (1.0 - cos_theta * cos_theta).max(0.0) / (eta * eta);
+    let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+    let wi = -wo / eta + (cos_theta / eta - cos_theta_t) * wm;
+    (wi.z() < 0.0).then_some(wi)
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::number_stuff::Rng;
+
+    #[test]
+    fn normals_are_distributed_and_sampled_consistently() {
+        // 20k samples miss the integrals by up to 0.05 with other seeds
+        let mut rng = Rng::new(21);
+        let samples = 20_000;
+        for (alpha_x, alpha_y) in [(0.3, 0.3), (0.6, 0.2)] {
+            let distribution = TrowbridgeReitz::new(alpha_x, alpha_y);
+            let wo = unit_vector(Vec3::new(0.4, -0.3, 0.8));
+            // projected areas of the facets add up to the area of the surface, and the
+            // visible normals are a density
+            let (mut projected, mut visible) = (0.0, 0.0);
+            for _ in 0..samples {
+                let mut wm = rng.random_unit_vector();
+                if wm.z() < 0.0 {
+                    wm = -wm;
+                }
+                projected += distribution.d(wm) * wm.z() * 2.0 * PI;
+                visible += distribution.pdf(wo, wm) * 2.0 * PI;
+            }
+            assert!((projected / samples as f64 - 1.0).abs() < 0.1);
+            assert!((visible / samples as f64 - 1.0).abs() < 0.1);
+
+            for _ in 0..100 {
+                let u = (rng.random_f64(), rng.random_f64());
+                let wi = sample_reflection(&distribution, wo, u);
+                if wi.z() > 0.0 {
+                    assert!(reflection_pdf(&distribution, wo, wi) > 0.0);
+                }
+            }
+        }
+
+        // the rough dielectric density covers both sides and matches its sampling, and
+        // glass doesn't make light out of nothing
+        let distribution = TrowbridgeReitz::new(0.3, 0.3);
+        let wo = unit_vector(Vec3::new(0.5, 0.1, 0.7));
+        for eta in [1.5, 1.0 / 1.5] {
+            let mut density = 0.0;
+            for _ in 0..samples {
+                density += dielectric_pdf(&distribution, wo, rng.random_unit_vector(), eta);
+            }
+            let (mut sampled, mut energy) = (0, 0.0);
+            for _ in 0..samples {
+                let u = (rng.random_f64(), rng.random_f64());
+                if let Some(wi) = sample_dielectric(&distribution, wo, eta, rng.random_f64(), u) {
+                    let pdf = dielectric_pdf(&distribution, wo, wi, eta);
+                    // facets at grazing angles have no density, the material drops them
+                    if pdf == 0.0 {
+                        continue;
+                    }
+                    sampled += 1;
+                    // undo the 1 / eta^2 of refraction to count energy
+                    let etap = if wi.z() < 0.0 { eta } else { 1.0 };
+                    energy += dielectric(&distribution, wo, wi, eta) * etap * etap / pdf;
+                }
+            }
+            let fraction = sampled as f64 / samples as f64;
+            // uniform directions rarely find the peak of the density, so this one is noisier
+            assert!((density * 4.0 * PI / samples as f64 - fraction).abs() < 0.2);
+            assert!(energy / (samples as f64) < 1.02 && energy / (samples as f64) > 0.75);
+        }
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        assert_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+
+        let head_on = fresnel_complex(1.0, 0.2, 3.0);
+        assert!((head_on - (0.8f64.powi(2) + 9.0) / (1.2f64.powi(2) + 9.0)).abs() < 1e-12);
+        assert!((fresnel_complex(0.0, 0.2, 3.0) - 1.0).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::aabb::Aabb;
+use crate::instance::{hit_transformed, placement};
+use crate::ray::*;
+use crate::ray_trace::*;
+use crate::vec3::*;
+use std::sync::Arc;
+
+/// Where an animated object is at one time: scaled, then rotated around x, y and z
+/// (in degrees), then moved, like an instance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time: f64,
+    pub scale: Vec3,
+    pub rotation_degrees: Vec3,
+    pub translation: Vec3,
+}
+
+impl Keyframe {
+    /// the object as it is, at time
+    pub fn at(time: f64) -> Self {
+        Self {
+            time,
+            scale: Vec3::new(1.0, 1.0, 1.0),
+            rotation_degrees: Vec3::default(),
+            translation: Vec3::default(),
+        }
+    }
+
+    pub fn with_scale(mut self, scale: Vec3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_rotation_degrees(mut self, rotation_degrees: Vec3) -> Self {
+        self.rotation_degrees = rotation_degrees;
+        self
+    }
+
+    pub fn with_translation(mut self, translation: Vec3) -> Self {
+        self.translation = translation;
+        self
+    }
+
+    pub fn transform(&self) -> Matrix4 {
+        placement(self.scale, self.rotation_degrees, self.translation)
+    }
+
+    /// fraction of the way from self to other, every part changes linearly
+    fn lerp(&self, other: &Keyframe, fraction: f64) -> Keyframe {
+        let mix = |a: Vec3, b: Vec3| a + fraction * (b - a);
+        Keyframe {
+            time: self.time + fraction * (other.time - self.time),
+            scale: mix(self.scale, other.scale),
+            rotation_degrees: mix(self.rotation_degrees, other.rotation_degrees),
+            translation: mix(self.translation, other.translation),
+        }
+    }
+}
+
+/// Errors if there are no keyframes, or if a scale is 0 along an axis or changes sign
+/// from one keyframe to the next, which would flatten the object on the way
+pub fn check_keyframes(keyframes: &[Keyframe]) -> Result<(), String> {
+    if keyframes.is_empty() {
+        return Err("an animation needs at least one keyframe".to_string());
+    }
+    if keyframes.iter().any(|k| k.transform().inverse().is_none()) {
+        return Err("keyframe scales should not be 0 along any axis".to_string());
+    }
+    let mut sorted = keyframes.to_vec();
+    sorted.sort_by(|a, b| a.time.total_cmp(&b.time));
+    let flips = sorted.windows(2).any(|pair| {
+        (0..3).any(|axis| pair[0].scale.component(axis) * pair[1].scale.component(axis) < 0.0)
+    });
+    match flips {
+        true => Err("keyframe scales should not change sign between keyframes".to_string()),
+        false => Ok(()),
+    }
+}
+
+/// Moves, turns and scales any object through keyframes. Between two keyframes the
+/// scale, the angles and the offset change linearly, before the first and after the
+/// last one the object stays put.
+pub struct Animated {
+    object: Arc<dyn Hittable>,
+    /// sorted by time
+    keyframes: Vec<Keyframe>,
+}
+
+impl Animated {
+    /// Errors like `check_keyframes`. The keyframes don't have to be sorted.
+    pub fn new(object: Arc<dyn Hittable>, mut keyframes: Vec<Keyframe>) -> Result<Self, String> {
+        check_keyframes(&keyframes)?;
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Ok(Self { object, keyframes })
+    }
+
+    /// where the object is at time
+    pub fn keyframe(&self, time: f64) -> Keyframe {
+        // index of the first keyframe after time
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return self.keyframes[0];
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1];
+        }
+        let (k0, k1) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        k0.lerp(k1, (time - k0.time) / (k1.time - k0.time))
+    }
+}
+
+/// most degrees any angle turns between two of the poses the bounding box is built from
+const BOX_STEP_DEGREES: f64 = 10.0;
+
+impl Hittable for Animated {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let transform = self.keyframe(r.time()).transform();
+        let inverse = transform.inverse()?;
+        hit_transformed(
+            self.object.as_ref(),
+            &transform,
+            &inverse,
+            &inverse.transpose(),
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    /// Without turning the corners move on straight lines, so the keyframes are the
+    /// extremes of the motion. Turning corners move on curves, those are followed in
+    /// steps of a few degrees and the box grows by half of the longest step, which is
+    /// more than the curves bulge out between the steps.
+    fn bounding_box(&self) -> Option<Aabb> {
+        let bbox = self.object.bounding_box()?;
+        let place = |keyframe: &Keyframe| {
+            let transform = keyframe.transform();
+            bbox.corners()
+                .map(|corner| transform.transform_point(corner))
+        };
+        let mut corners = place(&self.keyframes[0]);
+        let mut result = Aabb::new(corners[0], corners[0]);
+        let mut padding: f64 = 0.0;
+        for corner in corners {
+            result = result.expanded_to(corner);
+        }
+        for pair in self.keyframes.windows(2) {
+            let change = pair[1].rotation_degrees - pair[0].rotation_degrees;
+            let turn = (0..3)
+                .map(|axis| change.component(axis).abs())
+                .fold(0.0, f64::max);
+            let steps = (turn / BOX_STEP_DEGREES).ceil().max(1.0) as usize;
+            for step in 1..=steps {
+                let next = place(&pair[0].lerp(&pair[1], step as f64 / steps as f64));
+                for (from, to) in corners.iter().zip(next) {
+                    if turn > 0.0 {
+                        padding = padding.max(0.5 * (to - *from).length());
+                    }
+                    result = result.expanded_to(to);
+                }
+                corners = next;
+            }
+        }
+        let pad = Vec3::new(padding, padding, padding);
+        Some(Aabb::new(result.min() - pad, result.max() + pad))
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::quad::BoxShape;
+    use crate::sphere::{MovingSphere, Sphere};
+
+    #[test]
+    fn moving_objects_are_hit_where_they_are_at_ray_time() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Arc::new(Sphere::new(Point3::default(), 1.0, material.clone()));
+        let animated = Animated::new(
+            sphere,
+            vec![
+                Keyframe::at(1.0).with_translation(Vec3::new(4.0, 0.0, 0.0)),
+                Keyframe::at(0.0),
+            ],
+        )
+        .unwrap();
+        let moving = MovingSphere::new(
+            (Point3::default(), 0.0),
+            (Point3::new(4.0, 0.0, 0.0), 1.0),
+            1.0,
+            material,
+        );
+        let objects: [&dyn Hittable; 2] = [&animated, &moving];
+        for object in objects {
+            let ray = |time| {
+                Ray::new(Point3::new(3.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0)).with_time(time)
+            };
+            assert!(object.hit(&ray(0.0), 0.0, f64::MAX).is_none());
+            let record = object.hit(&ray(0.75), 0.0, f64::MAX).unwrap();
+            assert_eq!(record.point, Point3::new(3.0, 0.0, -1.0));
+            // stays at the end after the last keyframe
+            assert!(object.hit(&ray(2.0), 0.0, f64::MAX).is_some());
+            let bbox = object.bounding_box().unwrap();
+            assert_eq!(
+                (bbox.min(), bbox.max()),
+                (Point3::new(-1.0, -1.0, -1.0), Point3::new(5.0, 1.0, 1.0))
+            );
+        }
+    }
+
+    #[test]
+    fn turning_objects_stay_inside_their_box() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        // a bar along x that turns a quarter around y while it grows and moves up
+        let bar = Arc::new(BoxShape::new(
+            Point3::new(1.0, -0.5, -0.5),
+            Point3::new(3.0, 0.5, 0.5),
+            material,
+        ));
+        let animated = Animated::new(
+            bar.clone(),
+            vec![
+                Keyframe::at(0.0),
+                Keyframe::at(1.0)
+                    .with_scale(Vec3::new(2.0, 1.0, 1.0))
+                    .with_rotation_degrees(Vec3::new(0.0, 90.0, 0.0))
+                    .with_translation(Vec3::new(0.0, 1.0, 0.0)),
+            ],
+        )
+        .unwrap();
+
+        // at the end the bar reaches from z = -2 to z = -6
+        let ray = Ray::new(Point3::new(0.0, 1.0, -10.0), Vec3::new(0.0, 0.0, 1.0)).with_time(1.0);
+        let record = animated.hit(&ray, 0.0, f64::MAX).unwrap();
+        assert!((record.point - Point3::new(0.0, 1.0, -6.0)).really_small());
+        assert!((record.normal - Vec3::new(0.0, 0.0, -1.0)).really_small());
+        assert!(animated.hit(&ray.with_time(0.0), 0.0, f64::MAX).is_none());
+
+        // halfway the far end is out of the plane of both keyframes
+        let bbox = animated.bounding_box().unwrap();
+        for i in 0..=100 {
+            let transform = animated.keyframe(i as f64 / 100.0).transform();
+            for corner in bar.bounding_box().unwrap().corners() {
+                let corner = transform.transform_point(corner);
+                assert!(bbox.expanded_to(corner) == bbox, "{:?} outside", corner);
+            }
+        }
+
+        let flat = Keyframe::at(0.0).with_scale(Vec3::new(1.0, 0.0, 1.0));
+        assert!(Animated::new(bar.clone(), vec![flat]).is_err());
+        let mirrored = Keyframe::at(1.0).with_scale(Vec3::new(-1.0, 1.0, 1.0));
+        assert!(Animated::new(bar.clone(), vec![Keyframe::at(0.0), mirrored]).is_err());
+        assert!(Animated::new(bar, vec![]).is_err());
+    }
+}
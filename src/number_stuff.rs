@@ -1,61 +1,104 @@
 use crate::vec3::*;
-use rand::Rng;
+use rand::rngs::SmallRng;
+use rand::{Rng as _, SeedableRng};
 use std::f64::consts::PI;
 
-pub fn degrees_to_radians(degrees: f64) -> f64 {
-    degrees * PI / 180.0
+/// Random number generator for building scenes and for samplers that need random numbers.
+/// Samplers seed one for every pixel sample, so a seed gives the same image however rows
+/// are split between threads.
+pub struct Rng {
+    rng: SmallRng,
 }
 
-// TODO: optimize it to not call thread_rng every time (pass a reference to a local thread rng)
-pub fn random_f64() -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen::<f64>()
-}
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
 
-pub fn random_f64_in_range(min: f64, max: f64) -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(min..max)
-}
+    /// independent generator number `stream` of the seed
+    pub fn for_stream(seed: u64, stream: u64) -> Self {
+        Self::new(seed ^ splitmix64(stream))
+    }
 
-// TODO: Implement this with rand crate traits
-pub fn random_vec() -> Vec3 {
-    let mut rng = rand::thread_rng();
-    Vec3::new(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>())
-}
+    /// seeded from the operating system, different every time
+    pub fn from_entropy() -> Self {
+        Self {
+            rng: SmallRng::from_entropy(),
+        }
+    }
 
-/// generates random vec in cube with (min, min, min), (max, max, max) as extreme points
-pub fn random_vec_in_range(min: f64, max: f64) -> Vec3 {
-    let mut rng = rand::thread_rng();
-    Vec3::new(
-        rng.gen_range(min..max),
-        rng.gen_range(min..max),
-        rng.gen_range(min..max),
-    )
-}
+    pub fn random_u64(&mut self) -> u64 {
+        self.rng.gen()
+    }
+
+    pub fn random_f64(&mut self) -> f64 {
+        self.rng.gen::<f64>()
+    }
+
+    pub fn random_f64_in_range(&mut self, min: f64, max: f64) -> f64 {
+        self.rng.gen_range(min..max)
+    }
 
-/// generates random vector inside unit sphere
-pub fn random_in_unit_sphere() -> Vec3 {
-    loop {
-        let v = random_vec_in_range(-1.0, 1.0);
-        if v.length_squared() >= 1.0 {
-            continue;
+    /// random index into something of the given length, which should not be 0
+    pub fn random_index(&mut self, len: usize) -> usize {
+        self.rng.gen_range(0..len)
+    }
+
+    pub fn random_vec(&mut self) -> Vec3 {
+        Vec3::new(self.random_f64(), self.random_f64(), self.random_f64())
+    }
+
+    /// generates random vec in cube with (min, min, min), (max, max, max) as extreme points
+    pub fn random_vec_in_range(&mut self, min: f64, max: f64) -> Vec3 {
+        Vec3::new(
+            self.random_f64_in_range(min, max),
+            self.random_f64_in_range(min, max),
+            self.random_f64_in_range(min, max),
+        )
+    }
+
+    /// generates random vector inside unit sphere
+    pub fn random_in_unit_sphere(&mut self) -> Vec3 {
+        loop {
+            let v = self.random_vec_in_range(-1.0, 1.0);
+            if v.length_squared() < 1.0 {
+                break v;
+            }
         }
-        break v;
+    }
+
+    /// generates random vector on the unit sphere
+    pub fn random_unit_vector(&mut self) -> Vec3 {
+        unit_vector(self.random_in_unit_sphere())
     }
 }
 
-/// generates random vector on the unit sphere
-pub fn random_unit_vector() -> Vec3 {
-    unit_vector(random_in_unit_sphere())
+/// mixes the bits of x, the finalizer of the SplitMix64 generator
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    let mut rng = rand::thread_rng();
-    let x = rng.gen_range(-1.0..1.0);
-    let y = rng.gen_range(-1.0..1.0);
-    if x * x + y * y > 1.0 {
-        // no cycle because I can just pass rng reference down later
-        return random_in_unit_disk();
-    }
-    Vec3::new(x, y, 0.0)
+/// well mixed hash of the values, for seeds derived from other seeds
+pub fn hash_u64(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0, |hash, value| splitmix64(hash ^ value))
+}
+
+/// Number in [0, 1) that looks random but depends only on the values,
+/// for code that isn't handed an `Rng` like `Hittable::hit`
+pub fn hash_to_unit(values: &[f64]) -> f64 {
+    let hash = values
+        .iter()
+        .fold(0, |hash, value| splitmix64(hash ^ value.to_bits()));
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+pub fn degrees_to_radians(degrees: f64) -> f64 {
+    degrees * PI / 180.0
 }
@@ -0,0 +1,503 @@
+//! Wavefront OBJ and MTL importer. Polygons are triangulated as fans, so they are
+//! expected to be convex. Every group (`g`/`o`) and material (`usemtl`) pair becomes
+//! its own `TriangleMesh`, all of them share the vertex buffers of the file.
+
+use crate::material::Material;
+use crate::number_stuff::Rng;
+use crate::ray_trace::HittableList;
+use crate::scene::{MaterialDescription, TextureCache, TextureDescription};
+use crate::triangle::{MeshBuffers, MeshFace, TriangleMesh};
+use crate::vec3::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjError {
+    pub file: PathBuf,
+    /// None when the file couldn't be read at all
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file.display(), line, self.message),
+            None => write!(f, "{}: {}", self.file.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// faces of one group that use the same material
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjGroup {
+    pub name: String,
+    /// name given to `usemtl`, None before the first one
+    pub material: Option<String>,
+    pub faces: Vec<MeshFace>,
+}
+
+/// Everything read from an OBJ file and the MTL files it references
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ObjModel {
+    pub buffers: MeshBuffers,
+    pub groups: Vec<ObjGroup>,
+    pub materials: HashMap<String, MaterialDescription>,
+}
+
+impl ObjModel {
+    /// Builds a mesh per group. Groups get their MTL material, `fallback` is used
+    /// when a group has no material or it wasn't found in the MTL files.
+    /// Errors come from loading texture maps.
+    pub fn build(&self, fallback: Arc<dyn Material>) -> Result<HittableList, String> {
+        // materials that use the same image load it once
+        let mut textures = TextureCache::default();
+        let materials = self
+            .materials
+            .iter()
+            // MTL files only give colors and images, nothing is random
+            .map(|(name, material)| {
+                let material = material.build(&mut Rng::new(0), &mut textures)?;
+                Ok((name.as_str(), material))
+            })
+            .collect::<Result<HashMap<&str, Arc<dyn Material>>, String>>()?;
+        Ok(self.build_with(|name| {
+            name.and_then(|name| materials.get(name).cloned())
+                .unwrap_or_else(|| fallback.clone())
+        }))
+    }
+
+    /// builds every group with the same material, ignoring the MTL files
+    pub fn build_with_material(&self, material: Arc<dyn Material>) -> HittableList {
+        self.build_with(|_| material.clone())
+    }
+
+    fn build_with(&self, material: impl Fn(Option<&str>) -> Arc<dyn Material>) -> HittableList {
+        let buffers = Arc::new(self.buffers.clone());
+        let mut list = HittableList::default();
+        for group in self.groups.iter().filter(|group| !group.faces.is_empty()) {
+            list.add(Arc::new(TriangleMesh::new(
+                buffers.clone(),
+                group.faces.clone(),
+                material(group.material.as_deref()),
+            )));
+        }
+        list
+    }
+}
+
+struct Parser<'a> {
+    file: &'a Path,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn error<T>(&self, message: String) -> Result<T, ObjError> {
+        Err(ObjError {
+            file: self.file.to_path_buf(),
+            line: Some(self.line),
+            message,
+        })
+    }
+
+    /// parses at least `min` and at most `N` numbers, missing ones are 0
+    fn numbers<const N: usize>(
+        &self,
+        keyword: &str,
+        args: &[&str],
+        min: usize,
+    ) -> Result<[f64; N], ObjError> {
+        if args.len() < min || args.len() > N {
+            return self.error(format!("`{}` expects {} numbers", keyword, min));
+        }
+        let mut numbers = [0.0; N];
+        for (number, arg) in numbers.iter_mut().zip(args) {
+            *number = match arg.parse::<f64>() {
+                Ok(value) if value.is_finite() => value,
+                _ => return self.error(format!("`{}` is not a number", arg)),
+            };
+        }
+        Ok(numbers)
+    }
+
+    /// resolves a 1 based, possibly negative (relative to the end), index
+    fn index(&self, text: &str, len: usize, what: &str) -> Result<usize, ObjError> {
+        let index: i64 = match text.parse() {
+            Ok(index) => index,
+            Err(_) => return self.error(format!("`{}` is not a valid {} index", text, what)),
+        };
+        let resolved = if index < 0 {
+            len as i64 + index
+        } else {
+            index - 1
+        };
+        if index == 0 || resolved < 0 || resolved >= len as i64 {
+            return self.error(format!(
+                "{} index {} is out of range, there are {} so far",
+                what, index, len
+            ));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    std::fs::read_to_string(path).map_err(|e| ObjError {
+        file: path.to_path_buf(),
+        line: None,
+        message: e.to_string(),
+    })
+}
+
+/// reads an OBJ file together with the MTL files it references
+pub fn load_obj(path: &Path) -> Result<ObjModel, ObjError> {
+    let text = read_file(path)?;
+    parse_obj(&text, path)
+}
+
+/// `path` is used for error messages and to find MTL files next to the OBJ file
+pub fn parse_obj(text: &str, path: &Path) -> Result<ObjModel, ObjError> {
+    let mut model = ObjModel::default();
+    let mut parser = Parser {
+        file: path,
+        line: 0,
+    };
+    let mut group_name = String::from("default");
+    let mut material: Option<String> = None;
+    // index into model.groups of every (group, material) pair seen so far
+    let mut group_indices: HashMap<(String, Option<String>), usize> = HashMap::new();
+    let mut current_group: Option<usize> = None;
+
+    for (index, raw_line) in text.lines().enumerate() {
+        parser.line = index + 1;
+        let content = raw_line.split('#').next().unwrap_or("");
+        let tokens: Vec<&str> = content.split_whitespace().collect();
+        let Some((&keyword, args)) = tokens.split_first() else {
+            continue;
+        };
+        match keyword {
+            "v" => {
+                // optional w is ignored
+                let [x, y, z, _] = parser.numbers::<4>(keyword, args, 3)?;
+                model.buffers.positions.push(Point3::new(x, y, z));
+            }
+            "vn" => {
+                let [x, y, z] = parser.numbers::<3>(keyword, args, 3)?;
+                model.buffers.normals.push(Vec3::new(x, y, z));
+            }
+            "vt" => {
+                let [u, v, _] = parser.numbers::<3>(keyword, args, 1)?;
+                model.buffers.uvs.push((u, v));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return parser.error(String::from("a face needs at least 3 vertices"));
+                }
+                let mut vertices = Vec::with_capacity(args.len());
+                for arg in args {
+                    let mut parts = arg.split('/');
+                    let position = parts.next().unwrap_or("");
+                    let position =
+                        parser.index(position, model.buffers.positions.len(), "vertex")?;
+                    let uv = match parts.next() {
+                        None | Some("") => None,
+                        Some(uv) => Some(parser.index(uv, model.buffers.uvs.len(), "texture")?),
+                    };
+                    let normal = match parts.next() {
+                        None | Some("") => None,
+                        Some(normal) => {
+                            Some(parser.index(normal, model.buffers.normals.len(), "normal")?)
+                        }
+                    };
+                    vertices.push((position, uv, normal));
+                }
+                // normals and uvs are used only if every vertex of the face has them
+                let all_uvs: Option<Vec<usize>> = vertices.iter().map(|v| v.1).collect();
+                let all_normals: Option<Vec<usize>> = vertices.iter().map(|v| v.2).collect();
+
+                let group = *current_group.get_or_insert_with(|| {
+                    *group_indices
+                        .entry((group_name.clone(), material.clone()))
+                        .or_insert_with(|| {
+                            model.groups.push(ObjGroup {
+                                name: group_name.clone(),
+                                material: material.clone(),
+                                faces: Vec::new(),
+                            });
+                            model.groups.len() - 1
+                        })
+                });
+                for i in 1..vertices.len() - 1 {
+                    let corners = [0, i, i + 1];
+                    model.groups[group].faces.push(MeshFace {
+                        positions: corners.map(|c| vertices[c].0),
+                        normals: all_normals.as_ref().map(|n| corners.map(|c| n[c])),
+                        uvs: all_uvs.as_ref().map(|uv| corners.map(|c| uv[c])),
+                    });
+                }
+            }
+            "g" | "o" => {
+                group_name = if args.is_empty() {
+                    String::from("default")
+                } else {
+                    args.join(" ")
+                };
+                current_group = None;
+            }
+            "usemtl" => {
+                let Some(name) = args.first() else {
+                    return parser.error(String::from("`usemtl` needs a material name"));
+                };
+                material = Some(name.to_string());
+                current_group = None;
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return parser.error(String::from("`mtllib` needs a file name"));
+                }
+                let directory = path.parent().unwrap_or(Path::new(""));
+                for file in args {
+                    let mtl_path = directory.join(file);
+                    let mtl_text = read_file(&mtl_path).or_else(|e| {
+                        parser.error(format!("couldn't read {}: {}", file, e.message))
+                    })?;
+                    model.materials.extend(parse_mtl(&mtl_text, &mtl_path)?);
+                }
+            }
+            // smoothing groups, lines, points, curves and the rest aren't supported
+            _ => {}
+        }
+    }
+    Ok(model)
+}
+
+/// values of one `newmtl` block, named like in the MTL spec
+struct MtlEntry {
+    name: String,
+    kd: Color,
+    ks: Color,
+    ke: Color,
+    ns: f64,
+    ni: f64,
+    d: f64,
+    illum: u32,
+    /// diffuse texture, replaces kd
+    map_kd: Option<PathBuf>,
+}
+
+impl MtlEntry {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            kd: Color::new(0.8, 0.8, 0.8),
+            ks: Color::default(),
+            ke: Color::default(),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+            illum: 2,
+            map_kd: None,
+        }
+    }
+
+    /// Emissive color makes a light, transparent illumination models or dissolve make glass,
+    /// reflective models or a specular color without a diffuse one make metal,
+    /// everything else is diffuse.
+    fn to_material(&self) -> MaterialDescription {
+        if !self.ke.really_small() {
+            return MaterialDescription::DiffuseLight {
+                texture: self.ke.into(),
+            };
+        }
+        let diffuse = match &self.map_kd {
+            Some(file) => TextureDescription::Image { file: file.clone() },
+            None => self.kd.into(),
+        };
+        let transparent = matches!(self.illum, 4 | 6 | 7 | 9) || self.d < 1.0;
+        let reflective =
+            matches!(self.illum, 3 | 5 | 8) || (self.kd.really_small() && !self.ks.really_small());
+        if transparent {
+            MaterialDescription::dielectric(if self.ni > 0.0 { self.ni } else { 1.5 })
+        } else if reflective {
+            // shininess goes up to 1000, the shinier the less fuzzy
+            let fuzziness = 1.0 - (self.ns / 1000.0).clamp(0.0, 1.0).sqrt();
+            let texture = if self.ks.really_small() {
+                diffuse
+            } else {
+                self.ks.into()
+            };
+            MaterialDescription::Metal { texture, fuzziness }
+        } else {
+            MaterialDescription::Lambertian { texture: diffuse }
+        }
+    }
+}
+
+pub fn parse_mtl(
+    text: &str,
+    path: &Path,
+) -> Result<HashMap<String, MaterialDescription>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut parser = Parser {
+        file: path,
+        line: 0,
+    };
+    let mut current: Option<MtlEntry> = None;
+
+    for (index, raw_line) in text.lines().enumerate() {
+        parser.line = index + 1;
+        let content = raw_line.split('#').next().unwrap_or("");
+        let tokens: Vec<&str> = content.split_whitespace().collect();
+        let Some((&keyword, args)) = tokens.split_first() else {
+            continue;
+        };
+        if keyword == "newmtl" {
+            let Some(name) = args.first() else {
+                return parser.error(String::from("`newmtl` needs a material name"));
+            };
+            if let Some(entry) = current.replace(MtlEntry::new(name)) {
+                materials.insert(entry.name.clone(), entry.to_material());
+            }
+            continue;
+        }
+        let Some(entry) = current.as_mut() else {
+            if matches!(
+                keyword,
+                "Kd" | "Ks" | "Ke" | "Ns" | "Ni" | "d" | "Tr" | "illum" | "map_Kd"
+            ) {
+                return parser.error(format!("`{}` before any `newmtl`", keyword));
+            }
+            continue;
+        };
+        match keyword {
+            "Kd" | "Ks" | "Ke" => {
+                if args.first() == Some(&"spectral") || args.first() == Some(&"xyz") {
+                    return parser.error(format!("only rgb `{}` is supported", keyword));
+                }
+                // a single number means gray
+                let [r, g, b] = match args.len() {
+                    1 => [parser.numbers::<1>(keyword, args, 1)?[0]; 3],
+                    _ => parser.numbers::<3>(keyword, args, 3)?,
+                };
+                let color = Color::new(r, g, b);
+                match keyword {
+                    "Kd" => entry.kd = color,
+                    "Ks" => entry.ks = color,
+                    _ => entry.ke = color,
+                }
+            }
+            "Ns" => entry.ns = parser.numbers::<1>(keyword, args, 1)?[0],
+            "Ni" => entry.ni = parser.numbers::<1>(keyword, args, 1)?[0],
+            "d" => entry.d = parser.numbers::<1>(keyword, args, 1)?[0],
+            "Tr" => entry.d = 1.0 - parser.numbers::<1>(keyword, args, 1)?[0],
+            "illum" => {
+                entry.illum = match args.first().and_then(|a| a.parse().ok()) {
+                    Some(illum) => illum,
+                    None => return parser.error(String::from("`illum` expects a number")),
+                }
+            }
+            "map_Kd" => {
+                // options like `-s 2 2 1` come before the file name and aren't supported
+                let Some(file) = args.last() else {
+                    return parser.error(String::from("`map_Kd` needs a file name"));
+                };
+                entry.map_kd = Some(path.parent().unwrap_or(Path::new("")).join(file));
+            }
+            // other texture maps and ambient colors aren't supported
+            _ => {}
+        }
+    }
+    if let Some(entry) = current {
+        materials.insert(entry.name.clone(), entry.to_material());
+    }
+    Ok(materials)
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn faces_groups_and_negative_indices() {
+        let obj = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vn 0 0 1
+g quad
+f 1/1/1 2/1/1 3/1/1 4/1/1
+g triangle
+usemtl red
+f -4 -3 -2
+";
+        let model = parse_obj(obj, Path::new("test.obj")).unwrap();
+        assert_eq!(model.groups.len(), 2);
+        let quad = &model.groups[0];
+        assert_eq!(quad.name, "quad");
+        assert_eq!(quad.faces.len(), 2);
+        assert_eq!(quad.faces[1].positions, [0, 2, 3]);
+        assert_eq!(quad.faces[1].normals, Some([0, 0, 0]));
+        let triangle = &model.groups[1];
+        assert_eq!(triangle.material.as_deref(), Some("red"));
+        assert_eq!(triangle.faces[0].positions, [0, 1, 2]);
+        assert_eq!(triangle.faces[0].uvs, None);
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let err = parse_obj("v 0 0 0\n\nf 1 2 3\n", Path::new("model.obj")).unwrap_err();
+        assert_eq!(err.line, Some(3));
+        assert_eq!(
+            err.to_string(),
+            "model.obj:3: vertex index 2 is out of range, there are 1 so far"
+        );
+    }
+
+    #[test]
+    fn mtl_mapping() {
+        let mtl = "\
+newmtl glass
+Ni 1.33
+illum 7
+newmtl chrome
+Kd 0 0 0
+Ks 0.9 0.9 0.9
+Ns 1000
+newmtl paint
+Kd 0.1 0.2 0.3
+newmtl wood
+map_Kd -s 2 2 1 wood.jpg
+";
+        let materials = parse_mtl(mtl, Path::new("models/test.mtl")).unwrap();
+        assert_eq!(materials["glass"], MaterialDescription::dielectric(1.33));
+        assert_eq!(
+            materials["chrome"],
+            MaterialDescription::Metal {
+                texture: Color::new(0.9, 0.9, 0.9).into(),
+                fuzziness: 0.0
+            }
+        );
+        assert_eq!(
+            materials["paint"],
+            MaterialDescription::Lambertian {
+                texture: Color::new(0.1, 0.2, 0.3).into()
+            }
+        );
+        assert_eq!(
+            materials["wood"],
+            MaterialDescription::Lambertian {
+                texture: TextureDescription::Image {
+                    file: PathBuf::from("models/wood.jpg")
+                }
+            }
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Probability densities over the sphere of directions, for sending rays where
+//! the light comes from instead of only where materials scatter them.
+
+use crate::material::Material;
+use crate::ray_trace::Hittable;
+use crate::sampler::{sample_cosine_hemisphere, sample_sphere, Sampler};
+use crate::vec3::*;
+use crate::{HitRecord, Ray};
+use std::f64::consts::PI;
+
+pub trait Pdf {
+    /// density of `generate` picking the direction, which doesn't have to be a unit vector
+    fn value(&self, direction: Vec3) -> f64;
+
+    /// None if there's no direction to pick, like for a material that absorbs the ray
+    fn generate(&self, sampler: &mut dyn Sampler) -> Option<Vec3>;
+}
+
+/// every direction is as likely
+pub struct SpherePdf;
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        Some(sample_sphere(sampler.get_2d()))
+    }
+}
+
+/// Directions above the surface with density cos(theta) / pi, where theta is the angle
+/// from the normal. That's what diffuse surfaces reflect, so it cancels out their cosine.
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new(normal: Vec3) -> Self {
+        Self {
+            uvw: Onb::new(normal),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: Vec3) -> f64 {
+        let cosine = dot(unit_vector(direction), self.uvw.w());
+        cosine.max(0.0) / PI
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        Some(self.uvw.local(sample_cosine_hemisphere(sampler.get_2d())))
+    }
+}
+
+/// directions from origin towards an object, see `Hittable::random_direction`
+pub struct HittablePdf<'a> {
+    object: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(object: &'a dyn Hittable, origin: Point3) -> Self {
+        Self { object, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.object.pdf_value(self.origin, direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        Some(self.object.random_direction(self.origin, sampler))
+    }
+}
+
+/// the directions of a material at one hit point, see `Material::scattering_pdf`
+pub struct MaterialPdf<'a> {
+    material: &'a dyn Material,
+    ray: &'a Ray,
+    record: &'a HitRecord<'a>,
+}
+
+impl<'a> MaterialPdf<'a> {
+    pub fn new(material: &'a dyn Material, ray: &'a Ray, record: &'a HitRecord<'a>) -> Self {
+        Self {
+            material,
+            ray,
+            record,
+        }
+    }
+}
+
+impl Pdf for MaterialPdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        self.material
+            .scattering_pdf(self.ray, self.record, direction)
+    }
+
+    /// None if the material absorbs the ray, or blocks the direction it picked
+    fn generate(&self, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        self.material
+            .scatter(self.ray, self.record, sampler)
+            .filter(|scatter| !scatter.is_blocked())
+            .map(|scatter| scatter.ray.direction())
+    }
+}
+
+/// Picks one of two densities with the same probability. Weighting a sample by the
+/// mixed density is the balance heuristic of multiple importance sampling, so
+/// directions that either density is good at don't get noisy.
+pub struct MixturePdf<'a> {
+    a: &'a dyn Pdf,
+    b: &'a dyn Pdf,
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(a: &'a dyn Pdf, b: &'a dyn Pdf) -> Self {
+        Self { a, b }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: Vec3) -> f64 {
+        0.5 * self.a.value(direction) + 0.5 * self.b.value(direction)
+    }
+
+    fn generate(&self, sampler: &mut dyn Sampler) -> Option<Vec3> {
+        match sampler.get_1d() < 0.5 {
+            true => self.a.generate(sampler),
+            false => self.b.generate(sampler),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::number_stuff::Rng;
+    use crate::quad::Quad;
+    use crate::sampler::IndependentSampler;
+    use crate::sphere::Sphere;
+    use std::sync::Arc;
+
+    /// Monte Carlo estimate of the integral of the density over all directions
+    fn integral_over_sphere(pdf: &dyn Pdf, rng: &mut Rng) -> f64 {
+        let samples = 20_000;
+        let sum: f64 = (0..samples)
+            .map(|_| pdf.value(rng.random_unit_vector()) * 4.0 * PI)
+            .sum();
+        sum / samples as f64
+    }
+
+    #[test]
+    fn densities_integrate_to_one_and_match_generated_directions() {
+        let material = Arc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0)));
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 3.0), 1.0, material.clone());
+        let quad = Quad::new(
+            Point3::new(-1.0, 2.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 2.0),
+            material,
+        );
+        let cosine = CosinePdf::new(Vec3::new(0.0, 1.0, 0.0));
+        let towards_sphere = HittablePdf::new(&sphere, Point3::default());
+        let towards_quad = HittablePdf::new(&quad, Point3::default());
+        let mixture = MixturePdf::new(&cosine, &towards_quad);
+        let pdfs: [&dyn Pdf; 5] = [
+            &SpherePdf,
+            &cosine,
+            &towards_sphere,
+            &towards_quad,
+            &mixture,
+        ];
+        // with 20k uniform directions the peaked light densities are off by up to 0.09
+        let mut rng = Rng::new(17);
+        let mut sampler = IndependentSampler::new(17);
+        for pdf in pdfs {
+            assert!((integral_over_sphere(pdf, &mut rng) - 1.0).abs() < 0.12);
+            for _ in 0..100 {
+                assert!(pdf.value(pdf.generate(&mut sampler).unwrap()) > 0.0);
+            }
+        }
+
+        // lights absorb every ray, there's nothing for their density to pick
+        let lamp = DiffuseLight::new(Color::new(1.0, 1.0, 1.0));
+        let ray = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let record = HitRecord {
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            outside_ray: true,
+            ..Default::default()
+        };
+        assert!(MaterialPdf::new(&lamp, &ray, &record)
+            .generate(&mut sampler)
+            .is_none());
+    }
+}
@@ -0,0 +1,139 @@
+//! Lattice noise for procedural textures. Tables are filled from the generator they are
+//! given, so the same seed builds the same noise every time.
+
+use crate::number_stuff::Rng;
+use crate::vec3::*;
+
+const POINT_COUNT: usize = 256;
+
+/// random shuffle of 0..POINT_COUNT
+fn permutation(rng: &mut Rng) -> [usize; POINT_COUNT] {
+    let mut permutation = [0; POINT_COUNT];
+    for (i, p) in permutation.iter_mut().enumerate() {
+        *p = i;
+    }
+    for i in (1..POINT_COUNT).rev() {
+        permutation.swap(i, rng.random_index(i + 1));
+    }
+    permutation
+}
+
+/// 3t^2 - 2t^3, flat at both ends so cells join without visible edges
+fn smooth(t: f64) -> f64 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Gradient (Perlin) noise and value noise over the same hashed lattice
+pub struct Perlin {
+    gradients: [Vec3; POINT_COUNT],
+    values: [f64; POINT_COUNT],
+    permutations: [[usize; POINT_COUNT]; 3],
+}
+
+impl Perlin {
+    pub fn new(rng: &mut Rng) -> Self {
+        let mut gradients = [Vec3::default(); POINT_COUNT];
+        for gradient in &mut gradients {
+            *gradient = rng.random_unit_vector();
+        }
+        let mut values = [0.0; POINT_COUNT];
+        for value in &mut values {
+            *value = rng.random_f64();
+        }
+        Self {
+            gradients,
+            values,
+            permutations: [permutation(rng), permutation(rng), permutation(rng)],
+        }
+    }
+
+    /// index of the lattice point, wraps around every POINT_COUNT cells
+    fn hash(&self, i: i64, j: i64, k: i64) -> usize {
+        let wrap = |n: i64| n.rem_euclid(POINT_COUNT as i64) as usize;
+        self.permutations[0][wrap(i)]
+            ^ self.permutations[1][wrap(j)]
+            ^ self.permutations[2][wrap(k)]
+    }
+
+    /// Interpolates whatever `corner` gives at the 8 lattice points around p.
+    /// `corner` gets the hash of a point and the offset of p from it.
+    fn interpolate(&self, p: Point3, corner: impl Fn(usize, Vec3) -> f64) -> f64 {
+        let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - fx, p.y() - fy, p.z() - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let weight_x = if di == 1 { smooth(u) } else { 1.0 - smooth(u) };
+                    let weight_y = if dj == 1 { smooth(v) } else { 1.0 - smooth(v) };
+                    let weight_z = if dk == 1 { smooth(w) } else { 1.0 - smooth(w) };
+                    let offset = Vec3::new(u - di as f64, v - dj as f64, w - dk as f64);
+                    let hash = self.hash(i + di, j + dj, k + dk);
+                    sum += weight_x * weight_y * weight_z * corner(hash, offset);
+                }
+            }
+        }
+        sum
+    }
+
+    /// gradient noise, roughly in [-1, 1] and 0 at every lattice point
+    pub fn noise(&self, p: Point3) -> f64 {
+        self.interpolate(p, |hash, offset| dot(self.gradients[hash], offset))
+    }
+
+    /// smoothly interpolated random values in [0, 1], blockier than `noise`
+    pub fn value_noise(&self, p: Point3) -> f64 {
+        self.interpolate(p, |hash, _| self.values[hash])
+    }
+
+    /// Fractal Brownian motion, `octaves` layers of noise, each at twice the frequency
+    /// and half the amplitude of the one before. Roughly in [-1, 1].
+    pub fn fbm(&self, p: Point3, octaves: u32) -> f64 {
+        self.octaves(p, octaves, |p| self.noise(p))
+    }
+
+    /// like `fbm` but sums absolute values, which gives creases, in [0, 2)
+    pub fn turbulence(&self, p: Point3, octaves: u32) -> f64 {
+        self.octaves(p, octaves, |p| self.noise(p).abs())
+    }
+
+    /// fbm of value noise, in [0, 2)
+    pub fn value_fbm(&self, p: Point3, octaves: u32) -> f64 {
+        self.octaves(p, octaves, |p| self.value_noise(p))
+    }
+
+    fn octaves(&self, p: Point3, octaves: u32, noise: impl Fn(Point3) -> f64) -> f64 {
+        let mut sum = 0.0;
+        let mut point = p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            sum += weight * noise(point);
+            weight *= 0.5;
+            point = 2.0 * point;
+        }
+        sum
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_smooth_and_bounded() {
+        let perlin = Perlin::new(&mut Rng::new(11));
+        assert_eq!(perlin.noise(Point3::new(3.0, -7.0, 12.0)), 0.0);
+        let mut p = Point3::new(0.1, 0.2, 0.3);
+        for _ in 0..1000 {
+            let step = Vec3::new(0.013, 0.007, 0.011);
+            let (a, b) = (perlin.noise(p), perlin.noise(p + step));
+            assert!(a.abs() <= 1.5 && (a - b).abs() < 0.1);
+            assert!((0.0..=1.0).contains(&perlin.value_noise(p)));
+            assert!(perlin.turbulence(p, 7) >= 0.0);
+            p += step;
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! disney style principled material
+
+use crate::material::{Dielectric, Material, ScatterRecord};
+use crate::microfacet::{
+    fresnel_schlick, reflection, reflection_pdf, roughness_to_alpha, sample_reflection,
+    TrowbridgeReitz,
+};
+use crate::sampler::{sample_cosine_hemisphere, Sampler};
+use crate::texture::Texture;
+use crate::vec3::*;
+use crate::{HitRecord, Ray};
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// Inputs of `Principled`, every one can be a texture. Scalar inputs take the mean of
+/// the texture's channels, so gray textures and plain numbers (`gray(0.5)`) work.
+/// Scalars go from 0 to 1.
+pub struct PrincipledInputs {
+    /// diffuse color, the reflectance of metals and the tint of transmission
+    pub base_color: Arc<dyn Texture>,
+    /// 0 is a dielectric like plastic, 1 is metal
+    pub metallic: Arc<dyn Texture>,
+    /// of the specular lobe, 0 is a mirror
+    pub roughness: Arc<dyn Texture>,
+    /// stretches the specular highlight like brushed metal, along the direction in which
+    /// u grows
+    pub anisotropy: Arc<dyn Texture>,
+    /// strength of the specular reflection of dielectrics, 0.5 is about 4% head on
+    pub specular: Arc<dyn Texture>,
+    /// tints the specular reflection of dielectrics towards the base color
+    pub specular_tint: Arc<dyn Texture>,
+    /// extra reflection at grazing angles, for cloth
+    pub sheen: Arc<dyn Texture>,
+    /// tints the sheen towards the base color
+    pub sheen_tint: Arc<dyn Texture>,
+    /// second, white specular layer on top, like car paint
+    pub clearcoat: Arc<dyn Texture>,
+    /// 0 is a satin clearcoat, 1 a glossy one
+    pub clearcoat_gloss: Arc<dyn Texture>,
+    /// how much of the light goes through the surface like glass
+    pub transmission: Arc<dyn Texture>,
+    /// index of refraction of the transmission
+    pub ior: f64,
+}
+
+/// the same number everywhere, for scalar inputs
+pub fn gray(value: f64) -> Arc<dyn Texture> {
+    Color::new(value, value, value).into()
+}
+
+impl Default for PrincipledInputs {
+    /// rough white plastic
+    fn default() -> Self {
+        Self {
+            base_color: Color::new(0.8, 0.8, 0.8).into(),
+            metallic: gray(0.0),
+            roughness: gray(0.5),
+            anisotropy: gray(0.0),
+            specular: gray(0.5),
+            specular_tint: gray(0.0),
+            sheen: gray(0.0),
+            sheen_tint: gray(0.5),
+            clearcoat: gray(0.0),
+            clearcoat_gloss: gray(1.0),
+            transmission: gray(0.0),
+            ior: 1.5,
+        }
+    }
+}
+
+/// diffuse, sheen, specular, clearcoat and transmission lobes in one material
+pub struct Principled {
+    inputs: PrincipledInputs,
+}
+
+impl Principled {
+    pub fn new(inputs: PrincipledInputs) -> Self {
+        Self { inputs }
+    }
+}
+
+/// lobes that are picked, transmission is the only specular one
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Lobe {
+    Diffuse,
+    Specular,
+    Clearcoat,
+    Transmission,
+}
+
+/// the inputs looked up at a hit point, in the local frame of the hit normal
+struct Surface {
+    frame: Onb,
+    wo: Vec3,
+    base_color: Color,
+    /// weight of the diffuse and sheen lobe
+    diffuse: f64,
+    roughness: f64,
+    sheen: Color,
+    specular: TrowbridgeReitz,
+    /// specular reflectance head on
+    f0: Color,
+    /// weight of the clearcoat lobe
+    clearcoat: f64,
+    clearcoat_distribution: TrowbridgeReitz,
+    /// weight of the transmission lobe
+    transmission: f64,
+    probabilities: [(Lobe, f64); 4],
+}
+
+fn mix(a: Color, b: Color, t: f64) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+/// color with the hue of c at luminance 1, white for black
+fn tint(c: Color) -> Color {
+    match luminance(c) > 0.0 {
+        true => c / luminance(c),
+        false => Color::new(1.0, 1.0, 1.0),
+    }
+}
+
+/// (1 - cos)^5, how much Schlick's Fresnel grows at grazing angles
+fn schlick_weight(cosine: f64) -> f64 {
+    (1.0 - cosine.clamp(0.0, 1.0)).powi(5)
+}
+
+impl Surface {
+    fn new(inputs: &PrincipledInputs, ray: &Ray, record: &HitRecord<'_>) -> Self {
+        let scalar = |texture: &Arc<dyn Texture>| {
+            let value = texture.value(record.uv, record.point);
+            ((value.x() + value.y() + value.z()) / 3.0).clamp(0.0, 1.0)
+        };
+        let white = Color::new(1.0, 1.0, 1.0);
+        let base_color = inputs.base_color.value(record.uv, record.point);
+        let metallic = scalar(&inputs.metallic);
+        let roughness = scalar(&inputs.roughness);
+        let transmission = scalar(&inputs.transmission);
+        let frame = Onb::with_tangent(record.normal, record.tangent);
+        let wo = frame.to_local(-unit_vector(ray.direction()));
+
+        let (alpha_x, alpha_y) = roughness_to_alpha(roughness, scalar(&inputs.anisotropy));
+        let dielectric_f0 = 0.08
+            * scalar(&inputs.specular)
+            * mix(white, tint(base_color), scalar(&inputs.specular_tint));
+        let f0 = mix(dielectric_f0, base_color, metallic);
+        let sheen =
+            scalar(&inputs.sheen) * mix(white, tint(base_color), scalar(&inputs.sheen_tint));
+        // glossier clearcoats are sharper
+        let clearcoat_alpha = 0.1 + (0.001 - 0.1) * scalar(&inputs.clearcoat_gloss);
+
+        let mut surface = Self {
+            frame,
+            wo,
+            base_color,
+            diffuse: (1.0 - metallic) * (1.0 - transmission),
+            roughness,
+            sheen,
+            specular: TrowbridgeReitz::new(alpha_x, alpha_y),
+            f0,
+            clearcoat: 0.25 * scalar(&inputs.clearcoat),
+            clearcoat_distribution: TrowbridgeReitz::new(clearcoat_alpha, clearcoat_alpha),
+            transmission: (1.0 - metallic) * transmission,
+            probabilities: [(Lobe::Diffuse, 0.0); 4],
+        };
+        // lobes are picked by roughly how much light they reflect towards wo
+        let weights = [
+            (
+                Lobe::Diffuse,
+                surface.diffuse * (luminance(base_color) + luminance(sheen)),
+            ),
+            (Lobe::Specular, luminance(fresnel_schlick(wo.z(), f0))),
+            (
+                Lobe::Clearcoat,
+                surface.clearcoat * luminance(fresnel_schlick(wo.z(), 0.04 * white)),
+            ),
+            (Lobe::Transmission, surface.transmission),
+        ];
+        let total: f64 = weights.iter().map(|(_, weight)| weight).sum();
+        if total > 0.0 {
+            surface.probabilities = weights.map(|(lobe, weight)| (lobe, weight / total));
+        }
+        surface
+    }
+
+    fn probability(&self, lobe: Lobe) -> f64 {
+        self.probabilities
+            .iter()
+            .find(|(l, _)| *l == lobe)
+            .map_or(0.0, |(_, p)| *p)
+    }
+
+    /// chance of picking one of the lobes that aren't specular
+    fn non_specular(&self) -> f64 {
+        1.0 - self.probability(Lobe::Transmission)
+    }
+
+    /// Picks a lobe from a number in [0, 1), None if nothing is reflected at all
+    fn pick(&self, u: f64) -> Option<Lobe> {
+        let mut sum = 0.0;
+        for (lobe, probability) in self.probabilities {
+            sum += probability;
+            if u < sum {
+                return Some(lobe);
+            }
+        }
+        // rounding can leave the sum just below 1
+        self.probabilities
+            .iter()
+            .rev()
+            .find(|(_, probability)| *probability > 0.0)
+            .map(|(lobe, _)| *lobe)
+    }
+
+    /// BSDF times the cosine of the lobes that aren't specular
+    fn bsdf_cosine(&self, wi: Vec3) -> Color {
+        let wo = self.wo;
+        if wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::default();
+        }
+        let half = unit_vector(wo + wi);
+        let cos_d = dot(wi, half);
+        // Burley's diffuse gets brighter at grazing angles when rough, darker when smooth
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z()))
+            * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()));
+        let diffuse = self.diffuse
+            * (retro / PI * self.base_color + schlick_weight(cos_d) * self.sheen)
+            * wi.z();
+        let specular = reflection(&self.specular, wo, wi, |cosine| {
+            fresnel_schlick(cosine, self.f0)
+        });
+        let clearcoat = self.clearcoat
+            * reflection(&self.clearcoat_distribution, wo, wi, |cosine| {
+                fresnel_schlick(cosine, Color::new(0.04, 0.04, 0.04))
+            });
+        diffuse + specular + clearcoat
+    }
+
+    /// density of picking wi, summed over the lobes that aren't specular
+    fn pdf(&self, wi: Vec3) -> f64 {
+        if self.wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        self.probability(Lobe::Diffuse) * wi.z() / PI
+            + self.probability(Lobe::Specular) * reflection_pdf(&self.specular, self.wo, wi)
+            + self.probability(Lobe::Clearcoat)
+                * reflection_pdf(&self.clearcoat_distribution, self.wo, wi)
+    }
+}
+
+impl Material for Principled {
+    /// rays that a rough lobe sends below the surface are blocked
+    fn scatter(
+        &self,
+        ray: &Ray,
+        record: &HitRecord<'_>,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
+        let surface = Surface::new(&self.inputs, ray, record);
+        let wi = match surface.pick(sampler.get_1d())? {
+            Lobe::Transmission => {
+                let (direction, refracted) = transmit(ray, record, self.inputs.ior, sampler);
+                let color = match refracted {
+                    true => surface.base_color,
+                    false => Color::new(1.0, 1.0, 1.0),
+                };
+                let weight = surface.transmission / surface.probability(Lobe::Transmission);
+                return Some(ScatterRecord {
+                    ray: Ray::new(record.point, direction).with_time(ray.time()),
+                    attenuation: weight * color,
+                    pdf: None,
+                });
+            }
+            Lobe::Diffuse => sample_cosine_hemisphere(sampler.get_2d()),
+            Lobe::Specular => sample_reflection(&surface.specular, surface.wo, sampler.get_2d()),
+            Lobe::Clearcoat => sample_reflection(
+                &surface.clearcoat_distribution,
+                surface.wo,
+                sampler.get_2d(),
+            ),
+        };
+        let scattered = Ray::new(record.point, surface.frame.local(wi)).with_time(ray.time());
+        let pdf = surface.pdf(wi);
+        if pdf <= 0.0 {
+            return Some(ScatterRecord::blocked(scattered));
+        }
+        Some(ScatterRecord {
+            ray: scattered,
+            attenuation: surface.bsdf_cosine(wi) / pdf,
+            pdf: Some(pdf / surface.non_specular()),
+        })
+    }
+
+    fn scattering_pdf(&self, ray: &Ray, record: &HitRecord<'_>, direction: Vec3) -> f64 {
+        let surface = Surface::new(&self.inputs, ray, record);
+        let wi = surface.frame.to_local(unit_vector(direction));
+        match surface.non_specular() > 0.0 {
+            true => surface.pdf(wi) / surface.non_specular(),
+            false => 0.0,
+        }
+    }
+
+    fn bsdf_cosine(&self, ray: &Ray, record: &HitRecord<'_>, direction: Vec3) -> Color {
+        let surface = Surface::new(&self.inputs, ray, record);
+        let wi = surface.frame.to_local(unit_vector(direction));
+        match surface.non_specular() > 0.0 {
+            true => surface.bsdf_cosine(wi) / surface.non_specular(),
+            false => Color::default(),
+        }
+    }
+
+    fn albedo(&self, record: &HitRecord<'_>) -> Color {
+        self.inputs.base_color.value(record.uv, record.point)
+    }
+}
+
+/// Smooth glass: reflects or refracts by Fresnel like `Dielectric`.
+/// Returns the direction and whether it was refracted.
+fn transmit(
+    ray: &Ray,
+    record: &HitRecord<'_>,
+    ior: f64,
+    sampler: &mut dyn Sampler,
+) -> (Vec3, bool) {
+    let refraction_ratio = match record.outside_ray {
+        true => 1.0 / ior,
+        false => ior,
+    };
+    let unit_direction = unit_vector(ray.direction());
+    let cos_theta = dot(-unit_direction, record.normal).min(1.0);
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+    let cannot_refract = refraction_ratio * sin_theta > 1.0;
+    if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > sampler.get_1d() {
+        (reflect(&unit_direction, &record.normal), false)
+    } else {
+        (
+            refract(&unit_direction, &record.normal, refraction_ratio),
+            true,
+        )
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::IndependentSampler;
+    use crate::vec3::Point3;
+
+    #[test]
+    fn scatters_match_densities_and_keep_energy() {
+        let record = HitRecord {
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            outside_ray: true,
+            ..Default::default()
+        };
+        let ray = Ray::new(Point3::new(0.0, 1.0, -1.0), Vec3::new(0.0, -1.0, 1.0));
+        let white = Color::new(1.0, 1.0, 1.0);
+        let materials = [
+            Principled::new(PrincipledInputs {
+                base_color: white.into(),
+                ..Default::default()
+            }),
+            Principled::new(PrincipledInputs {
+                base_color: Color::new(0.9, 0.6, 0.2).into(),
+                metallic: gray(1.0),
+                roughness: gray(0.3),
+                anisotropy: gray(0.5),
+                ..Default::default()
+            }),
+            Principled::new(PrincipledInputs {
+                sheen: gray(1.0),
+                clearcoat: gray(1.0),
+                transmission: gray(0.5),
+                ..Default::default()
+            }),
+        ];
+        let mut sampler = IndependentSampler::new(22);
+        let samples = 20_000;
+        for material in &materials {
+            let mut reflected = Color::default();
+            for _ in 0..samples {
+                let Some(scatter) = material.scatter(&ray, &record, &mut sampler) else {
+                    continue;
+                };
+                reflected += scatter.attenuation / samples as f64;
+                if scatter.is_specular() || scatter.is_blocked() {
+                    continue;
+                }
+                // light sampling weighs directions with these, they have to agree
+                let direction = scatter.ray.direction();
+                let pdf = material.scattering_pdf(&ray, &record, direction);
+                let bsdf_cosine = material.bsdf_cosine(&ray, &record, direction);
+                assert!((scatter.pdf.unwrap() - pdf).abs() <= 1e-9 * pdf);
+                assert!((bsdf_cosine / pdf - scatter.attenuation).length() < 1e-9);
+            }
+            // a bit more than 1 is the sheen and Burley's retro reflection
+            assert!(reflected.x() < 1.1 && reflected.y() < 1.1 && reflected.z() < 1.1);
+            assert!(reflected.y() > 0.1);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use crate::aabb::Aabb;
+use crate::material::Material;
+use crate::ray::*;
+use crate::ray_trace::*;
+use crate::sampler::Sampler;
+use crate::vec3::*;
+use std::sync::Arc;
+
+/// Hit record of a flat shape with the given outward normal (not necessarily unit).
+/// The normal is flipped to face the ray like the normals of triangles.
+fn flat_record<'a>(
+    r: &Ray,
+    t: f64,
+    outward_normal: Vec3,
+    uv: (f64, f64),
+    tangent: Vec3,
+    material: &'a dyn Material,
+) -> HitRecord<'a> {
+    let outside_ray = !face_one_direction(r, &outward_normal);
+    let normal = unit_vector(outward_normal);
+    HitRecord {
+        point: r.at(t),
+        normal: if outside_ray { normal } else { -normal },
+        t,
+        material_reference: Some(material),
+        outside_ray,
+        uv,
+        tangent,
+        ..Default::default()
+    }
+}
+
+/// Parallelogram with corners corner, corner + u, corner + u + v and corner + v.
+/// Front side is the one from which u turns counterclockwise to v, texture coordinates
+/// go from 0 to 1 along u and v.
+pub struct Quad {
+    corner: Point3,
+    u: Vec3,
+    v: Vec3,
+    material: Arc<dyn Material>,
+    /// cross(u, v), the outward normal
+    normal: Vec3,
+    /// normal / |normal|^2, turns a point in the plane into its (u, v) coordinates
+    w: Vec3,
+}
+
+impl Quad {
+    /// u and v should not be parallel
+    pub fn new(corner: Point3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Self {
+        let normal = cross(u, v);
+        Self {
+            corner,
+            u,
+            v,
+            material,
+            normal,
+            w: normal / dot(normal, normal),
+        }
+    }
+
+    pub fn corner(&self) -> Point3 {
+        self.corner
+    }
+
+    pub fn edges(&self) -> (Vec3, Vec3) {
+        (self.u, self.v)
+    }
+
+    pub fn area(&self) -> f64 {
+        self.normal.length()
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let denominator = dot(self.normal, r.direction());
+        if denominator.abs() < 1e-12 {
+            // ray is parallel to the plane
+            return None;
+        }
+        let t = dot(self.normal, self.corner - r.origin()) / denominator;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let in_plane = r.at(t) - self.corner;
+        let alpha = dot(self.w, cross(in_plane, self.v));
+        let beta = dot(self.w, cross(self.u, in_plane));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some(flat_record(
+            r,
+            t,
+            self.normal,
+            (alpha, beta),
+            self.u,
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let far = self.corner + self.u + self.v;
+        Some(
+            Aabb::new(self.corner, far)
+                .expanded_to(self.corner + self.u)
+                .expanded_to(self.corner + self.v),
+        )
+    }
+
+    /// uniform over the area, seen from either side
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let Some(record) = self.hit(&Ray::new(origin, direction), 0.001, f64::MAX) else {
+            return 0.0;
+        };
+        let distance_squared = record.t * record.t * direction.length_squared();
+        let cosine = dot(direction, self.normal).abs() / (direction.length() * self.area());
+        distance_squared / (cosine * self.area())
+    }
+
+    fn random_direction(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let (a, b) = sampler.get_2d();
+        self.corner + a * self.u + b * self.v - origin
+    }
+}
+
+/// Rectangle lying in a plane `axis = k`, faster to hit than a `Quad`.
+/// Its front side looks towards +axis.
+pub struct AxisRect {
+    /// axis of the normal, 0, 1 or 2 for x, y or z
+    axis: usize,
+    /// the two axes in the plane, u goes along the first and v along the second
+    plane_axes: (usize, usize),
+    min: (f64, f64),
+    max: (f64, f64),
+    k: f64,
+    material: Arc<dyn Material>,
+}
+
+impl AxisRect {
+    fn new(
+        axis: usize,
+        plane_axes: (usize, usize),
+        (a0, a1): (f64, f64),
+        (b0, b1): (f64, f64),
+        k: f64,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            axis,
+            plane_axes,
+            min: (a0.min(a1), b0.min(b1)),
+            max: (a0.max(a1), b0.max(b1)),
+            k,
+            material,
+        }
+    }
+
+    /// rectangle between x0..x1 and y0..y1 at z = k
+    pub fn xy(x: (f64, f64), y: (f64, f64), k: f64, material: Arc<dyn Material>) -> Self {
+        Self::new(2, (0, 1), x, y, k, material)
+    }
+
+    /// rectangle between x0..x1 and z0..z1 at y = k
+    pub fn xz(x: (f64, f64), z: (f64, f64), k: f64, material: Arc<dyn Material>) -> Self {
+        Self::new(1, (0, 2), x, z, k, material)
+    }
+
+    /// rectangle between y0..y1 and z0..z1 at x = k
+    pub fn yz(y: (f64, f64), z: (f64, f64), k: f64, material: Arc<dyn Material>) -> Self {
+        Self::new(0, (1, 2), y, z, k, material)
+    }
+
+    fn point(&self, along_axis: f64, a: f64, b: f64) -> Point3 {
+        let mut coordinates = [0.0; 3];
+        coordinates[self.axis] = along_axis;
+        coordinates[self.plane_axes.0] = a;
+        coordinates[self.plane_axes.1] = b;
+        Point3::new(coordinates[0], coordinates[1], coordinates[2])
+    }
+}
+
+impl Hittable for AxisRect {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let t = (self.k - r.origin().component(self.axis)) / r.direction().component(self.axis);
+        // NaN and infinities of parallel rays fail here too
+        if !(t >= t_min && t <= t_max) {
+            return None;
+        }
+        let p = r.at(t);
+        let (a, b) = (
+            p.component(self.plane_axes.0),
+            p.component(self.plane_axes.1),
+        );
+        if a < self.min.0 || a > self.max.0 || b < self.min.1 || b > self.max.1 {
+            return None;
+        }
+        let uv = (
+            (a - self.min.0) / (self.max.0 - self.min.0),
+            (b - self.min.1) / (self.max.1 - self.min.1),
+        );
+        let outward_normal = self.point(1.0, 0.0, 0.0);
+        Some(flat_record(
+            r,
+            t,
+            outward_normal,
+            uv,
+            self.point(0.0, 1.0, 0.0),
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(
+            self.point(self.k, self.min.0, self.min.1),
+            self.point(self.k, self.max.0, self.max.1),
+        ))
+    }
+}
+
+/// Box with sides parallel to the axes made of six quads with normals looking out.
+/// Other orientations can be made with `instance::Instance`.
+pub struct BoxShape {
+    sides: HittableList,
+    bbox: Aabb,
+}
+
+impl BoxShape {
+    /// corners don't have to be ordered
+    pub fn new(a: Point3, b: Point3, material: Arc<dyn Material>) -> Self {
+        let bbox = Aabb::new(a, b);
+        let (min, max) = (bbox.min(), bbox.max());
+        let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+        let mut sides = HittableList::default();
+        for (corner, u, v) in [
+            (min + dz, dx, dy),       // front, +z
+            (min + dx + dz, -dz, dy), // right, +x
+            (min + dx, -dx, dy),      // back, -z
+            (min, dz, dy),            // left, -x
+            (min + dy + dz, dx, -dz), // top, +y
+            (min, dx, dz),            // bottom, -y
+        ] {
+            sides.add(Arc::new(Quad::new(corner, u, v, material.clone())));
+        }
+        Self { sides, bbox }
+    }
+}
+
+impl Hittable for BoxShape {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.sides.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn flat_shapes_have_outward_normals_and_uvs() {
+        let material: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let quad = Quad::new(
+            Point3::new(-1.0, 0.0, -1.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 4.0),
+            material.clone(),
+        );
+        let rect = AxisRect::xz((-1.0, 1.0), (-1.0, 3.0), 0.0, material.clone());
+        let down = Ray::new(Point3::new(0.5, 2.0, 2.0), Vec3::new(0.0, -1.0, 0.0));
+        // the quad's u turns clockwise to v seen from above, so its front side is below
+        let record = quad.hit(&down, 0.0, f64::MAX).unwrap();
+        assert!(!record.outside_ray);
+        assert_eq!(record.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(record.uv, (0.75, 0.75));
+        assert_eq!(record.tangent, Vec3::new(2.0, 0.0, 0.0));
+        let record = rect.hit(&down, 0.0, f64::MAX).unwrap();
+        assert!(record.outside_ray);
+        assert_eq!((record.t, record.uv), (2.0, (0.75, 0.75)));
+        let beside = Ray::new(Point3::new(1.5, 2.0, 2.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(quad.hit(&beside, 0.0, f64::MAX).is_none());
+        assert!(rect.hit(&beside, 0.0, f64::MAX).is_none());
+
+        let cube = BoxShape::new(Point3::new(1.0, 1.0, 1.0), Point3::default(), material);
+        for direction in [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ] {
+            let center = Point3::new(0.5, 0.5, 0.5);
+            let outside = Ray::new(center + 2.0 * direction, -direction);
+            let record = cube.hit(&outside, 0.0, f64::MAX).unwrap();
+            assert!(record.outside_ray);
+            assert_eq!(record.normal, direction);
+            let inside = Ray::new(center, direction);
+            let record = cube.hit(&inside, 0.0, f64::MAX).unwrap();
+            assert!(!record.outside_ray);
+            assert_eq!(record.point, center + 0.5 * direction);
+        }
+    }
+}
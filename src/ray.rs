@@ -1,14 +1,52 @@
 use crate::vec3::*;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub struct Ray {
     origin: Point3,
     direction: Vec3,
+    /// moment the ray exists at, moving objects are hit where they are at that time
+    time: f64,
+    /// in nanometers, only in spectral mode
+    wavelength: Option<f64>,
+    /// number in [0, 1) from the sampler that picks how far into a medium the ray
+    /// goes before it scatters
+    free_flight: f64,
 }
 
 impl Ray {
+    /// ray at time 0
     pub fn new(origin: Point3, direction: Vec3) -> Self {
-        Self { origin, direction }
+        Self {
+            origin,
+            direction,
+            time: 0.0,
+            wavelength: None,
+            free_flight: 0.5,
+        }
+    }
+
+    /// the ray in another space, like the one of an instance, with everything else kept
+    pub fn moved(&self, origin: Point3, direction: Vec3) -> Self {
+        Self {
+            origin,
+            direction,
+            ..*self
+        }
+    }
+
+    pub fn with_time(mut self, time: f64) -> Self {
+        self.time = time;
+        self
+    }
+
+    pub fn with_wavelength(mut self, wavelength: Option<f64>) -> Self {
+        self.wavelength = wavelength;
+        self
+    }
+
+    pub fn with_free_flight(mut self, free_flight: f64) -> Self {
+        self.free_flight = free_flight;
+        self
     }
 
     pub fn origin(&self) -> Point3 {
@@ -17,6 +55,17 @@ impl Ray {
     pub fn direction(&self) -> Vec3 {
         self.direction
     }
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
+    pub fn free_flight(&self) -> f64 {
+        self.free_flight
+    }
 
     pub fn at(&self, t: f64) -> Point3 {
         self.origin() + t * self.direction()
@@ -1,24 +1,52 @@
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::*;
+use crate::sampler::Sampler;
 use crate::vec3::*;
-use std::rc::Rc;
+use std::sync::Arc;
 
+// borrows the material from the object that was hit, so no reference counting
+// is needed while the world is shared between render threads
 #[derive(Default)]
-pub struct HitRecord {
+pub struct HitRecord<'a> {
     pub point: Point3,
     pub normal: Vec3,
     pub t: f64,
-    pub material_reference: Option<Rc<dyn Material>>,
+    pub material_reference: Option<&'a dyn Material>,
     pub outside_ray: bool,
+    /// texture coordinates of the hit point
+    pub uv: (f64, f64),
+    /// Direction on the surface in which u grows (dp/du), not unit. Anisotropic materials
+    /// stretch their highlight along it. Zero if the object has none.
+    pub tangent: Vec3,
+    /// weights of the second and the third vertex when a triangle is hit,
+    /// the first one gets 1 - b1 - b2
+    pub barycentric: Option<(f64, f64)>,
+    /// ID of the scene object that was hit, see `Identified`, 0 if it has none
+    pub object_id: u32,
 }
 
-pub trait Hittable {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
+pub trait Hittable: Send + Sync {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>>;
     // None if not hit
     // normal should face the direction of r
     // if angle(r, normal) = pi/2 then normal points outwards
     // TODO: maybe need to return if they face one direction later
     // (to determine whether ray hits obj from outside)
+
+    /// box that contains the whole object, None if the object is unbounded
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Density of `random_direction` picking the direction from origin, over the sphere
+    /// of directions. Objects that can't be sampled as lights keep 0.
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3) -> f64 {
+        0.0
+    }
+
+    /// direction from origin towards a random point of the object
+    fn random_direction(&self, _origin: Point3, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
 }
 
 pub fn face_one_direction(r: &Ray, v: &Vec3) -> bool {
@@ -26,50 +54,107 @@ pub fn face_one_direction(r: &Ray, v: &Vec3) -> bool {
     dot(r.direction(), *v) > 0.0
 }
 
+/// Gives an object's hits the ID of the object, so they can be told apart in the
+/// object ID output. Lights keep working through it.
+pub struct Identified {
+    id: u32,
+    object: Arc<dyn Hittable>,
+}
+
+impl Identified {
+    pub fn new(id: u32, object: Arc<dyn Hittable>) -> Self {
+        Self { id, object }
+    }
+}
+
+impl Hittable for Identified {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut record = self.object.hit(r, t_min, t_max)?;
+        record.object_id = self.id;
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random_direction(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        self.object.random_direction(origin, sampler)
+    }
+}
+
 #[derive(Default)]
 pub struct HittableList {
-    // might need to change to Vec<Rc<Cell<T>>>
-    objects: Vec<Rc<dyn Hittable>>,
+    objects: Vec<Arc<dyn Hittable>>,
 }
 
 impl HittableList {
-    pub fn add(&mut self, object: Rc<dyn Hittable>) {
+    pub fn add(&mut self, object: Arc<dyn Hittable>) {
         self.objects.push(object);
     }
     pub fn clear(&mut self) {
         self.objects.clear();
     }
-    pub fn new(object: Rc<dyn Hittable>) -> Self {
+    pub fn new(object: Arc<dyn Hittable>) -> Self {
         Self {
             objects: vec![object],
         }
     }
+    pub fn objects(&self) -> &[Arc<dyn Hittable>] {
+        &self.objects
+    }
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
 }
 
-// TODO: refactor this spaghetti
 impl Hittable for HittableList {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut temp_record = HitRecord {
-            t: t_max,
-            ..Default::default()
-        };
-        let mut hit_something = false;
-        self.objects
+    /// Closest hit. Every object only looks in front of the closest hit so far, which also
+    /// keeps volumes from scattering rays behind something that blocks them.
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let mut closest: Option<HitRecord<'_>> = None;
+        for object in &self.objects {
+            let t_closest = closest.as_ref().map_or(t_max, |record| record.t);
+            if let Some(record) = object.hit(r, t_min, t_closest) {
+                closest = Some(record);
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|obj| obj.bounding_box());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, bbox| Some(Aabb::surrounding_box(&acc, &bbox?)))
+    }
+
+    /// every object is picked with the same probability
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let sum: f64 = self
+            .objects
             .iter()
-            // might replace t_max with temp_record.t here to remove if condition later,
-            // but borrow checker doesn't let me
-            .map(|obj| obj.hit(r, t_min, t_max))
-            .for_each(|record_opt| {
-                if let Some(record) = record_opt {
-                    if record.t <= temp_record.t {
-                        temp_record = record;
-                        hit_something = true;
-                    }
-                }
-            });
-        if hit_something {
-            return Some(temp_record);
+            .map(|object| object.pdf_value(origin, direction))
+            .sum();
+        sum / self.objects.len() as f64
+    }
+
+    fn random_direction(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        match self.objects.len() {
+            0 => Vec3::new(1.0, 0.0, 0.0),
+            len => {
+                let index = ((sampler.get_1d() * len as f64) as usize).min(len - 1);
+                self.objects[index].random_direction(origin, sampler)
+            }
         }
-        None
     }
 }
@@ -0,0 +1,412 @@
+//! where the random numbers of a pixel sample come from
+
+use crate::number_stuff::{hash_u64, Rng};
+use crate::vec3::*;
+use std::f64::consts::PI;
+
+pub trait Sampler {
+    /// starts sample `index` of a pixel, the dimensions start over from the first one
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32);
+
+    /// next dimension, in [0, 1)
+    fn get_1d(&mut self) -> f64;
+
+    /// next two dimensions, spread evenly together and not only one by one
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+/// the samplers that can be picked by name
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    #[default]
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const ALL: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// the same seed gives the same numbers for a pixel sample, whatever was asked before
+    pub fn build(&self, samples_per_pixel: u32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+/// generator of one pixel sample, so samples don't depend on what was rendered before
+fn pixel_sample_rng(seed: u64, pixel: (u32, u32), index: u32) -> Rng {
+    Rng::new(hash_u64(&[
+        seed,
+        u64::from(pixel.0),
+        u64::from(pixel.1),
+        u64::from(index),
+    ]))
+}
+
+/// plain random numbers, every dimension is independent of the others
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Rng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.rng = pixel_sample_rng(self.seed, pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.random_f64()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.random_f64(), self.rng.random_f64())
+    }
+}
+
+/// Jittered grid. 1D dimensions are cut in samples_per_pixel strata and 2D ones in a grid
+/// of ceil(sqrt(samples_per_pixel)) cells per side, every sample gets a random point in a
+/// cell of its own. Cells are shuffled differently for every pixel and dimension, so the
+/// dimensions don't line up with each other. When samples_per_pixel isn't a square some
+/// cells of the grid stay empty.
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    grid_size: u32,
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: u64,
+    rng: Rng,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32, seed: u64) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        Self {
+            samples_per_pixel,
+            grid_size: (f64::from(samples_per_pixel).sqrt().ceil() as u32).max(1),
+            seed,
+            pixel_seed: seed,
+            index: 0,
+            dimension: 0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// cell of the current sample among `cells`, shuffled for the pixel and dimension
+    fn cell(&mut self, cells: u32) -> u32 {
+        let shuffle = hash_u64(&[self.pixel_seed, self.dimension]) as u32;
+        self.dimension += 1;
+        permutation_element(self.index % cells, cells, shuffle)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel_seed = hash_u64(&[self.seed, u64::from(pixel.0), u64::from(pixel.1)]);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = pixel_sample_rng(self.seed, pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.cell(self.samples_per_pixel);
+        (f64::from(stratum) + self.rng.random_f64()) / f64::from(self.samples_per_pixel)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let n = self.grid_size;
+        let cell = self.cell(n * n);
+        let (x, y) = (cell % n, cell / n);
+        (
+            (f64::from(x) + self.rng.random_f64()) / f64::from(n),
+            (f64::from(y) + self.rng.random_f64()) / f64::from(n),
+        )
+    }
+}
+
+/// bases of the Halton dimensions, later dimensions are independent random numbers
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// The Halton sequence, dimension i is the radical inverse of the sample index in the
+/// i-th prime base. Every pixel shifts every dimension by a random offset (a
+/// Cranley-Patterson rotation), so neighbouring pixels don't get the same points.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: usize,
+    rng: Rng,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_seed: seed,
+            index: 0,
+            dimension: 0,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel_seed = hash_u64(&[self.seed, u64::from(pixel.0), u64::from(pixel.1)]);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = pixel_sample_rng(self.seed, pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let Some(&base) = PRIMES.get(self.dimension) else {
+            return self.rng.random_f64();
+        };
+        let offset = hash_u64(&[self.pixel_seed, self.dimension as u64]);
+        self.dimension += 1;
+        (radical_inverse(base, self.index) + to_unit(offset as u32)).fract()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// digits of index in the base mirrored around the point, 0.21 for 12 in base 10
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inverse_base = 1.0 / f64::from(base);
+    let (mut value, mut digit_scale) = (0.0, inverse_base);
+    while index > 0 {
+        value += f64::from(index % base) * digit_scale;
+        index /= base;
+        digit_scale *= inverse_base;
+    }
+    value
+}
+
+/// The first two dimensions of the Sobol sequence, Owen scrambled with hashing as in
+/// Burley's "Practical Hash-based Owen Scrambling". Every 2D request uses the same two
+/// dimensions with an index shuffled by its own seed, which keeps each pair well
+/// spread without the pairs being correlated with each other.
+pub struct SobolSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            pixel_seed: seed,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    /// the index shuffled and the scrambling seed of each of the two dimensions
+    fn next_dimension(&mut self) -> (u32, [u32; 2]) {
+        let hash = |salt: u64| hash_u64(&[self.pixel_seed, self.dimension, salt]) as u32;
+        let shuffled = nested_uniform_scramble(self.index, hash(0));
+        let seeds = [hash(1), hash(2)];
+        self.dimension += 1;
+        (shuffled, seeds)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: (u32, u32), index: u32) {
+        self.pixel_seed = hash_u64(&[self.seed, u64::from(pixel.0), u64::from(pixel.1)]);
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (shuffled, [seed, _]) = self.next_dimension();
+        to_unit(nested_uniform_scramble(shuffled.reverse_bits(), seed))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (shuffled, [seed_x, seed_y]) = self.next_dimension();
+        let (x, y) = sobol_2d(shuffled);
+        (
+            to_unit(nested_uniform_scramble(x, seed_x)),
+            to_unit(nested_uniform_scramble(y, seed_y)),
+        )
+    }
+}
+
+/// Sobol points of the index as fractions of 2^32. The first dimension mirrors the bits
+/// of the index, the second one xors the rows of Pascal's triangle modulo 2.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let (mut y, mut direction) = (0, 1u32 << 31);
+    for bit in 0..32 {
+        if index >> bit & 1 == 1 {
+            y ^= direction;
+        }
+        direction ^= direction >> 1;
+    }
+    (index.reverse_bits(), y)
+}
+
+/// Random permutation of the bits of x where every bit only depends on the bits above it,
+/// which keeps points that were evenly spread evenly spread (Owen scrambling).
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    // Laine and Karras' permutation scrambles bits from the lowest up, so the bits are
+    // mirrored before and after it
+    let mut x = x.reverse_bits().wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+/// Element i of a random permutation of 0..len picked by the seed, without storing the
+/// permutation. Kensler's "Correlated Multi-Jittered Sampling".
+fn permutation_element(mut i: u32, len: u32, seed: u32) -> u32 {
+    let mut mask = len - 1;
+    mask |= mask >> 1;
+    mask |= mask >> 2;
+    mask |= mask >> 4;
+    mask |= mask >> 8;
+    mask |= mask >> 16;
+    // a permutation of the next power of two, repeated until it lands inside 0..len
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= seed >> 16;
+        i ^= (i & mask) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= seed >> 23;
+        i ^= (i & mask) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & mask) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & mask) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= mask;
+        i ^= i >> 5;
+        if i < len {
+            break (i + seed % len) % len;
+        }
+    }
+}
+
+/// fraction of 2^32, in [0, 1)
+fn to_unit(x: u32) -> f64 {
+    f64::from(x) / 4_294_967_296.0
+}
+
+/// Point in the unit disk in the xy plane. Shirley's concentric mapping turns squares
+/// into rings, so samples spread evenly over the square stay spread over the disk.
+pub fn sample_disk((u, v): (f64, f64)) -> Vec3 {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::default();
+    }
+    let (radius, theta) = match a.abs() > b.abs() {
+        true => (a, PI / 4.0 * (b / a)),
+        false => (b, PI / 2.0 - PI / 4.0 * (a / b)),
+    };
+    Vec3::new(radius * theta.cos(), radius * theta.sin(), 0.0)
+}
+
+/// direction above the xy plane with density cos(theta) / pi, where theta is the angle
+/// from the z axis
+pub fn sample_cosine_hemisphere(uv: (f64, f64)) -> Vec3 {
+    let disk = sample_disk(uv);
+    let z = (1.0 - disk.x() * disk.x() - disk.y() * disk.y())
+        .max(0.0)
+        .sqrt();
+    Vec3::new(disk.x(), disk.y(), z)
+}
+
+/// direction with the same density 1 / 4pi everywhere
+pub fn sample_sphere((u, v): (f64, f64)) -> Vec3 {
+    let z = 1.0 - 2.0 * u;
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+/// point inside the unit ball, uniform over its volume
+pub fn sample_ball(direction: (f64, f64), u: f64) -> Vec3 {
+    u.cbrt() * sample_sphere(direction)
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_spread_over_strata() {
+        let spp = 16;
+        for kind in SamplerKind::ALL {
+            let mut sampler = kind.build(spp, 42);
+            let mut cells_2d = [0; 16];
+            let mut strata_1d = [0; 16];
+            for index in 0..spp {
+                sampler.start_pixel_sample((3, 5), index);
+                let (x, y) = sampler.get_2d();
+                let z = sampler.get_1d();
+                assert!([x, y, z].iter().all(|v| (0.0..1.0).contains(v)));
+                cells_2d[(x * 4.0) as usize + 4 * (y * 4.0) as usize] += 1;
+                strata_1d[(z * 16.0) as usize] += 1;
+            }
+            // Halton's base 3 dimension doesn't stop at powers of 2
+            if kind != SamplerKind::Independent && kind != SamplerKind::Halton {
+                assert_eq!(cells_2d, [1; 16], "{}", kind.name());
+                assert_eq!(strata_1d, [1; 16], "{}", kind.name());
+            }
+            // the same pixel sample gives the same numbers again
+            sampler.start_pixel_sample((3, 5), 7);
+            let first = sampler.get_2d();
+            sampler.start_pixel_sample((3, 5), 7);
+            assert_eq!(sampler.get_2d(), first);
+        }
+    }
+}
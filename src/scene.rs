@@ -0,0 +1,710 @@
+use crate::camera::CameraSettings;
+use crate::environment::{ConstantEnvironment, Environment, EnvironmentMap, GradientEnvironment};
+use crate::generate_scene::{
+    bouncing_spheres_description, cornell_box_description, generate_scene_description,
+};
+use crate::image_input::{load_hdr, load_image};
+use crate::instance::{placement, Instance};
+use crate::material::{
+    Conductor, Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal,
+};
+use crate::medium::ConstantMedium;
+use crate::motion::{Animated, Keyframe};
+use crate::number_stuff::Rng;
+use crate::obj::load_obj;
+use crate::principled::{Principled, PrincipledInputs};
+use crate::quad::{BoxShape, Quad};
+use crate::ray_trace::{Hittable, HittableList, Identified};
+use crate::spectrum::Dispersion;
+use crate::sphere::{MovingSphere, Sphere};
+use crate::texture::{
+    CheckerTexture, ImageTexture, MarbleTexture, SolidColor, Texture, UvCheckerTexture,
+    ValueNoiseTexture, WoodTexture,
+};
+use crate::triangle::Triangle;
+use crate::vec3::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// a world together with the camera that looks at it
+pub struct Scene {
+    pub camera: CameraSettings,
+    pub world: HittableList,
+    /// emitting spheres and quads, the renderer aims some rays at them
+    pub lights: HittableList,
+    pub environment: Arc<dyn Environment>,
+    /// in the order of the description, material IDs count from 1 in this order
+    pub materials: Vec<Arc<dyn Material>>,
+}
+
+/// Plain data version of an environment
+#[derive(Clone, Debug, PartialEq)]
+pub enum EnvironmentDescription {
+    Constant {
+        color: Color,
+    },
+    Gradient {
+        bottom: Color,
+        top: Color,
+    },
+    /// equirectangular Radiance .hdr file
+    Map {
+        file: PathBuf,
+        rotation_degrees: f64,
+        intensity: f64,
+    },
+}
+
+impl Default for EnvironmentDescription {
+    /// the white to light blue sky
+    fn default() -> Self {
+        EnvironmentDescription::Gradient {
+            bottom: Color::new(1.0, 1.0, 1.0),
+            top: Color::new(0.5, 0.7, 1.0),
+        }
+    }
+}
+
+impl EnvironmentDescription {
+    /// loads the map file if there is one
+    pub fn build(&self) -> Result<Arc<dyn Environment>, String> {
+        Ok(match self {
+            EnvironmentDescription::Constant { color } => {
+                Arc::new(ConstantEnvironment::new(*color))
+            }
+            EnvironmentDescription::Gradient { bottom, top } => {
+                Arc::new(GradientEnvironment::new(*bottom, *top))
+            }
+            EnvironmentDescription::Map {
+                file,
+                rotation_degrees,
+                intensity,
+            } => Arc::new(EnvironmentMap::new(
+                load_hdr(file)?,
+                *rotation_degrees,
+                *intensity,
+            )),
+        })
+    }
+}
+
+/// Plain data version of a texture, checkers can be made of other textures
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureDescription {
+    Solid {
+        color: Color,
+    },
+    /// cubes with edges of the given size
+    Checker {
+        even: Box<TextureDescription>,
+        odd: Box<TextureDescription>,
+        size: f64,
+    },
+    UvChecker {
+        even: Box<TextureDescription>,
+        odd: Box<TextureDescription>,
+        columns: f64,
+        rows: f64,
+    },
+    /// PNG, JPEG or Radiance .hdr file
+    Image {
+        file: PathBuf,
+    },
+    Marble {
+        dark: Color,
+        light: Color,
+        scale: f64,
+        turbulence: f64,
+    },
+    Wood {
+        dark: Color,
+        light: Color,
+        scale: f64,
+        turbulence: f64,
+    },
+    /// octaves should be at least 1
+    ValueNoise {
+        low: Color,
+        high: Color,
+        scale: f64,
+        octaves: u32,
+    },
+}
+
+impl From<Color> for TextureDescription {
+    fn from(color: Color) -> Self {
+        TextureDescription::Solid { color }
+    }
+}
+
+/// Textures built so far. Materials that use the same texture, like one that a scene
+/// file names, share it instead of loading the image or building the noise again.
+#[derive(Default)]
+pub struct TextureCache {
+    built: Vec<(TextureDescription, Arc<dyn Texture>)>,
+}
+
+impl TextureDescription {
+    /// Loads the image files the texture uses. Noise textures are built from the
+    /// generator, so the same seed builds the same noise every time. Textures in the
+    /// cache are taken from there.
+    pub fn build(
+        &self,
+        rng: &mut Rng,
+        cache: &mut TextureCache,
+    ) -> Result<Arc<dyn Texture>, String> {
+        if let Some((_, texture)) = cache.built.iter().find(|(built, _)| built == self) {
+            return Ok(texture.clone());
+        }
+        let texture: Arc<dyn Texture> = match self {
+            TextureDescription::Solid { color } => Arc::new(SolidColor::new(*color)),
+            TextureDescription::Checker { even, odd, size } => Arc::new(CheckerTexture::new(
+                even.build(rng, cache)?,
+                odd.build(rng, cache)?,
+                *size,
+            )),
+            TextureDescription::UvChecker {
+                even,
+                odd,
+                columns,
+                rows,
+            } => Arc::new(UvCheckerTexture::new(
+                even.build(rng, cache)?,
+                odd.build(rng, cache)?,
+                *columns,
+                *rows,
+            )),
+            TextureDescription::Image { file } => Arc::new(ImageTexture::new(load_image(file)?)),
+            TextureDescription::Marble {
+                dark,
+                light,
+                scale,
+                turbulence,
+            } => Arc::new(MarbleTexture::new(*dark, *light, *scale, *turbulence, rng)),
+            TextureDescription::Wood {
+                dark,
+                light,
+                scale,
+                turbulence,
+            } => Arc::new(WoodTexture::new(*dark, *light, *scale, *turbulence, rng)),
+            TextureDescription::ValueNoise {
+                low,
+                high,
+                scale,
+                octaves,
+            } => Arc::new(ValueNoiseTexture::new(*low, *high, *scale, *octaves, rng)),
+        };
+        cache.built.push((self.clone(), texture.clone()));
+        Ok(texture)
+    }
+}
+
+/// Plain data version of a material, what scene files are made of.
+/// Parameters are expected to be valid, `build` panics like the material constructors do.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MaterialDescription {
+    Lambertian {
+        texture: TextureDescription,
+    },
+    Metal {
+        texture: TextureDescription,
+        fuzziness: f64,
+    },
+    /// metal with a measured complex index of refraction eta + k i for every channel
+    Conductor {
+        eta: Color,
+        k: Color,
+        roughness: f64,
+        anisotropy: f64,
+    },
+    /// Glass, see `Dielectric`. A white tint is clear glass, tint_distance doesn't matter
+    /// then. Dispersion only shows in spectral renders.
+    Dielectric {
+        refraction_index: f64,
+        dispersion: Option<Dispersion>,
+        roughness: f64,
+        tint: Color,
+        tint_distance: f64,
+        thin_walled: bool,
+    },
+    DiffuseLight {
+        texture: TextureDescription,
+    },
+    /// scatters evenly in every direction, for `ObjectDescription::ConstantMedium`
+    Isotropic {
+        texture: TextureDescription,
+    },
+    Principled(Box<PrincipledDescription>),
+}
+
+/// Plain data version of `PrincipledInputs`, scalar inputs are gray textures
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrincipledDescription {
+    pub base_color: TextureDescription,
+    pub metallic: TextureDescription,
+    pub roughness: TextureDescription,
+    pub anisotropy: TextureDescription,
+    pub specular: TextureDescription,
+    pub specular_tint: TextureDescription,
+    pub sheen: TextureDescription,
+    pub sheen_tint: TextureDescription,
+    pub clearcoat: TextureDescription,
+    pub clearcoat_gloss: TextureDescription,
+    pub transmission: TextureDescription,
+    pub ior: f64,
+}
+
+impl Default for PrincipledDescription {
+    /// the defaults of `PrincipledInputs`
+    fn default() -> Self {
+        let gray = |value| Color::new(value, value, value).into();
+        Self {
+            base_color: gray(0.8),
+            metallic: gray(0.0),
+            roughness: gray(0.5),
+            anisotropy: gray(0.0),
+            specular: gray(0.5),
+            specular_tint: gray(0.0),
+            sheen: gray(0.0),
+            sheen_tint: gray(0.5),
+            clearcoat: gray(0.0),
+            clearcoat_gloss: gray(1.0),
+            transmission: gray(0.0),
+            ior: 1.5,
+        }
+    }
+}
+
+impl PrincipledDescription {
+    /// every texture input with the name scene files give it, base_color first
+    pub fn textures_mut(&mut self) -> [(&'static str, &mut TextureDescription); 11] {
+        [
+            ("base_color", &mut self.base_color),
+            ("metallic", &mut self.metallic),
+            ("roughness", &mut self.roughness),
+            ("anisotropy", &mut self.anisotropy),
+            ("specular", &mut self.specular),
+            ("specular_tint", &mut self.specular_tint),
+            ("sheen", &mut self.sheen),
+            ("sheen_tint", &mut self.sheen_tint),
+            ("clearcoat", &mut self.clearcoat),
+            ("clearcoat_gloss", &mut self.clearcoat_gloss),
+            ("transmission", &mut self.transmission),
+        ]
+    }
+
+    pub fn build(
+        &self,
+        rng: &mut Rng,
+        cache: &mut TextureCache,
+    ) -> Result<PrincipledInputs, String> {
+        Ok(PrincipledInputs {
+            base_color: self.base_color.build(rng, cache)?,
+            metallic: self.metallic.build(rng, cache)?,
+            roughness: self.roughness.build(rng, cache)?,
+            anisotropy: self.anisotropy.build(rng, cache)?,
+            specular: self.specular.build(rng, cache)?,
+            specular_tint: self.specular_tint.build(rng, cache)?,
+            sheen: self.sheen.build(rng, cache)?,
+            sheen_tint: self.sheen_tint.build(rng, cache)?,
+            clearcoat: self.clearcoat.build(rng, cache)?,
+            clearcoat_gloss: self.clearcoat_gloss.build(rng, cache)?,
+            transmission: self.transmission.build(rng, cache)?,
+            ior: self.ior,
+        })
+    }
+}
+
+impl MaterialDescription {
+    /// clear smooth glass without dispersion
+    pub fn dielectric(refraction_index: f64) -> Self {
+        MaterialDescription::Dielectric {
+            refraction_index,
+            dispersion: None,
+            roughness: 0.0,
+            tint: Color::new(1.0, 1.0, 1.0),
+            tint_distance: 1.0,
+            thin_walled: false,
+        }
+    }
+
+    /// errors come from loading texture images, noise textures are built from the generator
+    /// unless the cache has them
+    pub fn build(
+        &self,
+        rng: &mut Rng,
+        cache: &mut TextureCache,
+    ) -> Result<Arc<dyn Material>, String> {
+        Ok(match self {
+            MaterialDescription::Lambertian { texture } => {
+                Arc::new(Lambertian::new(texture.build(rng, cache)?))
+            }
+            MaterialDescription::Metal { texture, fuzziness } => {
+                Arc::new(Metal::new(texture.build(rng, cache)?, *fuzziness))
+            }
+            MaterialDescription::Conductor {
+                eta,
+                k,
+                roughness,
+                anisotropy,
+            } => Arc::new(Conductor::new(*eta, *k, *roughness, *anisotropy)),
+            MaterialDescription::Dielectric {
+                refraction_index,
+                dispersion,
+                roughness,
+                tint,
+                tint_distance,
+                thin_walled,
+            } => Arc::new(
+                Dielectric::new(*refraction_index)
+                    .with_dispersion(*dispersion)
+                    .with_roughness(*roughness)
+                    .with_tint(*tint, *tint_distance)
+                    .with_thin_walled(*thin_walled),
+            ),
+            MaterialDescription::DiffuseLight { texture } => {
+                Arc::new(DiffuseLight::new(texture.build(rng, cache)?))
+            }
+            MaterialDescription::Isotropic { texture } => {
+                Arc::new(Isotropic::new(texture.build(rng, cache)?))
+            }
+            MaterialDescription::Principled(description) => {
+                Arc::new(Principled::new(description.build(rng, cache)?))
+            }
+        })
+    }
+}
+
+/// Plain data version of an object, materials are referenced by name
+#[derive(Clone, Debug, PartialEq)]
+pub enum ObjectDescription {
+    Sphere {
+        center: Point3,
+        radius: f64,
+        material: String,
+    },
+    /// at center0 at time0 and at center1 at time1, still before and after
+    MovingSphere {
+        center0: Point3,
+        center1: Point3,
+        time0: f64,
+        time1: f64,
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        uvs: Option<[(f64, f64); 3]>,
+        material: String,
+    },
+    /// parallelogram with corners corner, corner + u, corner + u + v and corner + v
+    Quad {
+        corner: Point3,
+        u: Vec3,
+        v: Vec3,
+        material: String,
+    },
+    /// box with sides parallel to the axes between two opposite corners
+    BoxShape {
+        min: Point3,
+        max: Point3,
+        material: String,
+    },
+    /// Wavefront OBJ file, uses its MTL materials unless a material is given
+    Mesh {
+        file: PathBuf,
+        material: Option<String>,
+    },
+    /// Fog or smoke filling a closed boundary, the material is usually `Isotropic`.
+    /// Only the shape of the boundary matters, not its material.
+    ConstantMedium {
+        boundary: Box<ObjectDescription>,
+        density: f64,
+        material: String,
+    },
+    /// Another object scaled, then rotated around x, y and z (in degrees), then moved.
+    /// Scale should not be 0 along any axis.
+    Instance {
+        object: Box<ObjectDescription>,
+        scale: Vec3,
+        rotation_degrees: Vec3,
+        translation: Vec3,
+    },
+    /// Another object moved, turned and scaled through keyframes, like an instance
+    /// that changes over time. Scales should not be 0 or change sign along any axis.
+    Animated {
+        object: Box<ObjectDescription>,
+        keyframes: Vec<Keyframe>,
+    },
+}
+
+impl ObjectDescription {
+    /// the transform of an `Instance`, identity for everything else
+    pub fn transform(&self) -> Matrix4 {
+        match self {
+            ObjectDescription::Instance {
+                scale,
+                rotation_degrees,
+                translation,
+                ..
+            } => placement(*scale, *rotation_degrees, *translation),
+            _ => Matrix4::identity(),
+        }
+    }
+}
+
+/// Everything a scene file describes. Can be built into a `Scene` or written back
+/// to a file with `scene_file::write_scene`.
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct SceneDescription {
+    pub camera: CameraSettings,
+    pub environment: EnvironmentDescription,
+    /// named materials in the order they were defined
+    pub materials: Vec<(String, MaterialDescription)>,
+    pub objects: Vec<ObjectDescription>,
+}
+
+impl SceneDescription {
+    /// Loads the files the scene references, errors are about those.
+    /// Panics if an object uses a material that isn't defined,
+    /// parsed descriptions are already checked for that.
+    /// Objects get IDs from 1 in the order of the description, all parts of a mesh share one.
+    /// Materials that use the same texture share it.
+    pub fn build(&self, rng: &mut Rng) -> Result<Scene, String> {
+        let mut textures = TextureCache::default();
+        let built = self
+            .materials
+            .iter()
+            .map(|(_, material)| material.build(rng, &mut textures))
+            .collect::<Result<Vec<Arc<dyn Material>>, String>>()?;
+        let materials: HashMap<&str, Arc<dyn Material>> = self
+            .materials
+            .iter()
+            .zip(&built)
+            .map(|((name, _), material)| (name.as_str(), material.clone()))
+            .collect();
+        let material = |name: &str| {
+            materials
+                .get(name)
+                .unwrap_or_else(|| panic!("material `{}` is not defined", name))
+                .clone()
+        };
+
+        let mut world = HittableList::default();
+        let mut lights = HittableList::default();
+        for (id, object) in (1..).zip(&self.objects) {
+            for hittable in build_object(object, &material)? {
+                let hittable: Arc<dyn Hittable> = Arc::new(Identified::new(id, hittable));
+                if self.is_sampled_light(object) {
+                    lights.add(hittable.clone());
+                }
+                world.add(hittable);
+            }
+        }
+        Ok(Scene {
+            camera: self.camera,
+            world,
+            lights,
+            environment: self.environment.build()?,
+            materials: built,
+        })
+    }
+
+    /// spheres and quads made of diffuse light, the objects that can pick directions towards them
+    fn is_sampled_light(&self, object: &ObjectDescription) -> bool {
+        let name = match object {
+            ObjectDescription::Sphere { material, .. } => material,
+            ObjectDescription::Quad { material, .. } => material,
+            _ => return false,
+        };
+        self.materials.iter().any(|(material_name, material)| {
+            material_name == name && matches!(material, MaterialDescription::DiffuseLight { .. })
+        })
+    }
+}
+
+/// Builds one object, or one mesh per group for OBJ files.
+/// `material` gives the material with the name.
+fn build_object(
+    object: &ObjectDescription,
+    material: &dyn Fn(&str) -> Arc<dyn Material>,
+) -> Result<Vec<Arc<dyn Hittable>>, String> {
+    let hittable: Arc<dyn Hittable> = match object {
+        ObjectDescription::Sphere {
+            center,
+            radius,
+            material: name,
+        } => Arc::new(Sphere::new(*center, *radius, material(name))),
+        ObjectDescription::MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material: name,
+        } => Arc::new(MovingSphere::new(
+            (*center0, *time0),
+            (*center1, *time1),
+            *radius,
+            material(name),
+        )),
+        ObjectDescription::Triangle {
+            vertices,
+            normals,
+            uvs,
+            material: name,
+        } => {
+            let mut triangle = Triangle::new(*vertices, material(name));
+            if let Some(normals) = normals {
+                triangle = triangle.with_normals(*normals);
+            }
+            if let Some(uvs) = uvs {
+                triangle = triangle.with_uvs(*uvs);
+            }
+            Arc::new(triangle)
+        }
+        ObjectDescription::Quad {
+            corner,
+            u,
+            v,
+            material: name,
+        } => Arc::new(Quad::new(*corner, *u, *v, material(name))),
+        ObjectDescription::BoxShape {
+            min,
+            max,
+            material: name,
+        } => Arc::new(BoxShape::new(*min, *max, material(name))),
+        ObjectDescription::Mesh {
+            file,
+            material: name,
+        } => {
+            let model = load_obj(file).map_err(|e| e.to_string())?;
+            let meshes = match name {
+                Some(name) => model.build_with_material(material(name)),
+                None => model.build(Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))))?,
+            };
+            return Ok(meshes.objects().to_vec());
+        }
+        ObjectDescription::ConstantMedium {
+            boundary,
+            density,
+            material: name,
+        } => {
+            let mut parts = build_object(boundary, material)?;
+            let boundary: Arc<dyn Hittable> = match parts.len() {
+                1 => parts.remove(0),
+                _ => {
+                    let mut list = HittableList::default();
+                    for part in parts {
+                        list.add(part);
+                    }
+                    Arc::new(list)
+                }
+            };
+            Arc::new(ConstantMedium::new(boundary, *density, material(name)))
+        }
+        ObjectDescription::Instance { object: inner, .. } => {
+            // every mesh group gets its own instance so they stay separate for the BVH
+            let transform = object.transform();
+            return build_object(inner, material)?
+                .into_iter()
+                .map(|part| Ok(Arc::new(Instance::new(part, transform)?) as Arc<dyn Hittable>))
+                .collect();
+        }
+        ObjectDescription::Animated {
+            object: inner,
+            keyframes,
+        } => {
+            return build_object(inner, material)?
+                .into_iter()
+                .map(|part| {
+                    Ok(Arc::new(Animated::new(part, keyframes.clone())?) as Arc<dyn Hittable>)
+                })
+                .collect();
+        }
+    };
+    Ok(vec![hittable])
+}
+
+/// scenes that are built in code and can be picked by name
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BuiltinScene {
+    /// the random spheres from the cover of Ray Tracing in One Weekend
+    #[default]
+    RandomSpheres,
+    /// the random spheres with diffuse ones jumping while the shutter is open
+    BouncingSpheres,
+    /// closed room lit only by a ceiling light
+    CornellBox,
+}
+
+impl BuiltinScene {
+    pub const ALL: [BuiltinScene; 3] = [
+        BuiltinScene::RandomSpheres,
+        BuiltinScene::BouncingSpheres,
+        BuiltinScene::CornellBox,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuiltinScene::RandomSpheres => "random-spheres",
+            BuiltinScene::BouncingSpheres => "bouncing-spheres",
+            BuiltinScene::CornellBox => "cornell-box",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scene| scene.name() == name)
+    }
+
+    /// random scenes take their layout from the generator
+    pub fn description(&self, rng: &mut Rng) -> SceneDescription {
+        match self {
+            BuiltinScene::RandomSpheres => generate_scene_description(rng),
+            BuiltinScene::BouncingSpheres => bouncing_spheres_description(rng),
+            BuiltinScene::CornellBox => cornell_box_description(),
+        }
+    }
+
+    pub fn build(&self, rng: &mut Rng) -> Scene {
+        self.description(rng)
+            .build(rng)
+            .expect("built in scenes don't load files")
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_textures_are_built_once() {
+        let marble = TextureDescription::Marble {
+            dark: Color::new(0.1, 0.1, 0.1),
+            light: Color::new(0.9, 0.9, 0.9),
+            scale: 4.0,
+            turbulence: 10.0,
+        };
+        let checker = TextureDescription::Checker {
+            even: Box::new(marble.clone()),
+            odd: Box::new(Color::new(1.0, 1.0, 1.0).into()),
+            size: 1.0,
+        };
+        let mut rng = Rng::new(1);
+        let mut cache = TextureCache::default();
+        let first = marble.build(&mut rng, &mut cache).unwrap();
+        checker.build(&mut rng, &mut cache).unwrap();
+        // the marble, the white and the checker
+        assert_eq!(cache.built.len(), 3);
+        let again = marble.build(&mut rng, &mut cache).unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+        let elsewhere = marble
+            .build(&mut rng, &mut TextureCache::default())
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first, &elsewhere));
+    }
+}
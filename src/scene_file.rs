@@ -0,0 +1,1722 @@
+//! Text format for scenes. Every line is a statement, `#` starts a comment:
+//!
+//! ```text
+//! camera look_from=13,2,3 look_at=0,0,0 vfov=30 aperture=0.1 focus_dist=10 shutter_open=0 shutter_close=1
+//! material ground lambertian color=0.5,0.5,0.5
+//! material mirror metal color=0.7,0.6,0.5 fuzziness=0
+//! material gold conductor eta=0.143,0.374,1.442 k=3.983,2.385,1.603 roughness=0.3 anisotropy=0
+//! material glass dielectric refraction_index=1.5
+//! material lamp diffuse_light color=4,4,4
+//! material paint principled base_color=0.8,0.1,0.1 metallic=0 roughness=0.4 clearcoat=1  # any input can be a texture
+//! texture tiles checker even=0.2,0.3,0.1 odd=0.9,0.9,0.9 size=0.5
+//! texture grid uv_checker even=0,0,0 odd=tiles columns=20 rows=10
+//! texture earth image file=earthmap.jpg
+//! texture stone marble dark=0.1,0.1,0.1 light=0.9,0.9,0.9 scale=4 turbulence=10
+//! texture oak wood dark=0.35,0.2,0.1 light=0.7,0.5,0.3 scale=8 turbulence=1
+//! texture clouds value_noise low=0.3,0.5,0.9 high=1,1,1 scale=2 octaves=5
+//! material globe lambertian color=earth  # a texture name works wherever a color does
+//! background color=0,0,0  # or `background sky`, the default
+//! background gradient bottom=1,1,1 top=0.5,0.7,1
+//! background map file=studio.hdr rotation=90 intensity=1.5
+//! sphere center=0,-1000,0 radius=1000 material=ground
+//! material smoke isotropic color=0.8,0.8,0.8
+//! medium density=0.5 material=smoke sphere center=0,1,0 radius=1  # any object gives the boundary
+//! moving_sphere center0=0,1,0 center1=0,1.5,0 time0=0 time1=1 radius=0.5 material=lamp
+//! triangle v0=0,0,0 v1=1,0,0 v2=0,1,0 n0=0,0,1 n1=0,0,1 n2=0,0,1 uv0=0,0 uv1=1,0 uv2=0,1 material=glass
+//! quad corner=-1,0,-1 u=0,0,2 v=2,0,0 material=ground  # front side is where u turns counterclockwise to v
+//! box min=0,0,0 max=1,2,1 material=mirror
+//! mesh file=teapot.obj  # uses the MTL materials unless material= is given
+//! instance scale=2,2,2 rotate=0,45,0 translate=1,0,-3 mesh file=teapot.obj  # scaled, then rotated around x, y, z, then moved
+//! animated time0=0 time1=1 rotate1=0,90,0 translate1=0,2,0 box min=0,0,0 max=1,1,1 material=mirror  # like instance, linear between keyframes
+//! ```
+//!
+//! Camera parameters that are left out keep their defaults, textures have to be defined
+//! before materials use them and materials before objects use them.
+
+use crate::camera::CameraSettings;
+use crate::motion::{check_keyframes, Keyframe};
+use crate::scene::{
+    EnvironmentDescription, MaterialDescription, ObjectDescription, PrincipledDescription,
+    SceneDescription, TextureDescription,
+};
+use crate::spectrum::{Dispersion, D_LINE};
+use crate::vec3::*;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneFileError {
+    /// line numbers start from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SceneFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SceneFileError {}
+
+fn error<T>(line: usize, message: String) -> Result<T, SceneFileError> {
+    Err(SceneFileError { line, message })
+}
+
+/// key=value pairs of one statement, every key has to be used exactly once
+struct Parameters<'a> {
+    line: usize,
+    statement: &'a str,
+    values: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Parameters<'a> {
+    fn parse(line: usize, statement: &'a str, tokens: &[&'a str]) -> Result<Self, SceneFileError> {
+        let mut values: Vec<(&str, &str)> = Vec::new();
+        for token in tokens {
+            let Some((key, value)) = token.split_once('=') else {
+                return error(line, format!("expected key=value but got `{}`", token));
+            };
+            if values.iter().any(|(k, _)| *k == key) {
+                return error(line, format!("`{}` is given twice", key));
+            }
+            values.push((key, value));
+        }
+        Ok(Self {
+            line,
+            statement,
+            values,
+        })
+    }
+
+    fn take(&mut self, key: &str) -> Option<&'a str> {
+        let index = self.values.iter().position(|(k, _)| *k == key)?;
+        Some(self.values.remove(index).1)
+    }
+
+    fn number(&mut self, key: &str) -> Result<Option<f64>, SceneFileError> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(value) => parse_number(self.line, key, value).map(Some),
+        }
+    }
+
+    /// true or false
+    fn flag(&mut self, key: &str) -> Result<Option<bool>, SceneFileError> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(value) => match value.parse() {
+                Ok(flag) => Ok(Some(flag)),
+                Err(_) => error(
+                    self.line,
+                    format!("`{}` should be true or false but was `{}`", key, value),
+                ),
+            },
+        }
+    }
+
+    /// comma separated numbers like 1,2,3
+    fn numbers<const N: usize>(&mut self, key: &str) -> Result<Option<[f64; N]>, SceneFileError> {
+        match self.take(key) {
+            None => Ok(None),
+            Some(value) => parse_numbers(self.line, key, value).map(Some),
+        }
+    }
+
+    fn vec3(&mut self, key: &str) -> Result<Option<Vec3>, SceneFileError> {
+        Ok(self.numbers::<3>(key)?.map(|[x, y, z]| Vec3::new(x, y, z)))
+    }
+
+    fn vec2(&mut self, key: &str) -> Result<Option<(f64, f64)>, SceneFileError> {
+        Ok(self.numbers::<2>(key)?.map(|[u, v]| (u, v)))
+    }
+
+    /// three values like n0, n1, n2 that are either all given or all left out
+    fn per_vertex<T>(
+        &mut self,
+        prefix: &str,
+        get: impl Fn(&mut Self, &str) -> Result<Option<T>, SceneFileError>,
+    ) -> Result<Option<[T; 3]>, SceneFileError> {
+        let keys = [0, 1, 2].map(|i| format!("{}{}", prefix, i));
+        match (
+            get(self, &keys[0])?,
+            get(self, &keys[1])?,
+            get(self, &keys[2])?,
+        ) {
+            (Some(a), Some(b), Some(c)) => Ok(Some([a, b, c])),
+            (None, None, None) => Ok(None),
+            _ => error(
+                self.line,
+                format!(
+                    "{}: {}, {} and {} should be given together",
+                    self.statement, keys[0], keys[1], keys[2]
+                ),
+            ),
+        }
+    }
+
+    /// either a color like 1,0,0, a number for gray or the name of a texture that is
+    /// already defined
+    fn texture(
+        &mut self,
+        key: &str,
+        textures: &HashMap<String, TextureDescription>,
+    ) -> Result<Option<TextureDescription>, SceneFileError> {
+        let Some(value) = self.take(key) else {
+            return Ok(None);
+        };
+        if value.contains(',') {
+            let [r, g, b] = parse_numbers::<3>(self.line, key, value)?;
+            return Ok(Some(Color::new(r, g, b).into()));
+        }
+        // texture names never read as numbers, so this is a gray or a mistake
+        if value.parse::<f64>().is_ok() {
+            let gray = parse_number(self.line, key, value)?;
+            return Ok(Some(Color::new(gray, gray, gray).into()));
+        }
+        match textures.get(value) {
+            Some(texture) => Ok(Some(texture.clone())),
+            None => error(self.line, format!("unknown texture `{}`", value)),
+        }
+    }
+
+    fn required_texture(
+        &mut self,
+        key: &str,
+        textures: &HashMap<String, TextureDescription>,
+    ) -> Result<TextureDescription, SceneFileError> {
+        self.texture(key, textures)?
+            .ok_or_else(|| self.missing(key))
+    }
+
+    /// name of a material that is already defined
+    fn material(&mut self, defined: &HashSet<String>) -> Result<String, SceneFileError> {
+        let name = self.required("material")?;
+        self.defined_material(name, defined)
+    }
+
+    fn optional_material(
+        &mut self,
+        defined: &HashSet<String>,
+    ) -> Result<Option<String>, SceneFileError> {
+        match self.take("material") {
+            Some(name) => self.defined_material(name, defined).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn defined_material(
+        &self,
+        name: &str,
+        defined: &HashSet<String>,
+    ) -> Result<String, SceneFileError> {
+        if !defined.contains(name) {
+            return error(self.line, format!("unknown material `{}`", name));
+        }
+        Ok(name.to_string())
+    }
+
+    fn missing(&self, key: &str) -> SceneFileError {
+        SceneFileError {
+            line: self.line,
+            message: format!("{} is missing `{}`", self.statement, key),
+        }
+    }
+
+    fn required_number(&mut self, key: &str) -> Result<f64, SceneFileError> {
+        self.number(key)?.ok_or_else(|| self.missing(key))
+    }
+
+    fn required_vec3(&mut self, key: &str) -> Result<Vec3, SceneFileError> {
+        self.vec3(key)?.ok_or_else(|| self.missing(key))
+    }
+
+    fn required(&mut self, key: &str) -> Result<&'a str, SceneFileError> {
+        self.take(key).ok_or_else(|| self.missing(key))
+    }
+
+    fn check(&self, ok: bool, message: &str) -> Result<(), SceneFileError> {
+        match ok {
+            true => Ok(()),
+            false => error(self.line, format!("{}: {}", self.statement, message)),
+        }
+    }
+
+    /// errors on keys nobody asked for
+    fn finish(self) -> Result<(), SceneFileError> {
+        match self.values.first() {
+            None => Ok(()),
+            Some((key, _)) => error(
+                self.line,
+                format!("unknown parameter `{}` for {}", key, self.statement),
+            ),
+        }
+    }
+}
+
+fn parse_number(line: usize, key: &str, value: &str) -> Result<f64, SceneFileError> {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => error(
+            line,
+            format!("`{}` should be a number but was `{}`", key, value),
+        ),
+    }
+}
+
+fn parse_numbers<const N: usize>(
+    line: usize,
+    key: &str,
+    value: &str,
+) -> Result<[f64; N], SceneFileError> {
+    let parts: Vec<&str> = value.split(',').collect();
+    if parts.len() != N {
+        let example: Vec<String> = (1..=N).map(|i| i.to_string()).collect();
+        return error(
+            line,
+            format!(
+                "`{}` should be {} numbers like {} but was `{}`",
+                key,
+                N,
+                example.join(","),
+                value
+            ),
+        );
+    }
+    let mut numbers = [0.0; N];
+    for (number, part) in numbers.iter_mut().zip(parts) {
+        *number = parse_number(line, key, part)?;
+    }
+    Ok(numbers)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+pub fn parse_scene(text: &str) -> Result<SceneDescription, SceneFileError> {
+    let mut scene = SceneDescription::default();
+    let mut seen_camera = false;
+    let mut seen_background = false;
+    let mut material_names = HashSet::new();
+    let mut textures = HashMap::new();
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line = index + 1;
+        let content = raw_line.split('#').next().unwrap_or("");
+        let tokens: Vec<&str> = content.split_whitespace().collect();
+        let Some((&keyword, rest)) = tokens.split_first() else {
+            continue;
+        };
+        match keyword {
+            "camera" => {
+                if seen_camera {
+                    return error(line, String::from("camera is defined twice"));
+                }
+                seen_camera = true;
+                let mut params = Parameters::parse(line, "camera", rest)?;
+                scene.camera = parse_camera(&mut params)?;
+                params.finish()?;
+            }
+            "background" => {
+                if seen_background {
+                    return error(line, String::from("background is defined twice"));
+                }
+                seen_background = true;
+                scene.environment = parse_background(line, rest)?;
+            }
+            "texture" => {
+                let [name, kind, rest @ ..] = rest else {
+                    return error(
+                        line,
+                        String::from("expected `texture <name> <type> key=value...`"),
+                    );
+                };
+                if !is_valid_name(name) {
+                    return error(line, format!("`{}` is not a valid texture name", name));
+                }
+                if name.parse::<f64>().is_ok() {
+                    return error(line, format!("texture name `{}` reads as a gray", name));
+                }
+                if textures.contains_key(*name) {
+                    return error(line, format!("texture `{}` is defined twice", name));
+                }
+                let statement = format!("{} texture", kind);
+                let mut params = Parameters::parse(line, &statement, rest)?;
+                let texture = parse_texture(&mut params, kind, &textures)?;
+                params.finish()?;
+                textures.insert(name.to_string(), texture);
+            }
+            "material" => {
+                let [name, kind, rest @ ..] = rest else {
+                    return error(
+                        line,
+                        String::from("expected `material <name> <type> key=value...`"),
+                    );
+                };
+                if !is_valid_name(name) {
+                    return error(line, format!("`{}` is not a valid material name", name));
+                }
+                if !material_names.insert(name.to_string()) {
+                    return error(line, format!("material `{}` is defined twice", name));
+                }
+                let statement = format!("{} material", kind);
+                let mut params = Parameters::parse(line, &statement, rest)?;
+                let material = parse_material(&mut params, kind, &textures)?;
+                params.finish()?;
+                scene.materials.push((name.to_string(), material));
+            }
+            _ => {
+                let object = parse_object(line, keyword, rest, &material_names)?;
+                scene.objects.push(object);
+            }
+        }
+    }
+    Ok(scene)
+}
+
+fn parse_object(
+    line: usize,
+    keyword: &str,
+    rest: &[&str],
+    material_names: &HashSet<String>,
+) -> Result<ObjectDescription, SceneFileError> {
+    match keyword {
+        "sphere" => {
+            let mut params = Parameters::parse(line, "sphere", rest)?;
+            let center = params.required_vec3("center")?;
+            let radius = params.required_number("radius")?;
+            params.check(radius > 0.0, "radius should be positive")?;
+            let material = params.material(material_names)?;
+            params.finish()?;
+            Ok(ObjectDescription::Sphere {
+                center,
+                radius,
+                material,
+            })
+        }
+        "moving_sphere" => {
+            let mut params = Parameters::parse(line, "moving_sphere", rest)?;
+            let center0 = params.required_vec3("center0")?;
+            let center1 = params.required_vec3("center1")?;
+            let time0 = params.number("time0")?.unwrap_or(0.0);
+            let time1 = params.number("time1")?.unwrap_or(1.0);
+            let radius = params.required_number("radius")?;
+            params.check(radius > 0.0, "radius should be positive")?;
+            params.check(time1 > time0, "time1 should be after time0")?;
+            let material = params.material(material_names)?;
+            params.finish()?;
+            Ok(ObjectDescription::MovingSphere {
+                center0,
+                center1,
+                time0,
+                time1,
+                radius,
+                material,
+            })
+        }
+        "triangle" => {
+            let mut params = Parameters::parse(line, "triangle", rest)?;
+            let vertices = [
+                params.required_vec3("v0")?,
+                params.required_vec3("v1")?,
+                params.required_vec3("v2")?,
+            ];
+            let normals = params.per_vertex("n", Parameters::vec3)?;
+            let uvs = params.per_vertex("uv", Parameters::vec2)?;
+            let material = params.material(material_names)?;
+            params.finish()?;
+            Ok(ObjectDescription::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material,
+            })
+        }
+        "quad" => {
+            let mut params = Parameters::parse(line, "quad", rest)?;
+            let corner = params.required_vec3("corner")?;
+            let u = params.required_vec3("u")?;
+            let v = params.required_vec3("v")?;
+            params.check(
+                !cross(u, v).really_small(),
+                "u and v should not be parallel",
+            )?;
+            let material = params.material(material_names)?;
+            params.finish()?;
+            Ok(ObjectDescription::Quad {
+                corner,
+                u,
+                v,
+                material,
+            })
+        }
+        "box" => {
+            let mut params = Parameters::parse(line, "box", rest)?;
+            let min = params.required_vec3("min")?;
+            let max = params.required_vec3("max")?;
+            params.check(
+                min.x() < max.x() && min.y() < max.y() && min.z() < max.z(),
+                "min should be below max along every axis",
+            )?;
+            let material = params.material(material_names)?;
+            params.finish()?;
+            Ok(ObjectDescription::BoxShape { min, max, material })
+        }
+        "mesh" => {
+            let mut params = Parameters::parse(line, "mesh", rest)?;
+            let file = PathBuf::from(params.required("file")?);
+            let material = params.optional_material(material_names)?;
+            params.finish()?;
+            Ok(ObjectDescription::Mesh { file, material })
+        }
+        "medium" => {
+            let (params, kind, boundary_tokens) = split_wrapper(line, "medium", rest)?;
+            let mut params = Parameters::parse(line, "medium", params)?;
+            let density = params.required_number("density")?;
+            params.check(density > 0.0, "density should be positive")?;
+            let material = params.material(material_names)?;
+            params.finish()?;
+            // the boundary only gives the shape, it may leave out its material
+            let default_material = format!("material={}", material);
+            let mut boundary_tokens = boundary_tokens.to_vec();
+            if !boundary_tokens.iter().any(|t| t.starts_with("material=")) {
+                boundary_tokens.push(&default_material);
+            }
+            let boundary = parse_object(line, kind, &boundary_tokens, material_names)?;
+            Ok(ObjectDescription::ConstantMedium {
+                boundary: Box::new(boundary),
+                density,
+                material,
+            })
+        }
+        "instance" => {
+            let (params, kind, object_tokens) = split_wrapper(line, "instance", rest)?;
+            let mut params = Parameters::parse(line, "instance", params)?;
+            let scale = params.vec3("scale")?.unwrap_or(Vec3::new(1.0, 1.0, 1.0));
+            let rotation_degrees = params.vec3("rotate")?.unwrap_or_default();
+            let translation = params.vec3("translate")?.unwrap_or_default();
+            // rotations and moves can always be undone, scales close to 0 can't
+            params.check(
+                Matrix4::scale(scale).inverse().is_some(),
+                "scale should not be 0 along any axis",
+            )?;
+            params.finish()?;
+            let object = parse_object(line, kind, object_tokens, material_names)?;
+            Ok(ObjectDescription::Instance {
+                object: Box::new(object),
+                scale,
+                rotation_degrees,
+                translation,
+            })
+        }
+        "animated" => {
+            let (params, kind, object_tokens) = split_wrapper(line, "animated", rest)?;
+            let mut params = Parameters::parse(line, "animated", params)?;
+            // keyframes are numbered from 0 up, each one starts with its time
+            let mut keyframes = Vec::new();
+            while let Some(time) = params.number(&format!("time{}", keyframes.len()))? {
+                let i = keyframes.len();
+                let mut keyframe = Keyframe::at(time);
+                if let Some(scale) = params.vec3(&format!("scale{}", i))? {
+                    keyframe = keyframe.with_scale(scale);
+                }
+                if let Some(rotation_degrees) = params.vec3(&format!("rotate{}", i))? {
+                    keyframe = keyframe.with_rotation_degrees(rotation_degrees);
+                }
+                if let Some(translation) = params.vec3(&format!("translate{}", i))? {
+                    keyframe = keyframe.with_translation(translation);
+                }
+                keyframes.push(keyframe);
+            }
+            params.check(!keyframes.is_empty(), "time0 is missing")?;
+            if let Err(message) = check_keyframes(&keyframes) {
+                return error(line, format!("{}: {}", params.statement, message));
+            }
+            params.finish()?;
+            let object = parse_object(line, kind, object_tokens, material_names)?;
+            Ok(ObjectDescription::Animated {
+                object: Box::new(object),
+                keyframes,
+            })
+        }
+        _ => error(line, format!("unknown statement `{}`", keyword)),
+    }
+}
+
+/// Splits `<wrapper> key=value... <object statement>` into the wrapper's parameters,
+/// the keyword of the wrapped object and the object's parameters
+fn split_wrapper<'t, 'a>(
+    line: usize,
+    wrapper: &str,
+    rest: &'t [&'a str],
+) -> Result<(&'t [&'a str], &'a str, &'t [&'a str]), SceneFileError> {
+    match rest.iter().position(|token| !token.contains('=')) {
+        Some(kind_index) => Ok((
+            &rest[..kind_index],
+            rest[kind_index],
+            &rest[kind_index + 1..],
+        )),
+        None => error(
+            line,
+            format!("expected `{} key=value... <object statement>`", wrapper),
+        ),
+    }
+}
+
+fn parse_background(line: usize, rest: &[&str]) -> Result<EnvironmentDescription, SceneFileError> {
+    let (kind, rest) = match rest.split_first() {
+        Some((kind, rest)) if !kind.contains('=') => (*kind, rest),
+        // `background color=...` is short for a constant color
+        _ => ("color", rest),
+    };
+    let statement = format!("{} background", kind);
+    let mut params = Parameters::parse(line, &statement, rest)?;
+    let environment = match kind {
+        "sky" => EnvironmentDescription::default(),
+        "color" => EnvironmentDescription::Constant {
+            color: params.required_vec3("color")?,
+        },
+        "gradient" => EnvironmentDescription::Gradient {
+            bottom: params.required_vec3("bottom")?,
+            top: params.required_vec3("top")?,
+        },
+        "map" => {
+            let file = PathBuf::from(params.required("file")?);
+            let rotation_degrees = params.number("rotation")?.unwrap_or(0.0);
+            let intensity = params.number("intensity")?.unwrap_or(1.0);
+            params.check(intensity >= 0.0, "intensity should not be negative")?;
+            EnvironmentDescription::Map {
+                file,
+                rotation_degrees,
+                intensity,
+            }
+        }
+        _ => return error(line, format!("unknown background type `{}`", kind)),
+    };
+    params.finish()?;
+    Ok(environment)
+}
+
+fn parse_camera(params: &mut Parameters<'_>) -> Result<CameraSettings, SceneFileError> {
+    let default = CameraSettings::default();
+    let camera = CameraSettings {
+        vertical_fov_degrees: params
+            .number("vfov")?
+            .unwrap_or(default.vertical_fov_degrees),
+        aspect_ratio: params
+            .number("aspect_ratio")?
+            .unwrap_or(default.aspect_ratio),
+        look_from: params.vec3("look_from")?.unwrap_or(default.look_from),
+        look_at: params.vec3("look_at")?.unwrap_or(default.look_at),
+        vec_up: params.vec3("vec_up")?.unwrap_or(default.vec_up),
+        aperture: params.number("aperture")?.unwrap_or(default.aperture),
+        focus_dist: params.number("focus_dist")?.unwrap_or(default.focus_dist),
+        shutter_open: params
+            .number("shutter_open")?
+            .unwrap_or(default.shutter_open),
+        shutter_close: params
+            .number("shutter_close")?
+            .unwrap_or(default.shutter_close),
+    };
+    let vfov = camera.vertical_fov_degrees;
+    params.check(
+        vfov > 0.0 && vfov < 180.0,
+        "vfov should be between 0 and 180",
+    )?;
+    params.check(camera.aspect_ratio > 0.0, "aspect_ratio should be positive")?;
+    params.check(camera.aperture >= 0.0, "aperture should not be negative")?;
+    params.check(camera.focus_dist > 0.0, "focus_dist should be positive")?;
+    params.check(
+        camera.shutter_close >= camera.shutter_open,
+        "shutter_close should not be before shutter_open",
+    )?;
+    let view_direction = camera.look_at - camera.look_from;
+    params.check(
+        !view_direction.really_small(),
+        "look_from and look_at should be different points",
+    )?;
+    params.check(
+        !cross(view_direction, camera.vec_up).really_small(),
+        "vec_up should not be parallel to the view direction",
+    )?;
+    Ok(camera)
+}
+
+fn parse_texture(
+    params: &mut Parameters<'_>,
+    kind: &str,
+    textures: &HashMap<String, TextureDescription>,
+) -> Result<TextureDescription, SceneFileError> {
+    match kind {
+        "solid" => Ok(params.required_vec3("color")?.into()),
+        "checker" => {
+            let even = params.required_texture("even", textures)?;
+            let odd = params.required_texture("odd", textures)?;
+            let size = params.required_number("size")?;
+            params.check(size > 0.0, "size should be positive")?;
+            Ok(TextureDescription::Checker {
+                even: Box::new(even),
+                odd: Box::new(odd),
+                size,
+            })
+        }
+        "uv_checker" => {
+            let even = params.required_texture("even", textures)?;
+            let odd = params.required_texture("odd", textures)?;
+            let columns = params.required_number("columns")?;
+            let rows = params.required_number("rows")?;
+            params.check(
+                columns > 0.0 && rows > 0.0,
+                "columns and rows should be positive",
+            )?;
+            Ok(TextureDescription::UvChecker {
+                even: Box::new(even),
+                odd: Box::new(odd),
+                columns,
+                rows,
+            })
+        }
+        "image" => Ok(TextureDescription::Image {
+            file: PathBuf::from(params.required("file")?),
+        }),
+        "marble" | "wood" => {
+            let dark = params.required_vec3("dark")?;
+            let light = params.required_vec3("light")?;
+            let scale = params.number("scale")?.unwrap_or(1.0);
+            let default_turbulence = if kind == "marble" { 10.0 } else { 1.0 };
+            let turbulence = params.number("turbulence")?.unwrap_or(default_turbulence);
+            params.check(scale > 0.0, "scale should be positive")?;
+            Ok(match kind {
+                "marble" => TextureDescription::Marble {
+                    dark,
+                    light,
+                    scale,
+                    turbulence,
+                },
+                _ => TextureDescription::Wood {
+                    dark,
+                    light,
+                    scale,
+                    turbulence,
+                },
+            })
+        }
+        "value_noise" => {
+            let low = params.required_vec3("low")?;
+            let high = params.required_vec3("high")?;
+            let scale = params.number("scale")?.unwrap_or(1.0);
+            let octaves = params.number("octaves")?.unwrap_or(4.0);
+            params.check(scale > 0.0, "scale should be positive")?;
+            params.check(
+                (1.0..=16.0).contains(&octaves) && octaves.fract() == 0.0,
+                "octaves should be a whole number from 1 to 16",
+            )?;
+            Ok(TextureDescription::ValueNoise {
+                low,
+                high,
+                scale,
+                octaves: octaves as u32,
+            })
+        }
+        _ => error(params.line, format!("unknown texture type `{}`", kind)),
+    }
+}
+
+fn parse_material(
+    params: &mut Parameters<'_>,
+    kind: &str,
+    textures: &HashMap<String, TextureDescription>,
+) -> Result<MaterialDescription, SceneFileError> {
+    match kind {
+        "lambertian" => {
+            let texture = params.required_texture("color", textures)?;
+            Ok(MaterialDescription::Lambertian { texture })
+        }
+        "metal" => {
+            let texture = params.required_texture("color", textures)?;
+            let fuzziness = params.number("fuzziness")?.unwrap_or(0.0);
+            params.check(
+                (0.0..=1.0).contains(&fuzziness),
+                "fuzziness should be in [0, 1] range",
+            )?;
+            Ok(MaterialDescription::Metal { texture, fuzziness })
+        }
+        "conductor" => {
+            let eta = params.required_vec3("eta")?;
+            let k = params.required_vec3("k")?;
+            let roughness = params.number("roughness")?.unwrap_or(0.0);
+            let anisotropy = params.number("anisotropy")?.unwrap_or(0.0);
+            params.check(
+                eta.x() > 0.0 && eta.y() > 0.0 && eta.z() > 0.0,
+                "eta should be positive",
+            )?;
+            params.check(
+                k.x() >= 0.0 && k.y() >= 0.0 && k.z() >= 0.0,
+                "k should not be negative",
+            )?;
+            params.check(
+                (0.0..=1.0).contains(&roughness),
+                "roughness should be in [0, 1] range",
+            )?;
+            params.check(
+                (0.0..=1.0).contains(&anisotropy),
+                "anisotropy should be in [0, 1] range",
+            )?;
+            Ok(MaterialDescription::Conductor {
+                eta,
+                k,
+                roughness,
+                anisotropy,
+            })
+        }
+        "dielectric" => {
+            let dispersion = match (params.numbers("cauchy")?, params.numbers("sellmeier")?) {
+                (Some([a, b]), None) => Some(Dispersion::Cauchy { a, b }),
+                (None, Some([b1, b2, b3, c1, c2, c3])) => Some(Dispersion::Sellmeier {
+                    b: [b1, b2, b3],
+                    c: [c1, c2, c3],
+                }),
+                (None, None) => None,
+                (Some(_), Some(_)) => {
+                    return error(
+                        params.line,
+                        format!("{}: give cauchy or sellmeier, not both", params.statement),
+                    )
+                }
+            };
+            if let Some(Err(message)) = dispersion.map(|dispersion| dispersion.check()) {
+                return error(params.line, format!("{}: {}", params.statement, message));
+            }
+            // glass catalogs give the index at the d line
+            let refraction_index = match (params.number("refraction_index")?, dispersion) {
+                (Some(index), _) => index,
+                (None, Some(dispersion)) => dispersion.index(D_LINE),
+                (None, None) => return Err(params.missing("refraction_index")),
+            };
+            let roughness = params.number("roughness")?.unwrap_or(0.0);
+            let tint = params.vec3("tint")?.unwrap_or(Color::new(1.0, 1.0, 1.0));
+            let tint_distance = params.number("tint_distance")?.unwrap_or(1.0);
+            let thin_walled = params.flag("thin_walled")?.unwrap_or(false);
+            params.check(
+                refraction_index > 0.0,
+                "refraction_index should be positive",
+            )?;
+            params.check(
+                (0.0..=1.0).contains(&roughness),
+                "roughness should be in [0, 1] range",
+            )?;
+            params.check(
+                [tint.x(), tint.y(), tint.z()]
+                    .iter()
+                    .all(|c| *c > 0.0 && *c <= 1.0),
+                "tint should be in (0, 1] range",
+            )?;
+            params.check(tint_distance > 0.0, "tint_distance should be positive")?;
+            Ok(MaterialDescription::Dielectric {
+                refraction_index,
+                dispersion,
+                roughness,
+                tint,
+                tint_distance,
+                thin_walled,
+            })
+        }
+        "diffuse_light" => {
+            let texture = params.required_texture("color", textures)?;
+            Ok(MaterialDescription::DiffuseLight { texture })
+        }
+        "isotropic" => {
+            let texture = params.required_texture("color", textures)?;
+            Ok(MaterialDescription::Isotropic { texture })
+        }
+        "principled" => {
+            let mut description = PrincipledDescription::default();
+            for (key, texture) in description.textures_mut() {
+                if let Some(given) = params.texture(key, textures)? {
+                    *texture = given;
+                }
+            }
+            description.ior = params.number("ior")?.unwrap_or(description.ior);
+            params.check(description.ior > 0.0, "ior should be positive")?;
+            Ok(MaterialDescription::Principled(Box::new(description)))
+        }
+        _ => error(params.line, format!("unknown material type `{}`", kind)),
+    }
+}
+
+/// reads and parses a scene file, errors are prefixed with the path and line.
+/// Relative paths of files used by the scene are resolved against the scene's directory.
+pub fn read_scene_file(path: &Path) -> Result<SceneDescription, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+    let mut scene =
+        parse_scene(&text).map_err(|e| format!("{}:{}: {}", path.display(), e.line, e.message))?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+    map_files(&mut scene, &mut |file| directory.join(file));
+    Ok(scene)
+}
+
+/// replaces the path of every file the scene uses
+fn map_files(scene: &mut SceneDescription, map: &mut dyn FnMut(&Path) -> PathBuf) {
+    for object in &mut scene.objects {
+        map_object_files(object, map);
+    }
+    for (_, material) in &mut scene.materials {
+        match material {
+            MaterialDescription::Lambertian { texture }
+            | MaterialDescription::Metal { texture, .. }
+            | MaterialDescription::DiffuseLight { texture }
+            | MaterialDescription::Isotropic { texture } => map_texture_files(texture, map),
+            MaterialDescription::Principled(description) => {
+                for (_, texture) in description.textures_mut() {
+                    map_texture_files(texture, map);
+                }
+            }
+            MaterialDescription::Conductor { .. } | MaterialDescription::Dielectric { .. } => {}
+        }
+    }
+    if let EnvironmentDescription::Map { file, .. } = &mut scene.environment {
+        *file = map(file);
+    }
+}
+
+/// meshes can be inside media, instances and animations, any number of levels deep
+fn map_object_files(object: &mut ObjectDescription, map: &mut dyn FnMut(&Path) -> PathBuf) {
+    match object {
+        ObjectDescription::Mesh { file, .. } => *file = map(file),
+        ObjectDescription::ConstantMedium { boundary, .. } => map_object_files(boundary, map),
+        ObjectDescription::Instance { object, .. } | ObjectDescription::Animated { object, .. } => {
+            map_object_files(object, map)
+        }
+        ObjectDescription::Sphere { .. }
+        | ObjectDescription::MovingSphere { .. }
+        | ObjectDescription::Triangle { .. }
+        | ObjectDescription::Quad { .. }
+        | ObjectDescription::BoxShape { .. } => {}
+    }
+}
+
+fn map_texture_files(texture: &mut TextureDescription, map: &mut dyn FnMut(&Path) -> PathBuf) {
+    match texture {
+        TextureDescription::Solid { .. } => {}
+        TextureDescription::Checker { even, odd, .. }
+        | TextureDescription::UvChecker { even, odd, .. } => {
+            map_texture_files(even, map);
+            map_texture_files(odd, map);
+        }
+        TextureDescription::Image { file } => *file = map(file),
+        TextureDescription::Marble { .. }
+        | TextureDescription::Wood { .. }
+        | TextureDescription::ValueNoise { .. } => {}
+    }
+}
+
+/// The way from directory to file, both relative to the working directory or absolute.
+/// Stays absolute when they have nothing in common, like different drives.
+fn relative_path(file: &Path, directory: &Path) -> PathBuf {
+    // absolute and without . or .., symbolic links are taken as they are
+    let normalize = |path: &Path| -> Option<PathBuf> {
+        let mut normal = PathBuf::new();
+        for component in std::path::absolute(path).ok()?.components() {
+            match component {
+                Component::ParentDir => {
+                    normal.pop();
+                }
+                Component::CurDir => {}
+                component => normal.push(component),
+            }
+        }
+        Some(normal)
+    };
+    let (Some(file), Some(directory)) = (normalize(file), normalize(directory)) else {
+        return file.to_path_buf();
+    };
+    let mut file_parts = file.components().peekable();
+    let mut directory_parts = directory.components().peekable();
+    let mut shared = 0;
+    while file_parts.peek().is_some() && file_parts.peek() == directory_parts.peek() {
+        file_parts.next();
+        directory_parts.next();
+        shared += 1;
+    }
+    if shared == 0 {
+        return file;
+    }
+    let mut relative: PathBuf = directory_parts.map(|_| Component::ParentDir).collect();
+    relative.extend(file_parts);
+    relative
+}
+
+fn vec3_to_string(v: Vec3) -> String {
+    format!("{},{},{}", v.x(), v.y(), v.z())
+}
+
+/// the name, or the name with the first number from 2 up that isn't in `used` yet.
+/// Never something that reads as a number, that would be a gray.
+fn unused_name(used: &mut HashSet<String>, name: &str) -> String {
+    let name = (1..)
+        .map(|i| match i {
+            1 => name.to_string(),
+            _ => format!("{}_{}", name, i),
+        })
+        .find(|candidate| !used.contains(candidate) && candidate.parse::<f64>().is_err())
+        .expect("some number is free");
+    used.insert(name.clone());
+    name
+}
+
+/// Writes the texture statements a texture needs, parts of checkers first. Texture
+/// names are made up from `name`, `used` keeps them from being defined twice.
+/// Returns what goes after `color=`, a color or the name of the texture.
+fn write_texture(
+    out: &mut String,
+    used: &mut HashSet<String>,
+    name: &str,
+    texture: &TextureDescription,
+) -> String {
+    if let TextureDescription::Solid { color } = texture {
+        return vec3_to_string(*color);
+    }
+    let name = unused_name(used, name);
+    let _ = match texture {
+        TextureDescription::Solid { .. } => unreachable!("solid colors have no statement"),
+        TextureDescription::Checker { even, odd, size } => {
+            let even = write_texture(out, used, &format!("{}_even", name), even);
+            let odd = write_texture(out, used, &format!("{}_odd", name), odd);
+            writeln!(
+                out,
+                "texture {} checker even={} odd={} size={}",
+                name, even, odd, size
+            )
+        }
+        TextureDescription::UvChecker {
+            even,
+            odd,
+            columns,
+            rows,
+        } => {
+            let even = write_texture(out, used, &format!("{}_even", name), even);
+            let odd = write_texture(out, used, &format!("{}_odd", name), odd);
+            writeln!(
+                out,
+                "texture {} uv_checker even={} odd={} columns={} rows={}",
+                name, even, odd, columns, rows
+            )
+        }
+        TextureDescription::Image { file } => {
+            writeln!(out, "texture {} image file={}", name, file.display())
+        }
+        TextureDescription::Marble {
+            dark,
+            light,
+            scale,
+            turbulence,
+        } => writeln!(
+            out,
+            "texture {} marble dark={} light={} scale={} turbulence={}",
+            name,
+            vec3_to_string(*dark),
+            vec3_to_string(*light),
+            scale,
+            turbulence
+        ),
+        TextureDescription::Wood {
+            dark,
+            light,
+            scale,
+            turbulence,
+        } => writeln!(
+            out,
+            "texture {} wood dark={} light={} scale={} turbulence={}",
+            name,
+            vec3_to_string(*dark),
+            vec3_to_string(*light),
+            scale,
+            turbulence
+        ),
+        TextureDescription::ValueNoise {
+            low,
+            high,
+            scale,
+            octaves,
+        } => writeln!(
+            out,
+            "texture {} value_noise low={} high={} scale={} octaves={}",
+            name,
+            vec3_to_string(*low),
+            vec3_to_string(*high),
+            scale,
+            octaves
+        ),
+    };
+    name
+}
+
+/// Exports the scene to the text format, parsing the result gives back the same scene.
+pub fn write_scene(scene: &SceneDescription) -> String {
+    let mut out = String::new();
+    let camera = &scene.camera;
+    // writing to a String never fails
+    let _ = writeln!(
+        out,
+        "camera look_from={} look_at={} vec_up={} vfov={} aspect_ratio={} aperture={} focus_dist={} \
+         shutter_open={} shutter_close={}",
+        vec3_to_string(camera.look_from),
+        vec3_to_string(camera.look_at),
+        vec3_to_string(camera.vec_up),
+        camera.vertical_fov_degrees,
+        camera.aspect_ratio,
+        camera.aperture,
+        camera.focus_dist,
+        camera.shutter_open,
+        camera.shutter_close,
+    );
+    let _ = match &scene.environment {
+        EnvironmentDescription::Constant { color } => {
+            writeln!(out, "background color={}", vec3_to_string(*color))
+        }
+        EnvironmentDescription::Gradient { bottom, top } => writeln!(
+            out,
+            "background gradient bottom={} top={}",
+            vec3_to_string(*bottom),
+            vec3_to_string(*top)
+        ),
+        EnvironmentDescription::Map {
+            file,
+            rotation_degrees,
+            intensity,
+        } => writeln!(
+            out,
+            "background map file={} rotation={} intensity={}",
+            file.display(),
+            rotation_degrees,
+            intensity
+        ),
+    };
+
+    out.push('\n');
+    let mut texture_names = HashSet::new();
+    for (name, material) in &scene.materials {
+        let _ = match material {
+            // textures and materials have separate names, so textures get the material's name
+            MaterialDescription::Lambertian { texture } => {
+                let color = write_texture(&mut out, &mut texture_names, name, texture);
+                writeln!(out, "material {} lambertian color={}", name, color)
+            }
+            MaterialDescription::Metal { texture, fuzziness } => {
+                let color = write_texture(&mut out, &mut texture_names, name, texture);
+                writeln!(
+                    out,
+                    "material {} metal color={} fuzziness={}",
+                    name, color, fuzziness
+                )
+            }
+            MaterialDescription::DiffuseLight { texture } => {
+                let color = write_texture(&mut out, &mut texture_names, name, texture);
+                writeln!(out, "material {} diffuse_light color={}", name, color)
+            }
+            MaterialDescription::Isotropic { texture } => {
+                let color = write_texture(&mut out, &mut texture_names, name, texture);
+                writeln!(out, "material {} isotropic color={}", name, color)
+            }
+            MaterialDescription::Conductor {
+                eta,
+                k,
+                roughness,
+                anisotropy,
+            } => writeln!(
+                out,
+                "material {} conductor eta={} k={} roughness={} anisotropy={}",
+                name,
+                vec3_to_string(*eta),
+                vec3_to_string(*k),
+                roughness,
+                anisotropy
+            ),
+            MaterialDescription::Dielectric {
+                refraction_index,
+                dispersion,
+                roughness,
+                tint,
+                tint_distance,
+                thin_walled,
+            } => {
+                let dispersion = match dispersion {
+                    None => String::new(),
+                    Some(Dispersion::Cauchy { a, b }) => format!(" cauchy={},{}", a, b),
+                    Some(Dispersion::Sellmeier { b, c }) => format!(
+                        " sellmeier={},{},{},{},{},{}",
+                        b[0], b[1], b[2], c[0], c[1], c[2]
+                    ),
+                };
+                writeln!(
+                    out,
+                    "material {} dielectric refraction_index={}{} roughness={} tint={} \
+                     tint_distance={} thin_walled={}",
+                    name,
+                    refraction_index,
+                    dispersion,
+                    roughness,
+                    vec3_to_string(*tint),
+                    tint_distance,
+                    thin_walled
+                )
+            }
+            MaterialDescription::Principled(description) => {
+                let mut description = description.clone();
+                let mut statement = format!("material {} principled", name);
+                for (key, texture) in description.textures_mut() {
+                    let value = match texture {
+                        TextureDescription::Solid { color }
+                            if color.x() == color.y() && color.y() == color.z() =>
+                        {
+                            color.x().to_string()
+                        }
+                        _ => {
+                            let name = format!("{}_{}", name, key);
+                            write_texture(&mut out, &mut texture_names, &name, texture)
+                        }
+                    };
+                    let _ = write!(statement, " {}={}", key, value);
+                }
+                writeln!(out, "{} ior={}", statement, description.ior)
+            }
+        };
+    }
+
+    out.push('\n');
+    for object in &scene.objects {
+        out.push_str(&object_statement(object));
+        out.push('\n');
+    }
+    out
+}
+
+fn object_statement(object: &ObjectDescription) -> String {
+    match object {
+        ObjectDescription::Sphere {
+            center,
+            radius,
+            material,
+        } => format!(
+            "sphere center={} radius={} material={}",
+            vec3_to_string(*center),
+            radius,
+            material
+        ),
+        ObjectDescription::MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        } => format!(
+            "moving_sphere center0={} center1={} time0={} time1={} radius={} material={}",
+            vec3_to_string(*center0),
+            vec3_to_string(*center1),
+            time0,
+            time1,
+            radius,
+            material
+        ),
+        ObjectDescription::Triangle {
+            vertices,
+            normals,
+            uvs,
+            material,
+        } => {
+            let mut statement = String::from("triangle");
+            for (i, vertex) in vertices.iter().enumerate() {
+                let _ = write!(statement, " v{}={}", i, vec3_to_string(*vertex));
+            }
+            for (i, normal) in normals.iter().flatten().enumerate() {
+                let _ = write!(statement, " n{}={}", i, vec3_to_string(*normal));
+            }
+            for (i, (u, v)) in uvs.iter().flatten().enumerate() {
+                let _ = write!(statement, " uv{}={},{}", i, u, v);
+            }
+            format!("{} material={}", statement, material)
+        }
+        ObjectDescription::Quad {
+            corner,
+            u,
+            v,
+            material,
+        } => format!(
+            "quad corner={} u={} v={} material={}",
+            vec3_to_string(*corner),
+            vec3_to_string(*u),
+            vec3_to_string(*v),
+            material
+        ),
+        ObjectDescription::BoxShape { min, max, material } => format!(
+            "box min={} max={} material={}",
+            vec3_to_string(*min),
+            vec3_to_string(*max),
+            material
+        ),
+        ObjectDescription::Mesh { file, material } => match material {
+            Some(material) => {
+                format!("mesh file={} material={}", file.display(), material)
+            }
+            None => format!("mesh file={}", file.display()),
+        },
+        ObjectDescription::ConstantMedium {
+            boundary,
+            density,
+            material,
+        } => format!(
+            "medium density={} material={} {}",
+            density,
+            material,
+            object_statement(boundary)
+        ),
+        ObjectDescription::Instance {
+            object,
+            scale,
+            rotation_degrees,
+            translation,
+        } => format!(
+            "instance scale={} rotate={} translate={} {}",
+            vec3_to_string(*scale),
+            vec3_to_string(*rotation_degrees),
+            vec3_to_string(*translation),
+            object_statement(object)
+        ),
+        ObjectDescription::Animated { object, keyframes } => {
+            let mut statement = String::from("animated");
+            for (i, keyframe) in keyframes.iter().enumerate() {
+                let _ = write!(
+                    statement,
+                    " time{i}={} scale{i}={} rotate{i}={} translate{i}={}",
+                    keyframe.time,
+                    vec3_to_string(keyframe.scale),
+                    vec3_to_string(keyframe.rotation_degrees),
+                    vec3_to_string(keyframe.translation),
+                );
+            }
+            format!("{} {}", statement, object_statement(object))
+        }
+    }
+}
+
+/// Like `write_scene`, with the files the scene uses relative to the written file,
+/// which is how `read_scene_file` finds them
+pub fn write_scene_file(path: &Path, scene: &SceneDescription) -> std::io::Result<()> {
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    let mut scene = scene.clone();
+    map_files(&mut scene, &mut |file| relative_path(file, directory));
+    std::fs::write(path, write_scene(&scene))
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate_scene::{
+        bouncing_spheres_description, cornell_box_description, generate_scene_description,
+    };
+    use crate::number_stuff::Rng;
+
+    fn parse_error(text: &str) -> SceneFileError {
+        parse_scene(text).expect_err("scene should not parse")
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut scene = generate_scene_description(&mut Rng::new(7));
+        // the same seed lays out the same spheres
+        assert_eq!(scene, generate_scene_description(&mut Rng::new(7)));
+        scene.objects.push(ObjectDescription::Triangle {
+            vertices: [
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            normals: None,
+            uvs: Some([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]),
+            material: String::from("material0"),
+        });
+        let image = TextureDescription::Image {
+            file: PathBuf::from("earth.jpg"),
+        };
+        let grid = TextureDescription::UvChecker {
+            even: Box::new(Color::new(0.0, 0.0, 0.0).into()),
+            odd: Box::new(image),
+            columns: 20.0,
+            rows: 10.0,
+        };
+        scene.materials[1].1 = MaterialDescription::Metal {
+            texture: TextureDescription::Marble {
+                dark: Color::new(0.1, 0.1, 0.1),
+                light: Color::new(0.9, 0.9, 0.9),
+                scale: 4.0,
+                turbulence: 10.0,
+            },
+            fuzziness: 0.2,
+        };
+        scene.materials[2].1 = MaterialDescription::DiffuseLight {
+            texture: TextureDescription::ValueNoise {
+                low: Color::new(0.3, 0.5, 0.9),
+                high: Color::new(1.0, 1.0, 1.0),
+                scale: 2.0,
+                octaves: 5,
+            },
+        };
+        scene.materials[0].1 = MaterialDescription::Lambertian {
+            texture: TextureDescription::Checker {
+                even: Box::new(grid),
+                odd: Box::new(Color::new(0.9, 0.9, 0.9).into()),
+                size: 0.5,
+            },
+        };
+        scene.materials.push((
+            String::from("smoke"),
+            MaterialDescription::Isotropic {
+                texture: Color::new(0.8, 0.8, 0.8).into(),
+            },
+        ));
+        scene.materials.push((
+            String::from("brushed_gold"),
+            MaterialDescription::Conductor {
+                eta: Color::new(0.143, 0.374, 1.442),
+                k: Color::new(3.983, 2.385, 1.603),
+                roughness: 0.3,
+                anisotropy: 0.8,
+            },
+        ));
+        scene.materials.push((
+            String::from("car_paint"),
+            MaterialDescription::Principled(Box::new(PrincipledDescription {
+                base_color: Color::new(0.8, 0.1, 0.1).into(),
+                roughness: TextureDescription::ValueNoise {
+                    low: Color::new(0.2, 0.2, 0.2),
+                    high: Color::new(0.6, 0.6, 0.6),
+                    scale: 3.0,
+                    octaves: 2,
+                },
+                clearcoat: Color::new(1.0, 1.0, 1.0).into(),
+                ior: 1.45,
+                ..Default::default()
+            })),
+        ));
+        scene.materials.push((
+            String::from("frosted_bottle"),
+            MaterialDescription::Dielectric {
+                refraction_index: 1.52,
+                dispersion: Some(Dispersion::Sellmeier {
+                    b: [1.03961212, 0.231792344, 1.01046945],
+                    c: [0.00600069867, 0.0200179144, 103.560653],
+                }),
+                roughness: 0.25,
+                tint: Color::new(0.4, 0.8, 0.5),
+                tint_distance: 0.5,
+                thin_walled: false,
+            },
+        ));
+        scene.materials.push((
+            String::from("bubble"),
+            MaterialDescription::Dielectric {
+                refraction_index: 1.33,
+                dispersion: None,
+                roughness: 0.0,
+                tint: Color::new(1.0, 1.0, 1.0),
+                tint_distance: 1.0,
+                thin_walled: true,
+            },
+        ));
+        let medium = ObjectDescription::ConstantMedium {
+            boundary: Box::new(ObjectDescription::Sphere {
+                center: Point3::new(0.0, 1.0, 0.0),
+                radius: 1.0,
+                material: String::from("material0"),
+            }),
+            density: 0.5,
+            material: String::from("smoke"),
+        };
+        scene.objects.push(ObjectDescription::Instance {
+            object: Box::new(medium),
+            scale: Vec3::new(1.0, 0.5, 2.0),
+            rotation_degrees: Vec3::new(0.0, 45.0, 0.0),
+            translation: Vec3::new(1.0, 0.0, -3.0),
+        });
+        scene.objects.push(ObjectDescription::Animated {
+            object: Box::new(ObjectDescription::BoxShape {
+                min: Point3::new(0.0, 0.0, 0.0),
+                max: Point3::new(1.0, 2.0, 1.0),
+                material: String::from("material0"),
+            }),
+            keyframes: vec![
+                Keyframe::at(0.0),
+                Keyframe::at(0.5).with_translation(Vec3::new(0.0, 1.0, 0.0)),
+                Keyframe::at(1.0)
+                    .with_scale(Vec3::new(0.5, 0.5, 0.5))
+                    .with_rotation_degrees(Vec3::new(0.0, 90.0, 30.0))
+                    .with_translation(Vec3::new(2.0, 1.0, 0.0)),
+            ],
+        });
+        assert_eq!(parse_scene(&write_scene(&scene)), Ok(scene));
+        let bouncing = bouncing_spheres_description(&mut Rng::new(7));
+        assert_eq!(parse_scene(&write_scene(&bouncing)), Ok(bouncing));
+        let cornell_box = cornell_box_description();
+        assert_eq!(parse_scene(&write_scene(&cornell_box)), Ok(cornell_box));
+        let studio = SceneDescription {
+            environment: EnvironmentDescription::Map {
+                file: PathBuf::from("studio.hdr"),
+                rotation_degrees: 90.0,
+                intensity: 1.5,
+            },
+            ..Default::default()
+        };
+        assert_eq!(parse_scene(&write_scene(&studio)), Ok(studio));
+    }
+
+    #[test]
+    fn texture_names_made_up_by_the_writer_are_unique() {
+        let image = |file: &str| TextureDescription::Image {
+            file: PathBuf::from(file),
+        };
+        let checker = TextureDescription::Checker {
+            even: Box::new(Color::new(1.0, 1.0, 1.0).into()),
+            odd: Box::new(image("odd.png")),
+            size: 1.0,
+        };
+        // the odd part of a's checker would get the name of a_odd's texture
+        let mut scene = SceneDescription::default();
+        for (name, texture) in [("a", checker), ("a_odd", image("b.png"))] {
+            let material = MaterialDescription::Lambertian { texture };
+            scene.materials.push((name.to_string(), material));
+        }
+        let text = write_scene(&scene);
+        assert!(
+            text.contains("texture a_odd_2 image file=b.png"),
+            "{}",
+            text
+        );
+        assert_eq!(parse_scene(&text), Ok(scene));
+
+        // principled inputs are named after the material and the input
+        let principled = PrincipledDescription {
+            base_color: image("base.png"),
+            sheen_tint: image("tint.png"),
+            ..Default::default()
+        };
+        let mut scene = SceneDescription::default();
+        let wall = MaterialDescription::Lambertian {
+            texture: image("wall.png"),
+        };
+        scene.materials.push((String::from("p_base_color"), wall));
+        let principled = MaterialDescription::Principled(Box::new(principled));
+        scene.materials.push((String::from("p"), principled));
+        let text = write_scene(&scene);
+        assert!(text.contains("base_color=p_base_color_2"), "{}", text);
+        assert_eq!(parse_scene(&text), Ok(scene));
+
+        // a texture named like its material would read as a gray
+        let mut scene = SceneDescription::default();
+        let wall = MaterialDescription::Lambertian {
+            texture: image("wall.png"),
+        };
+        scene.materials.push((String::from("5"), wall));
+        let text = write_scene(&scene);
+        assert!(text.contains("color=5_2"), "{}", text);
+        assert_eq!(parse_scene(&text), Ok(scene));
+    }
+
+    #[test]
+    fn nested_meshes_are_found_next_to_the_scene() {
+        // the scene isn't in the working directory of the test
+        let directory = std::env::temp_dir().join(format!("nested_meshes_{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("models")).unwrap();
+        std::fs::write(
+            directory.join("models/tri.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("nested.scene"),
+            "material fog isotropic color=1,1,1\n\
+             instance translate=1,0,0 mesh file=models/tri.obj\n\
+             medium density=1 material=fog instance scale=2,2,2 mesh file=models/tri.obj\n\
+             animated time0=0 time1=1 translate1=0,1,0 mesh file=models/tri.obj\n",
+        )
+        .unwrap();
+        let scene = read_scene_file(&directory.join("nested.scene")).unwrap();
+        let mesh_file = |object: &ObjectDescription| -> PathBuf {
+            let mut object = object.clone();
+            loop {
+                object = match object {
+                    ObjectDescription::Instance { object, .. }
+                    | ObjectDescription::Animated { object, .. } => *object,
+                    ObjectDescription::ConstantMedium { boundary, .. } => *boundary,
+                    ObjectDescription::Mesh { file, .. } => return file,
+                    _ => panic!("expected a mesh"),
+                }
+            }
+        };
+        for object in &scene.objects {
+            assert_eq!(mesh_file(object), directory.join("models/tri.obj"));
+        }
+        assert!(scene.build(&mut Rng::new(1)).is_ok());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn exported_scenes_find_their_files_again() {
+        let directory = std::env::temp_dir().join(format!("exported_{}", std::process::id()));
+        std::fs::create_dir_all(directory.join("scenes/models")).unwrap();
+        std::fs::write(
+            directory.join("scenes/a.scene"),
+            "background map file=studio.hdr\n\
+             texture wall image file=models/wall.png\n\
+             material wall lambertian color=wall\n\
+             instance translate=1,0,0 mesh file=models/tri.obj\n",
+        )
+        .unwrap();
+        let scene = read_scene_file(&directory.join("scenes/a.scene")).unwrap();
+        let files = |scene: &SceneDescription| {
+            let mut files = Vec::new();
+            let mut scene = scene.clone();
+            map_files(&mut scene, &mut |file| {
+                files.push(file.to_path_buf());
+                file.to_path_buf()
+            });
+            files
+        };
+        // next to the scene, in a directory below it and in one beside it
+        for (exported, written) in [
+            ("scenes/b.scene", "file=models/tri.obj"),
+            ("scenes/models/c.scene", "file=tri.obj"),
+            ("other/d.scene", "file=../scenes/models/tri.obj"),
+        ] {
+            let path = directory.join(exported);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            write_scene_file(&path, &scene).unwrap();
+            let text = std::fs::read_to_string(&path).unwrap();
+            assert!(text.contains(written), "{}", text);
+            let normalize = |file: &PathBuf| relative_path(file, &directory);
+            let again = read_scene_file(&path).unwrap();
+            assert_eq!(
+                files(&again).iter().map(normalize).collect::<Vec<_>>(),
+                files(&scene).iter().map(normalize).collect::<Vec<_>>()
+            );
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn parses_comments_and_defaults() {
+        let scene = parse_scene(
+            "# a lonely sphere\n\
+             camera look_from=0,0,-5 # the rest is default\n\
+             \n\
+             material red lambertian color=1,0,0\n\
+             sphere center=0,0,0 radius=1 material=red\n\
+             material fog isotropic color=1,1,1\n\
+             medium density=0.1 material=fog sphere center=0,0,0 radius=2\n",
+        )
+        .unwrap();
+        assert_eq!(scene.camera.look_from, Point3::new(0.0, 0.0, -5.0));
+        assert_eq!(scene.camera.vertical_fov_degrees, 30.0);
+        assert_eq!(scene.objects.len(), 2);
+        // the boundary gets the material of the medium when it has none
+        let ObjectDescription::ConstantMedium { boundary, .. } = &scene.objects[1] else {
+            panic!("expected a medium");
+        };
+        assert!(
+            matches!(**boundary, ObjectDescription::Sphere { ref material, .. } if material == "fog")
+        );
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let unknown = parse_error(
+            "material red lambertian color=1,0,0\n\nsphere center=0,0,0 radius=1 material=blue",
+        );
+        assert_eq!(unknown.line, 3);
+        assert_eq!(unknown.message, "unknown material `blue`");
+
+        let fuzzy = parse_error("\nmaterial steel metal color=1,1,1 fuzziness=2");
+        assert_eq!(fuzzy.line, 2);
+        assert!(fuzzy.message.contains("fuzziness"));
+
+        let missing = parse_error("material glass dielectric");
+        assert_eq!(
+            missing.message,
+            "dielectric material is missing `refraction_index`"
+        );
+
+        let thin = parse_error("material window dielectric refraction_index=1.5 thin_walled=yes");
+        assert_eq!(
+            thin.message,
+            "`thin_walled` should be true or false but was `yes`"
+        );
+
+        let both = parse_error("material prism dielectric cauchy=1.5,0.004 sellmeier=1,1,1,0,0,0");
+        assert_eq!(
+            both.message,
+            "dielectric material: give cauchy or sellmeier, not both"
+        );
+
+        let below_one = parse_error("material prism dielectric cauchy=0.9,0.004");
+        assert!(
+            below_one
+                .message
+                .starts_with("dielectric material: index of refraction should be at least 1"),
+            "{}",
+            below_one.message
+        );
+        let pole = parse_error("material prism dielectric sellmeier=1,0.2,1,0.006,0.25,100");
+        assert_eq!(
+            pole.message,
+            "dielectric material: c2 puts a pole at 500 nm, inside 360 to 830 nm"
+        );
+        let nan = parse_error("material prism dielectric sellmeier=-2,0,0,0.01,0,0");
+        assert!(nan.message.ends_with("is NaN at 360 nm"), "{}", nan.message);
+
+        let nan = parse_error("material m lambertian color=nan");
+        assert_eq!(nan.message, "`color` should be a number but was `nan`");
+        let named_inf = parse_error("texture inf checker even=0 odd=1 size=1");
+        assert_eq!(named_inf.message, "texture name `inf` reads as a gray");
+
+        let shutter = parse_error("camera shutter_open=1 shutter_close=0.5");
+        assert_eq!(
+            shutter.message,
+            "camera: shutter_close should not be before shutter_open"
+        );
+
+        let typo = parse_error("camera look_form=1,2,3");
+        assert_eq!(typo.message, "unknown parameter `look_form` for camera");
+
+        let flat = parse_error("instance scale=1,0,1 mesh file=teapot.obj");
+        assert_eq!(
+            flat.message,
+            "instance: scale should not be 0 along any axis"
+        );
+        let tiny = parse_error("instance scale=1e-13,1,1 sphere center=0,0,0 radius=1");
+        assert_eq!(
+            tiny.message,
+            "instance: scale should not be 0 along any axis"
+        );
+
+        let no_keyframes = parse_error("animated translate0=1,0,0 mesh file=teapot.obj");
+        assert_eq!(no_keyframes.message, "animated: time0 is missing");
+        let skipped = parse_error("animated time0=0 time2=1 mesh file=teapot.obj");
+        assert_eq!(skipped.message, "unknown parameter `time2` for animated");
+        let mirrored = parse_error("animated time0=0 time1=1 scale1=-1,1,1 mesh file=teapot.obj");
+        assert_eq!(
+            mirrored.message,
+            "animated: keyframe scales should not change sign between keyframes"
+        );
+    }
+}
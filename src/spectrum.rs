@@ -0,0 +1,259 @@
+//! Spectral rendering: paths carry one wavelength, colors are turned into spectra at the
+//! hits and the light found is turned back into sRGB through the CIE XYZ color space.
+
+use crate::vec3::*;
+use std::f64::consts::PI;
+
+/// shortest and longest wavelength traced, in nanometers
+pub const MIN_WAVELENGTH: f64 = 360.0;
+pub const MAX_WAVELENGTH: f64 = 830.0;
+
+/// Yellow helium line, where glass catalogs give the plain index of refraction
+pub const D_LINE: f64 = 587.6;
+
+/// Wavelength in nanometers from a number in [0, 1) and its density. Visible
+/// wavelengths, where the eye is more sensitive, are picked more often (pbrt-v4).
+pub fn sample_wavelength(u: f64) -> (f64, f64) {
+    let wavelength = 538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh();
+    let wavelength = wavelength.clamp(MIN_WAVELENGTH, MAX_WAVELENGTH);
+    let pdf = 0.0039398042 / (0.0072 * (wavelength - 538.0)).cosh().powi(2);
+    (wavelength, pdf)
+}
+
+/// Gaussian that is wider on one side, the building block of `matching_functions`
+fn lobe(wavelength: f64, mean: f64, left_width: f64, right_width: f64) -> f64 {
+    let width = match wavelength < mean {
+        true => left_width,
+        false => right_width,
+    };
+    (-0.5 * ((wavelength - mean) / width).powi(2)).exp()
+}
+
+/// CIE 1931 color matching functions x, y and z at the wavelength, with the multi-lobe
+/// fit of Wyman, Sloan and Shirley instead of tables.
+pub fn matching_functions(wavelength: f64) -> Vec3 {
+    let l = wavelength;
+    Vec3::new(
+        1.056 * lobe(l, 599.8, 37.9, 31.0) + 0.362 * lobe(l, 442.0, 16.0, 26.7)
+            - 0.065 * lobe(l, 501.1, 20.4, 26.2),
+        0.821 * lobe(l, 568.8, 46.9, 40.5) + 0.286 * lobe(l, 530.9, 16.3, 31.1),
+        1.217 * lobe(l, 437.0, 11.8, 36.0) + 0.681 * lobe(l, 459.0, 26.0, 13.8),
+    )
+}
+
+/// linear sRGB with the D65 white point
+pub fn xyz_to_srgb(xyz: Vec3) -> Color {
+    Color::new(
+        3.2404542 * xyz.x() - 1.5371385 * xyz.y() - 0.4985314 * xyz.z(),
+        -0.9692660 * xyz.x() + 1.8760108 * xyz.y() + 0.0415560 * xyz.z(),
+        0.0556434 * xyz.x() - 0.2040259 * xyz.y() + 1.0572252 * xyz.z(),
+    )
+}
+
+/// Light that is the same at every wavelength in sRGB. Colors are divided by it, so
+/// white surfaces stay white instead of taking the pink of equal energy light.
+fn white() -> Color {
+    // the integral of a lobe is sqrt(pi / 2) times the sum of its widths
+    let area = |weight: f64, left: f64, right: f64| weight * (PI / 2.0).sqrt() * (left + right);
+    xyz_to_srgb(Vec3::new(
+        area(1.056, 37.9, 31.0) + area(0.362, 16.0, 26.7) - area(0.065, 20.4, 26.2),
+        area(0.821, 46.9, 40.5) + area(0.286, 16.3, 31.1),
+        area(1.217, 11.8, 36.0) + area(0.681, 26.0, 13.8),
+    ))
+}
+
+/// sRGB color of the light found at one sampled wavelength, so that averaging over
+/// wavelengths sampled with `sample_wavelength` gives the color of the pixel
+pub fn wavelength_to_srgb(value: f64, wavelength: f64, pdf: f64) -> Color {
+    let rgb = xyz_to_srgb(value * matching_functions(wavelength) / pdf);
+    let white = white();
+    Color::new(
+        rgb.x() / white.x(),
+        rgb.y() / white.y(),
+        rgb.z() / white.z(),
+    )
+}
+
+/// Smits' spectra of the colors a color is made of, ten bins 34 nm wide from 380 nm
+const SMITS_WHITE: [f64; 10] = [1.0, 1.0, 0.9999, 0.9993, 0.9992, 0.9998, 1.0, 1.0, 1.0, 1.0];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0, 0.0, 0.0,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0, 1.0, 0.9685, 0.2229, 0.0, 0.0458, 0.8369, 1.0, 1.0, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0, 0.1088, 0.6651, 1.0, 1.0, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0, 0.0, 0.0, 0.0, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0, 0.0, 0.0273, 0.7937, 1.0, 0.9418, 0.1719, 0.0, 0.0, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0, 1.0, 0.8916, 0.3323, 0.0, 0.0, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+/// Value at the wavelength of a smooth spectrum with the color, with Smits' method: white
+/// for the smallest channel, then the secondary and primary colors for the rest. Scales
+/// with the color, so it works for lights brighter than 1 too.
+pub fn rgb_to_spectrum(color: Color, wavelength: f64) -> f64 {
+    let bin = (((wavelength - 380.0) / 34.0).max(0.0) as usize).min(9);
+    let (r, g, b) = (color.x(), color.y(), color.z());
+    let (smallest, secondary, primary, over_secondary, over_primary) = if r <= g && r <= b {
+        match g <= b {
+            true => (r, &SMITS_CYAN, &SMITS_BLUE, g - r, b - g),
+            false => (r, &SMITS_CYAN, &SMITS_GREEN, b - r, g - b),
+        }
+    } else if g <= r && g <= b {
+        match r <= b {
+            true => (g, &SMITS_MAGENTA, &SMITS_BLUE, r - g, b - r),
+            false => (g, &SMITS_MAGENTA, &SMITS_RED, b - g, r - b),
+        }
+    } else {
+        match r <= g {
+            true => (b, &SMITS_YELLOW, &SMITS_GREEN, r - b, g - r),
+            false => (b, &SMITS_YELLOW, &SMITS_RED, g - b, r - g),
+        }
+    };
+    smallest * SMITS_WHITE[bin] + over_secondary * secondary[bin] + over_primary * primary[bin]
+}
+
+/// The color itself without a wavelength, the value of its spectrum in every channel with
+/// one. This lets the RGB and the spectral paths share all the code that multiplies colors.
+pub fn at_wavelength(color: Color, wavelength: Option<f64>) -> Color {
+    match wavelength {
+        None => color,
+        Some(wavelength) => {
+            let value = rgb_to_spectrum(color, wavelength);
+            Color::new(value, value, value)
+        }
+    }
+}
+
+/// How the index of refraction changes with the wavelength, which splits white light into
+/// colors. Wavelengths here are in micrometers, like in glass catalogs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dispersion {
+    /// n = a + b / wavelength^2, crown glass is about a = 1.5046 and b = 0.0042
+    Cauchy { a: f64, b: f64 },
+    /// n^2 = 1 + sum of b wavelength^2 / (wavelength^2 - c), BK7 is b = 1.03961212,
+    /// 0.231792344, 1.01046945 and c = 0.00600069867, 0.0200179144, 103.560653
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    /// index of refraction at the wavelength in nanometers
+    pub fn index(&self, wavelength: f64) -> f64 {
+        let micrometers2 = (wavelength / 1000.0).powi(2);
+        match self {
+            Dispersion::Cauchy { a, b } => a + b / micrometers2,
+            Dispersion::Sellmeier { b, c } => {
+                let sum: f64 = (0..3)
+                    .map(|i| b[i] * micrometers2 / (micrometers2 - c[i]))
+                    .sum();
+                (1.0 + sum).sqrt()
+            }
+        }
+    }
+
+    /// Errors unless the index is a number of at least 1 at every wavelength that is
+    /// traced. Sellmeier terms can't have their pole there, however weak they are.
+    pub fn check(&self) -> Result<(), String> {
+        if let Dispersion::Sellmeier { b, c } = self {
+            let range = (MIN_WAVELENGTH / 1000.0).powi(2)..=(MAX_WAVELENGTH / 1000.0).powi(2);
+            if let Some(i) = (0..3).find(|&i| b[i] != 0.0 && range.contains(&c[i])) {
+                return Err(format!(
+                    "c{} puts a pole at {:.0} nm, inside {} to {} nm",
+                    i + 1,
+                    c[i].sqrt() * 1000.0,
+                    MIN_WAVELENGTH,
+                    MAX_WAVELENGTH
+                ));
+            }
+        }
+        let mut wavelength = MIN_WAVELENGTH;
+        while wavelength <= MAX_WAVELENGTH {
+            let index = self.index(wavelength);
+            if !(index >= 1.0 && index.is_finite()) {
+                return Err(format!(
+                    "index of refraction should be at least 1 but is {} at {} nm",
+                    index, wavelength
+                ));
+            }
+            wavelength += 1.0;
+        }
+        Ok(())
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_survive_the_trip_through_spectra() {
+        // wavelengths picked evenly by their density, so averaging is integrating
+        let samples = 20_000;
+        for color in [
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.0, 1.0, 0.0),
+            Color::new(0.0, 0.0, 1.0),
+            Color::new(0.8, 0.3, 0.1),
+            Color::new(0.2, 0.6, 0.9),
+            Color::new(3.0, 3.0, 2.0),
+        ] {
+            let mut sum = Color::default();
+            for i in 0..samples {
+                let (wavelength, pdf) = sample_wavelength((i as f64 + 0.5) / samples as f64);
+                sum += wavelength_to_srgb(rgb_to_spectrum(color, wavelength), wavelength, pdf);
+            }
+            let back = sum / samples as f64;
+            for (a, b) in [
+                (back.x(), color.x()),
+                (back.y(), color.y()),
+                (back.z(), color.z()),
+            ] {
+                assert!(
+                    (a - b).abs() < 0.03 * color.x().max(1.0),
+                    "{:?} {:?}",
+                    back,
+                    color
+                );
+            }
+        }
+        assert_eq!(
+            at_wavelength(Color::new(0.1, 0.2, 0.3), None),
+            Color::new(0.1, 0.2, 0.3)
+        );
+
+        let bk7 = Dispersion::Sellmeier {
+            b: [1.03961212, 0.231792344, 1.01046945],
+            c: [0.00600069867, 0.0200179144, 103.560653],
+        };
+        assert!((bk7.index(D_LINE) - 1.5168).abs() < 1e-4);
+        // blue bends more than red
+        assert!(bk7.index(450.0) > bk7.index(650.0));
+        let crown = Dispersion::Cauchy {
+            a: 1.5046,
+            b: 0.0042,
+        };
+        assert!((crown.index(500.0) - 1.5214).abs() < 1e-12);
+        assert!(bk7.check().is_ok() && crown.check().is_ok());
+        // too weak to show up between the sampled wavelengths
+        let weak_pole = Dispersion::Sellmeier {
+            b: [1.0, 1e-9, 0.0],
+            c: [0.01, 0.25, 0.0],
+        };
+        assert_eq!(
+            weak_pole.check(),
+            Err(String::from(
+                "c2 puts a pole at 500 nm, inside 360 to 830 nm"
+            ))
+        );
+    }
+}
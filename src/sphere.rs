@@ -1,17 +1,28 @@
+use crate::aabb::Aabb;
 use crate::material::Material;
 use crate::ray::*;
 use crate::ray_trace::*;
+use crate::sampler::{sample_sphere, Sampler};
 use crate::vec3::*;
-use std::rc::Rc;
+use std::f64::consts::PI;
+use std::sync::Arc;
 
-pub struct Sphere<M: Material + 'static> {
+/// Texture coordinates of a point on the unit sphere. u goes around the y axis
+/// starting from -x, v goes from the bottom (-y) to the top.
+fn sphere_uv(p: Vec3) -> (f64, f64) {
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+pub struct Sphere {
     center: Point3,
     radius: f64,
-    material: Rc<M>,
+    material: Arc<dyn Material>,
 }
 
-impl<M: Material + 'static> Sphere<M> {
-    pub fn new(center: Point3, radius: f64, material: Rc<M>) -> Self {
+impl Sphere {
+    pub fn new(center: Point3, radius: f64, material: Arc<dyn Material>) -> Self {
         Self {
             center,
             radius,
@@ -27,40 +38,171 @@ impl<M: Material + 'static> Sphere<M> {
     }
 }
 
-impl<M: Material + 'static> Hittable for Sphere<M> {
-    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        assert!(t_max >= t_min);
-        let oc = r.origin() - self.center();
-        let a = dot(r.direction(), r.direction());
-        let half_b = dot(oc, r.direction());
-        let c = dot(oc, oc) - self.radius() * self.radius();
-        let discriminant_quarter = half_b * half_b - a * c;
+/// hit record of a sphere that is at `center` for this ray
+fn hit_sphere<'a>(
+    center: Point3,
+    radius: f64,
+    material: &'a dyn Material,
+    r: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord<'a>> {
+    assert!(t_max >= t_min);
+    let oc = r.origin() - center;
+    let a = dot(r.direction(), r.direction());
+    let half_b = dot(oc, r.direction());
+    let c = dot(oc, oc) - radius * radius;
+    let discriminant_quarter = half_b * half_b - a * c;
 
-        if discriminant_quarter < 0.0 {
+    if discriminant_quarter < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant_quarter.sqrt();
+    let mut root = (-half_b - sqrtd) / a;
+    //let root2 = (-half_b + sqrtd) / a;
+    if root < t_min || root > t_max {
+        root = (-half_b + sqrtd) / a;
+        if root < t_min || root > t_max {
             return None;
         }
-        let sqrtd = discriminant_quarter.sqrt();
-        let mut root = (-half_b - sqrtd) / a;
-        //let root2 = (-half_b + sqrtd) / a;
-        if root < t_min || root > t_max {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || root > t_max {
-                return None;
-            }
+    }
+    // outward normal here
+    let mut normal = (r.at(root) - center) / radius;
+    let uv = sphere_uv(normal);
+    // u grows around the y axis, the derivative of the angle in `sphere_uv`
+    let tangent = Vec3::new(normal.z(), 0.0, -normal.x());
+    let mut outside_ray = true;
+    if face_one_direction(r, &normal) {
+        normal = -normal;
+        outside_ray = false;
+    }
+    Some(HitRecord {
+        point: r.at(root),
+        normal,
+        t: root,
+        material_reference: Some(material),
+        outside_ray,
+        uv,
+        tangent,
+        ..Default::default()
+    })
+}
+
+fn sphere_box(center: Point3, radius: f64) -> Aabb {
+    let r = Vec3::new(radius, radius, radius);
+    Aabb::new(center - r, center + r)
+}
+
+impl Hittable for Sphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        hit_sphere(
+            self.center,
+            self.radius,
+            self.material.as_ref(),
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(sphere_box(self.center, self.radius))
+    }
+
+    /// uniform over the cone of directions the sphere covers
+    fn pdf_value(&self, origin: Point3, direction: Vec3) -> f64 {
+        let ray = Ray::new(origin, direction);
+        if self.hit(&ray, 0.001, f64::MAX).is_none() {
+            return 0.0;
         }
-        // outward normal here
-        let mut normal = (r.at(root) - self.center()) / self.radius();
-        let mut outside_ray = true;
-        if face_one_direction(r, &normal) {
-            normal = -normal;
-            outside_ray = false;
+        let distance_squared = (self.center - origin).length_squared();
+        if distance_squared <= self.radius * self.radius {
+            // from inside every direction hits
+            return 1.0 / (4.0 * PI);
         }
-        Some(HitRecord {
-            point: r.at(root),
-            normal,
-            t: root,
-            material_reference: Some(self.material.clone()),
-            outside_ray,
-        })
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
+
+    fn random_direction(&self, origin: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let to_center = self.center - origin;
+        let distance_squared = to_center.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return sample_sphere(sampler.get_2d());
+        }
+        Onb::new(to_center).local(random_to_sphere(
+            self.radius,
+            distance_squared,
+            sampler.get_2d(),
+        ))
+    }
+}
+
+/// Direction around the z axis towards a sphere of the given radius at the given
+/// squared distance from two numbers in [0, 1), uniform over the cone the sphere covers
+fn random_to_sphere(radius: f64, distance_squared: f64, (r1, r2): (f64, f64)) -> Vec3 {
+    let cos_theta_max = (1.0 - radius * radius / distance_squared).sqrt();
+    let z = 1.0 + r2 * (cos_theta_max - 1.0);
+    let phi = 2.0 * PI * r1;
+    let sin_theta = (1.0 - z * z).sqrt();
+    Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+}
+
+/// Sphere that moves in a straight line, it is at `center0` at `time0` and at `center1`
+/// at `time1`. It stays put before and after, so the bounding box covers every time.
+pub struct MovingSphere {
+    center0: Point3,
+    center1: Point3,
+    time0: f64,
+    time1: f64,
+    radius: f64,
+    material: Arc<dyn Material>,
+}
+
+impl MovingSphere {
+    /// times should differ
+    pub fn new(
+        (center0, time0): (Point3, f64),
+        (center1, time1): (Point3, f64),
+        radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Self {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
+        }
+    }
+
+    pub fn center(&self, time: f64) -> Point3 {
+        let fraction = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + fraction * (self.center1 - self.center0)
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+}
+
+impl Hittable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        hit_sphere(
+            self.center(r.time()),
+            self.radius,
+            self.material.as_ref(),
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::surrounding_box(
+            &sphere_box(self.center0, self.radius),
+            &sphere_box(self.center1, self.radius),
+        ))
     }
 }
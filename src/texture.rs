@@ -0,0 +1,283 @@
+use crate::image_output::Framebuffer;
+use crate::number_stuff::Rng;
+use crate::perlin::Perlin;
+use crate::vec3::*;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+/// color that changes over a surface, looked up by texture coordinates or by the point itself
+pub trait Texture: Send + Sync {
+    fn value(&self, uv: (f64, f64), point: Point3) -> Color;
+}
+
+/// plain colors can be used wherever a texture is expected
+impl From<Color> for Arc<dyn Texture> {
+    fn from(color: Color) -> Self {
+        Arc::new(SolidColor::new(color))
+    }
+}
+
+pub struct SolidColor {
+    color: Color,
+}
+
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        Self { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _uv: (f64, f64), _point: Point3) -> Color {
+        self.color
+    }
+}
+
+fn is_even(cells: f64) -> bool {
+    (cells as i64).rem_euclid(2) == 0
+}
+
+/// Checkers made of cubes with the given edge size that fill the space,
+/// so they don't depend on how a surface is parametrized.
+pub struct CheckerTexture {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    size: f64,
+}
+
+impl CheckerTexture {
+    pub fn new(
+        even: impl Into<Arc<dyn Texture>>,
+        odd: impl Into<Arc<dyn Texture>>,
+        size: f64,
+    ) -> Self {
+        Self {
+            even: even.into(),
+            odd: odd.into(),
+            size,
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, uv: (f64, f64), point: Point3) -> Color {
+        let cell = |c: f64| (c / self.size).floor();
+        match is_even(cell(point.x()) + cell(point.y()) + cell(point.z())) {
+            true => self.even.value(uv, point),
+            false => self.odd.value(uv, point),
+        }
+    }
+}
+
+/// checkers in texture space, the unit uv square is split into columns x rows cells
+pub struct UvCheckerTexture {
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+    columns: f64,
+    rows: f64,
+}
+
+impl UvCheckerTexture {
+    pub fn new(
+        even: impl Into<Arc<dyn Texture>>,
+        odd: impl Into<Arc<dyn Texture>>,
+        columns: f64,
+        rows: f64,
+    ) -> Self {
+        Self {
+            even: even.into(),
+            odd: odd.into(),
+            columns,
+            rows,
+        }
+    }
+}
+
+impl Texture for UvCheckerTexture {
+    fn value(&self, uv: (f64, f64), point: Point3) -> Color {
+        let (u, v) = uv;
+        match is_even((u * self.columns).floor() + (v * self.rows).floor()) {
+            true => self.even.value(uv, point),
+            false => self.odd.value(uv, point),
+        }
+    }
+}
+
+fn blend(from: Color, to: Color, t: f64) -> Color {
+    (1.0 - t) * from + t * to
+}
+
+/// Veins along z that turbulence bends, like the marble in Ray Tracing: The Next Week.
+/// Scale sets how close the veins are, turbulence how much they wander.
+pub struct MarbleTexture {
+    noise: Perlin,
+    dark: Color,
+    light: Color,
+    scale: f64,
+    turbulence: f64,
+}
+
+impl MarbleTexture {
+    /// the noise is built from the generator
+    pub fn new(dark: Color, light: Color, scale: f64, turbulence: f64, rng: &mut Rng) -> Self {
+        Self {
+            noise: Perlin::new(rng),
+            dark,
+            light,
+            scale,
+            turbulence,
+        }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _uv: (f64, f64), point: Point3) -> Color {
+        let phase = self.scale * point.z() + self.turbulence * self.noise.turbulence(point, 7);
+        blend(self.dark, self.light, 0.5 * (1.0 + phase.sin()))
+    }
+}
+
+/// Rings around the y axis, `scale` of them per unit, distorted by noise
+pub struct WoodTexture {
+    noise: Perlin,
+    dark: Color,
+    light: Color,
+    scale: f64,
+    turbulence: f64,
+}
+
+impl WoodTexture {
+    /// the noise is built from the generator
+    pub fn new(dark: Color, light: Color, scale: f64, turbulence: f64, rng: &mut Rng) -> Self {
+        Self {
+            noise: Perlin::new(rng),
+            dark,
+            light,
+            scale,
+            turbulence,
+        }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _uv: (f64, f64), point: Point3) -> Color {
+        let radius = (point.x() * point.x() + point.z() * point.z()).sqrt();
+        let rings = self.scale * radius + self.turbulence * self.noise.fbm(point, 4);
+        // sharp edge at the start of every ring, fading towards the next one
+        let t = rings.rem_euclid(1.0);
+        blend(self.dark, self.light, (0.5 * PI * t).sin())
+    }
+}
+
+/// Blotches of fractal value noise between two colors, `scale` sets the size of the biggest ones
+pub struct ValueNoiseTexture {
+    noise: Perlin,
+    low: Color,
+    high: Color,
+    scale: f64,
+    octaves: u32,
+}
+
+impl ValueNoiseTexture {
+    /// The noise is built from the generator. Panics if there are no octaves.
+    pub fn new(low: Color, high: Color, scale: f64, octaves: u32, rng: &mut Rng) -> Self {
+        assert!(octaves > 0, "value noise needs at least one octave");
+        Self {
+            noise: Perlin::new(rng),
+            low,
+            high,
+            scale,
+            octaves,
+        }
+    }
+}
+
+impl Texture for ValueNoiseTexture {
+    fn value(&self, _uv: (f64, f64), point: Point3) -> Color {
+        // weights of the octaves add up to almost 2
+        let max = 2.0 - 0.5f64.powi(self.octaves as i32 - 1);
+        let t = self.noise.value_fbm(self.scale * point, self.octaves) / max;
+        blend(self.low, self.high, t)
+    }
+}
+
+/// Image wrapped around the unit uv square and repeated outside of it.
+/// v goes up, so (0, 0) is the bottom left corner of the image.
+pub struct ImageTexture {
+    image: Framebuffer,
+}
+
+impl ImageTexture {
+    /// colors of the image are expected to be linear, see `image_input::load_image`
+    pub fn new(image: Framebuffer) -> Self {
+        Self { image }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, (u, v): (f64, f64), _point: Point3) -> Color {
+        let width = self.image.width();
+        let height = self.image.height();
+        if width == 0 || height == 0 {
+            // magenta makes a missing image easy to spot
+            return Color::new(1.0, 0.0, 1.0);
+        }
+        let x = (u.rem_euclid(1.0) * width as f64) as usize;
+        let y = ((1.0 - v.rem_euclid(1.0)) * height as f64) as usize;
+        self.image.pixel(x.min(width - 1), y.min(height - 1))
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkers_alternate() {
+        let black = Color::default();
+        let white = Color::new(1.0, 1.0, 1.0);
+        let solid = CheckerTexture::new(black, white, 2.0);
+        assert_eq!(solid.value((0.0, 0.0), Point3::new(0.5, 0.5, 0.5)), black);
+        assert_eq!(solid.value((0.0, 0.0), Point3::new(2.5, 0.5, 0.5)), white);
+        assert_eq!(solid.value((0.0, 0.0), Point3::new(-0.5, 0.5, 0.5)), white);
+
+        let uv = UvCheckerTexture::new(black, white, 4.0, 2.0);
+        assert_eq!(uv.value((0.1, 0.1), Point3::default()), black);
+        assert_eq!(uv.value((0.3, 0.1), Point3::default()), white);
+        assert_eq!(uv.value((0.3, 0.6), Point3::default()), black);
+    }
+
+    #[test]
+    fn noise_textures_stay_between_their_colors() {
+        let (low, high) = (Color::new(0.1, 0.2, 0.3), Color::new(0.5, 0.6, 0.7));
+        let rng = &mut Rng::new(5);
+        let textures: [Box<dyn Texture>; 3] = [
+            Box::new(MarbleTexture::new(low, high, 4.0, 10.0, rng)),
+            Box::new(WoodTexture::new(low, high, 4.0, 1.0, rng)),
+            Box::new(ValueNoiseTexture::new(low, high, 3.0, 5, rng)),
+        ];
+        for texture in &textures {
+            for i in 0..100 {
+                let p = Point3::new(0.37 * i as f64, 0.11 * i as f64, -0.23 * i as f64);
+                let color = texture.value((0.0, 0.0), p);
+                assert!(color.x() >= 0.1 - 1e-9 && color.x() <= 0.5 + 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn image_bottom_left_is_uv_origin() {
+        let mut image = Framebuffer::new(2, 2);
+        let red = Color::new(1.0, 0.0, 0.0);
+        image.set_pixel(0, 1, red);
+        let texture = ImageTexture::new(image);
+        assert_eq!(texture.value((0.25, 0.25), Point3::default()), red);
+        assert_eq!(texture.value((1.25, -0.75), Point3::default()), red);
+        assert_eq!(
+            texture.value((0.75, 0.25), Point3::default()),
+            Color::default()
+        );
+    }
+}
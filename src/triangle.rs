@@ -0,0 +1,347 @@
+use crate::aabb::Aabb;
+use crate::bvh::{BvhNode, SplitHeuristic};
+use crate::material::Material;
+use crate::ray::*;
+use crate::ray_trace::*;
+use crate::vec3::*;
+use std::sync::Arc;
+
+/// Möller–Trumbore intersection, returns t and the weights of the second and third vertex
+fn intersect(r: &Ray, vertices: &[Point3; 3], t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+    let p = cross(r.direction(), edge2);
+    let det = dot(edge1, p);
+    if det.abs() < 1e-12 {
+        // ray is parallel to the triangle
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let to_origin = r.origin() - vertices[0];
+    let b1 = dot(to_origin, p) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let q = cross(to_origin, edge1);
+    let b2 = dot(r.direction(), q) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = dot(edge2, q) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+fn interpolate(values: [Vec3; 3], b1: f64, b2: f64) -> Vec3 {
+    (1.0 - b1 - b2) * values[0] + b1 * values[1] + b2 * values[2]
+}
+
+fn bounding_box_of(vertices: &[Point3; 3]) -> Aabb {
+    Aabb::new(vertices[0], vertices[1]).expanded_to(vertices[2])
+}
+
+/// Fills the hit record of a triangle. Normal faces the ray, front side is the one
+/// from which vertices go counterclockwise or, with vertex normals, the one they point
+/// to, so meshes whose winding disagrees with their normals still face the right way.
+/// Shading normals are flipped along with it.
+fn hit_record<'a>(
+    r: &Ray,
+    (t, b1, b2): (f64, f64, f64),
+    vertices: &[Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    material: &'a dyn Material,
+) -> HitRecord<'a> {
+    let mut geometric_normal = cross(vertices[1] - vertices[0], vertices[2] - vertices[0]);
+    let mut normal = match normals {
+        Some(normals) => unit_vector(interpolate(normals, b1, b2)),
+        None => unit_vector(geometric_normal),
+    };
+    if dot(normal, geometric_normal) < 0.0 {
+        geometric_normal = -geometric_normal;
+    }
+    let outside_ray = !face_one_direction(r, &geometric_normal);
+    if !outside_ray {
+        normal = -normal;
+    }
+    let uv = match uvs {
+        Some([uv0, uv1, uv2]) => (
+            (1.0 - b1 - b2) * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+            (1.0 - b1 - b2) * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+        ),
+        None => (b1, b2),
+    };
+    HitRecord {
+        point: r.at(t),
+        normal,
+        t,
+        material_reference: Some(material),
+        outside_ray,
+        uv,
+        tangent: tangent(vertices, uvs),
+        barycentric: Some((b1, b2)),
+        ..Default::default()
+    }
+}
+
+/// dp/du from the edges and how much uv changes along them. Without texture coordinates
+/// u is the weight of the second vertex, so it is the first edge.
+fn tangent(vertices: &[Point3; 3], uvs: Option<[(f64, f64); 3]>) -> Vec3 {
+    let edge1 = vertices[1] - vertices[0];
+    let edge2 = vertices[2] - vertices[0];
+    let Some([uv0, uv1, uv2]) = uvs else {
+        return edge1;
+    };
+    let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+    let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+    let determinant = du1 * dv2 - du2 * dv1;
+    match determinant.abs() < 1e-12 {
+        // u doesn't change over the triangle
+        true => edge1,
+        false => (dv2 * edge1 - dv1 * edge2) / determinant,
+    }
+}
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    material: Arc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(vertices: [Point3; 3], material: Arc<dyn Material>) -> Self {
+        Self {
+            vertices,
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    /// per vertex normals, interpolated over the triangle for smooth shading
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    /// per vertex texture coordinates, without them uv are the barycentric coordinates
+    pub fn with_uvs(mut self, uvs: [(f64, f64); 3]) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
+
+    pub fn vertices(&self) -> [Point3; 3] {
+        self.vertices
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let hit = intersect(r, &self.vertices, t_min, t_max)?;
+        Some(hit_record(
+            r,
+            hit,
+            &self.vertices,
+            self.normals,
+            self.uvs,
+            self.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(bounding_box_of(&self.vertices))
+    }
+}
+
+/// indices of one mesh triangle into the vertex buffers of the mesh
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MeshFace {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+/// vertex data that can be shared by several meshes, like the groups of an OBJ file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshBuffers {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+}
+
+struct MeshData {
+    buffers: Arc<MeshBuffers>,
+    material: Arc<dyn Material>,
+}
+
+/// one face of a mesh, only stores indices so vertex data is shared
+struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: MeshFace,
+}
+
+impl MeshTriangle {
+    fn vertices(&self) -> [Point3; 3] {
+        self.face.positions.map(|i| self.mesh.buffers.positions[i])
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let vertices = self.vertices();
+        let hit = intersect(r, &vertices, t_min, t_max)?;
+        Some(hit_record(
+            r,
+            hit,
+            &vertices,
+            self.face
+                .normals
+                .map(|n| n.map(|i| self.mesh.buffers.normals[i])),
+            self.face.uvs.map(|uv| uv.map(|i| self.mesh.buffers.uvs[i])),
+            self.mesh.material.as_ref(),
+        ))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(bounding_box_of(&self.vertices()))
+    }
+}
+
+/// Many triangles sharing vertex buffers and a material, traced through their own BVH
+pub struct TriangleMesh {
+    bvh: BvhNode,
+    triangle_count: usize,
+}
+
+impl TriangleMesh {
+    /// Panics if there are no faces or a face points outside of the buffers.
+    pub fn new(
+        buffers: Arc<MeshBuffers>,
+        faces: Vec<MeshFace>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        for face in &faces {
+            let in_bounds = |indices: Option<[usize; 3]>, len: usize| {
+                indices.is_none_or(|indices| indices.iter().all(|&i| i < len))
+            };
+            assert!(
+                in_bounds(Some(face.positions), buffers.positions.len())
+                    && in_bounds(face.normals, buffers.normals.len())
+                    && in_bounds(face.uvs, buffers.uvs.len()),
+                "mesh face {:?} points outside of the vertex buffers",
+                face
+            );
+        }
+        let mesh = Arc::new(MeshData { buffers, material });
+        let mut triangles = HittableList::default();
+        for face in &faces {
+            triangles.add(Arc::new(MeshTriangle {
+                mesh: mesh.clone(),
+                face: *face,
+            }));
+        }
+        Self {
+            bvh: BvhNode::new(&triangles, SplitHeuristic::Sah),
+            triangle_count: faces.len(),
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangle_count
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.bvh.hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn gray() -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn unit_triangle() -> [Point3; 3] {
+        [
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ]
+    }
+
+    #[test]
+    fn barycentric_and_facing() {
+        let triangle = Triangle::new(unit_triangle(), gray());
+        let from_front = Ray::new(Point3::new(0.25, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let record = triangle.hit(&from_front, 0.0, f64::MAX).unwrap();
+        assert_eq!(record.t, 1.0);
+        assert_eq!(record.barycentric, Some((0.25, 0.5)));
+        assert_eq!(record.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!(record.outside_ray);
+        // u is the weight of the second vertex without texture coordinates
+        assert_eq!(record.tangent, Vec3::new(1.0, 0.0, 0.0));
+        let mapped =
+            Triangle::new(unit_triangle(), gray()).with_uvs([(0.0, 0.0), (0.0, 1.0), (2.0, 0.0)]);
+        let record = mapped.hit(&from_front, 0.0, f64::MAX).unwrap();
+        assert_eq!(record.tangent, Vec3::new(0.0, 0.5, 0.0));
+
+        let from_back = Ray::new(Point3::new(0.25, 0.5, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let record = triangle.hit(&from_back, 0.0, f64::MAX).unwrap();
+        assert_eq!(record.normal, Vec3::new(0.0, 0.0, -1.0));
+        assert!(!record.outside_ray);
+
+        let beside = Ray::new(Point3::new(0.75, 0.75, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(triangle.hit(&beside, 0.0, f64::MAX).is_none());
+
+        // vertex normals against the winding turn the triangle around
+        let tilted = unit_vector(Vec3::new(0.0, 0.2, -1.0));
+        let flipped = Triangle::new(unit_triangle(), gray()).with_normals([tilted; 3]);
+        let record = flipped.hit(&from_front, 0.0, f64::MAX).unwrap();
+        assert!(!record.outside_ray);
+        assert!((record.normal + tilted).really_small());
+        let record = flipped.hit(&from_back, 0.0, f64::MAX).unwrap();
+        assert!(record.outside_ray);
+        assert!((record.normal - tilted).really_small());
+    }
+
+    #[test]
+    fn mesh_interpolates_shared_vertices() {
+        let [a, b, c] = unit_triangle();
+        let d = Point3::new(1.0, 1.0, 0.0);
+        let faces = vec![
+            MeshFace {
+                positions: [0, 1, 2],
+                normals: None,
+                uvs: Some([0, 1, 2]),
+            },
+            MeshFace {
+                positions: [1, 3, 2],
+                normals: None,
+                uvs: Some([1, 3, 2]),
+            },
+        ];
+        let uvs = vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)];
+        let buffers = MeshBuffers {
+            positions: vec![a, b, c, d],
+            normals: Vec::new(),
+            uvs,
+        };
+        let mesh = TriangleMesh::new(Arc::new(buffers), faces, gray());
+        let r = Ray::new(Point3::new(0.75, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let record = mesh.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((record.uv.0 - 0.75).abs() < 1e-12 && (record.uv.1 - 0.5).abs() < 1e-12);
+    }
+}
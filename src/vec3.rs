@@ -1,6 +1,4 @@
 use derive_more::{Add, AddAssign, Neg, Sub, SubAssign};
-// use std::io::Write;
-use num::clamp;
 use std::ops::{Div, DivAssign, Mul, MulAssign};
 
 // Note: intentionally used Self and not &Self in operations (cuz i'm too lazy)
@@ -36,6 +34,16 @@ impl Vec3 {
         self.length_squared().sqrt()
     }
 
+    /// returns x, y or z for axis 0, 1 or 2
+    pub fn component(&self, axis: usize) -> f64 {
+        match axis {
+            0 => self.x,
+            1 => self.y,
+            2 => self.z,
+            _ => panic!("axis should be 0, 1 or 2 but was given: {}", axis),
+        }
+    }
+
     pub fn really_small(&self) -> bool {
         let small = 0.00001;
         self.x.abs() < small && self.y.abs() < small && self.z.abs() < small
@@ -94,6 +102,16 @@ pub fn cross(u: Vec3, v: Vec3) -> Vec3 {
     }
 }
 
+/// component-wise minimum
+pub fn min_components(u: Vec3, v: Vec3) -> Vec3 {
+    Vec3::new(u.x.min(v.x), u.y.min(v.y), u.z.min(v.z))
+}
+
+/// component-wise maximum
+pub fn max_components(u: Vec3, v: Vec3) -> Vec3 {
+    Vec3::new(u.x.max(v.x), u.y.max(v.y), u.z.max(v.z))
+}
+
 pub fn unit_vector(u: Vec3) -> Vec3 {
     u / u.length()
 }
@@ -114,30 +132,9 @@ pub fn refract(v: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
 pub type Point3 = Vec3;
 pub type Color = Vec3;
 
-// Note: idk how streams work in rust so just implemented simple print
-
-// pub fn write_color(stdout: &mut std::io::Stdout, pixel_color: Color) {
-//     let r = (255.999 * pixel_color.x()) as i32;
-//     let g = (255.999 * pixel_color.y()) as i32;
-//     let b = (255.999 * pixel_color.z()) as i32;
-//     writeln!(stdout, "{} {} {}", r, g, b).unwrap();
-// }
-
-pub fn generate_color(pixel_color: Color, samples_per_pixel: u32) -> String {
-    let mut r = pixel_color.x();
-    let mut g = pixel_color.y();
-    let mut b = pixel_color.z();
-
-    let scale = 1.0 / samples_per_pixel as f64;
-    r = (scale * r).sqrt();
-    g = (scale * g).sqrt();
-    b = (scale * b).sqrt();
-
-    let final_r = (256.0 * clamp(r, 0.0, 0.999)) as i32;
-    let final_g = (256.0 * clamp(g, 0.0, 0.999)) as i32;
-    let final_b = (256.0 * clamp(b, 0.0, 0.999)) as i32;
-
-    format!("{} {} {}\n", final_r, final_g, final_b)
+/// brightness of a linear color as the eye sees it, Rec. 709 weights
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
 }
 
 impl Mul for Color {
@@ -152,6 +149,208 @@ impl Mul for Color {
     }
 }
 
+/// Orthonormal basis with w along the given direction, for turning vectors
+/// sampled around the z axis to be around w
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn new(w: Vec3) -> Self {
+        let w = unit_vector(w);
+        // any axis that isn't close to w works
+        let helper = match w.x.abs() > 0.9 {
+            true => Vec3::new(0.0, 1.0, 0.0),
+            false => Vec3::new(1.0, 0.0, 0.0),
+        };
+        let v = unit_vector(cross(w, helper));
+        let u = cross(w, v);
+        Self { u, v, w }
+    }
+
+    /// Basis with u along the part of the tangent that is perpendicular to w, so that
+    /// it doesn't depend on which way w points. Falls back to `new` without a tangent.
+    pub fn with_tangent(w: Vec3, tangent: Vec3) -> Self {
+        let w = unit_vector(w);
+        let u = tangent - dot(tangent, w) * w;
+        if u.length_squared() <= 1e-12 * tangent.length_squared() {
+            return Self::new(w);
+        }
+        let u = unit_vector(u);
+        Self {
+            u,
+            v: cross(w, u),
+            w,
+        }
+    }
+
+    pub fn w(&self) -> Vec3 {
+        self.w
+    }
+
+    /// the vector with coordinates a in this basis
+    pub fn local(&self, a: Vec3) -> Vec3 {
+        a.x * self.u + a.y * self.v + a.z * self.w
+    }
+
+    /// coordinates of a in this basis, the opposite of `local`
+    pub fn to_local(&self, a: Vec3) -> Vec3 {
+        Vec3::new(dot(a, self.u), dot(a, self.v), dot(a, self.w))
+    }
+}
+
+/// Affine transform as a 4x4 row major matrix, points are columns with w = 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Matrix4 {
+    m: [[f64; 4]; 4],
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Matrix4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Self { m }
+    }
+
+    pub fn identity() -> Self {
+        Self::scale(Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        Self::new([
+            [1.0, 0.0, 0.0, offset.x],
+            [0.0, 1.0, 0.0, offset.y],
+            [0.0, 0.0, 1.0, offset.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// scales every axis by its own factor, negative factors mirror
+    pub fn scale(factors: Vec3) -> Self {
+        Self::new([
+            [factors.x, 0.0, 0.0, 0.0],
+            [0.0, factors.y, 0.0, 0.0],
+            [0.0, 0.0, factors.z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    /// counterclockwise rotation around the axis when it points at the viewer
+    pub fn rotate(axis: Vec3, radians: f64) -> Self {
+        let Vec3 { x, y, z } = unit_vector(axis);
+        let (sin, cos) = radians.sin_cos();
+        let t = 1.0 - cos;
+        Self::new([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rotate_x(radians: f64) -> Self {
+        Self::rotate(Vec3::new(1.0, 0.0, 0.0), radians)
+    }
+
+    pub fn rotate_y(radians: f64) -> Self {
+        Self::rotate(Vec3::new(0.0, 1.0, 0.0), radians)
+    }
+
+    pub fn rotate_z(radians: f64) -> Self {
+        Self::rotate(Vec3::new(0.0, 0.0, 1.0), radians)
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> f64 {
+        self.m[row][column]
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        self.transform_vector(p) + Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    /// ignores the translation, for directions
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let row = |r: [f64; 4]| r[0] * v.x + r[1] * v.y + r[2] * v.z;
+        Vec3::new(row(self.m[0]), row(self.m[1]), row(self.m[2]))
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (r, row) in m.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = self.m[c][r];
+            }
+        }
+        Self::new(m)
+    }
+
+    /// Gauss-Jordan elimination, None if the matrix is singular (like a scale by 0)
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inverse = Self::identity().m;
+        for column in 0..4 {
+            // the biggest pivot keeps the error small
+            let pivot =
+                (column..4).max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))?;
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+            let scale = 1.0 / a[column][column];
+            for c in 0..4 {
+                a[column][c] *= scale;
+                inverse[column][c] *= scale;
+            }
+            for row in (0..4).filter(|&row| row != column) {
+                let factor = a[row][column];
+                for c in 0..4 {
+                    a[row][c] -= factor * a[column][c];
+                    inverse[row][c] -= factor * inverse[column][c];
+                }
+            }
+        }
+        Some(Self::new(inverse))
+    }
+}
+
+/// `a * b` applies b first, then a
+impl Mul for Matrix4 {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+        for (r, row) in m.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[r][k] * rhs.m[k][c]).sum();
+            }
+        }
+        Self::new(m)
+    }
+}
+
 //////////////////////////////////////////////////////////////////////////////////////
 
 #[cfg(test)]
@@ -198,4 +397,39 @@ mod tests {
         );
         assert_eq!(u.length(), 14.0_f64.sqrt());
     }
+
+    #[test]
+    fn tangent_frames_do_not_flip() {
+        // `Onb::new` picks another helper axis past |w.x| = 0.9
+        let tangent = Vec3::new(0.0, 1.0, 0.0);
+        for x in [0.89, 0.91] {
+            let w = Vec3::new(x, 0.0, (1.0 - x * x).sqrt());
+            let frame = Onb::with_tangent(w, tangent);
+            assert!((frame.local(Vec3::new(1.0, 0.0, 0.0)) - tangent).really_small());
+            assert!((frame.local(Vec3::new(0.0, 0.0, 1.0)) - w).really_small());
+        }
+        let w = Vec3::new(0.0, 0.0, 2.0);
+        let frame = Onb::with_tangent(w, Vec3::new(1.0, 1.0, 5.0));
+        let diagonal = unit_vector(Vec3::new(1.0, 1.0, 0.0));
+        assert!((frame.local(Vec3::new(1.0, 0.0, 0.0)) - diagonal).really_small());
+        assert_eq!(Onb::with_tangent(w, Vec3::default()), Onb::new(w));
+    }
+
+    #[test]
+    fn matrix_transforms_and_inverse() {
+        let close = |u: Vec3, v: Vec3| (u - v).really_small();
+        let quarter_turn = Matrix4::rotate_y(std::f64::consts::FRAC_PI_2);
+        assert!(close(
+            quarter_turn.transform_vector(Vec3::new(1.0, 0.0, 0.0)),
+            Vec3::new(0.0, 0.0, -1.0)
+        ));
+        let m = Matrix4::translate(Vec3::new(1.0, 2.0, 3.0))
+            * quarter_turn
+            * Matrix4::scale(Vec3::new(2.0, 2.0, 2.0));
+        let p = Point3::new(1.0, 0.0, 0.0);
+        assert!(close(m.transform_point(p), Point3::new(1.0, 2.0, 1.0)));
+        let inverse = m.inverse().unwrap();
+        assert!(close(inverse.transform_point(m.transform_point(p)), p));
+        assert!(Matrix4::scale(Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
 }